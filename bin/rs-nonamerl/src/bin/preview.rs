@@ -0,0 +1,299 @@
+//! Builds maps with the level pipeline of the game, without opening a window, and prints them
+//! as ASCII or writes them as PNG images, with a few stats. With `--report`, the metrics of
+//! every map and their distributions are written to a JSON or CSV file.
+//!
+//! ```text
+//! cargo run --release -p rs-nonamerl --bin preview -- --seeds 0..100 --stats
+//! cargo run --release -p rs-nonamerl --bin preview -- --seeds 0..500 --report report.csv
+//! cargo run --release -p rs-nonamerl --bin preview -- --seed 42 --depth 2 --png previews/{seed}.png --sprites data/config/sprites.json
+//! ```
+
+use std::{collections::HashMap, ops::Range, path::Path, process::ExitCode};
+
+use macroquad::{prelude::Color, texture::Image};
+use rs_nonamerl::tiles::TestTile;
+use rs_nonamerl_core::{
+    prelude::{
+        BuilderRegistry, GameMap, GridStorage, MapBuilder, MapMetrics, MetricsReport,
+        PipelineConfig, SpriteSheetConfig, Tile, TileSpriteInfo, Walkable,
+    },
+    IntExtent2, IntVector2,
+};
+
+const USAGE: &str = "\
+usage: preview [options]
+
+  --pipeline <path>     the level pipeline, data/config/pipeline.json by default
+  --seed <seed>         the seed of the level, the one of the pipeline or 0 by default
+  --seeds <from>..<to>  builds a level for every seed of the range
+  --depth <depth>       the depth of the level, 0 by default
+  --extent <x,y,w,h>    the cells to build, -100,-100,200,200 by default; the steps of the
+                        pipeline with an extent of their own keep it
+  --png <path>          writes the map to a PNG image instead of printing it, {seed} is
+                        replaced by the seed
+  --sprites <path>      draws the tiles of the PNG with the sprites of this sprite config,
+                        instead of one pixel per tile
+  --stats               only prints the stats
+  --report <path>       writes the metrics of the maps and their distributions to a CSV file
+                        when the extension is csv, to a JSON file otherwise, and doesn't print
+                        the maps";
+
+/// What the command line asked for.
+struct Options {
+    pipeline: String,
+    seeds: Option<Range<u64>>,
+    depth: u32,
+    extent: IntExtent2,
+    png: Option<String>,
+    sprites: Option<String>,
+    stats_only: bool,
+    report: Option<String>,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options {
+            pipeline: "data/config/pipeline.json".to_owned(),
+            seeds: None,
+            depth: 0,
+            extent: IntExtent2::new(-100, -100, 200, 200),
+            png: None,
+            sprites: None,
+            stats_only: false,
+            report: None,
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {}", arg))
+            };
+            match arg.as_str() {
+                "--pipeline" => options.pipeline = value()?,
+                "--seed" => {
+                    let seed = parse_number(&value()?)?;
+                    options.seeds = Some(seed..seed + 1);
+                }
+                "--seeds" => {
+                    let range = value()?;
+                    let (from, to) = range
+                        .split_once("..")
+                        .ok_or_else(|| format!("invalid seed range {}", range))?;
+                    options.seeds = Some(parse_number(from)?..parse_number(to)?);
+                }
+                "--depth" => options.depth = parse_number(&value()?)?,
+                "--extent" => {
+                    let extent = value()?;
+                    let parts: Vec<&str> = extent.split(',').collect();
+                    let [x, y, width, height] = parts[..] else {
+                        return Err(format!("invalid extent {}", extent));
+                    };
+                    options.extent = IntExtent2::new(
+                        parse_number(x)?,
+                        parse_number(y)?,
+                        parse_number(width)?,
+                        parse_number(height)?,
+                    );
+                }
+                "--png" => options.png = Some(value()?),
+                "--sprites" => options.sprites = Some(value()?),
+                "--stats" => options.stats_only = true,
+                "--report" => options.report = Some(value()?),
+                _ => return Err(format!("unknown option {}", arg)),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+fn parse_number<N: std::str::FromStr>(value: &str) -> Result<N, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid number {}", value))
+}
+
+/// The sprites of a sprite config, cut out of their sheets.
+struct Sprites {
+    size: (u32, u32),
+    images: HashMap<String, Image>,
+}
+
+impl Sprites {
+    fn load(config_path: &str) -> Result<Self, String> {
+        let config = SpriteSheetConfig::from_file(config_path);
+        let mut sheets = HashMap::<String, Image>::new();
+        let mut images = HashMap::new();
+        let mut size = (1, 1);
+        for (name, (sheet, rect)) in config.sprite_rects() {
+            if !sheets.contains_key(&sheet) {
+                let bytes =
+                    std::fs::read(&sheet).map_err(|error| format!("{}: {}", sheet, error))?;
+                let image = Image::from_file_with_format(&bytes, None)
+                    .map_err(|error| format!("{}: {}", sheet, error))?;
+                sheets.insert(sheet.clone(), image);
+            }
+            let image = &sheets[&sheet];
+            if rect.right() > image.width as f32 || rect.bottom() > image.height as f32 {
+                return Err(format!("sprite {} is outside of {}", name, sheet));
+            }
+            size = (size.0.max(rect.w as u32), size.1.max(rect.h as u32));
+            images.insert(name, image.sub_image(rect));
+        }
+        Ok(Self { size, images })
+    }
+}
+
+/// The color of a tile drawn as a single pixel.
+fn tile_color(tile: &TestTile) -> Color {
+    match tile.sprite_info() {
+        TileSpriteInfo::Fill(color) => color,
+        // the sprites of the tileset are mostly black, paint them by what they do instead
+        _ if tile.is_walkable() => Color::from_rgba(150, 150, 150, 255),
+        _ => Color::from_rgba(60, 60, 60, 255),
+    }
+}
+
+/// Draws the cells of `extent`, with a sprite per tile when there are `sprites` and with a
+/// pixel per tile otherwise.
+fn draw_png(
+    map: &GameMap<TestTile>,
+    extent: &IntExtent2,
+    sprites: Option<&Sprites>,
+    path: &str,
+) -> Result<(), String> {
+    let (cell_width, cell_height) = sprites.map_or((1, 1), |sprites| sprites.size);
+    let width = extent.width() * cell_width;
+    let height = extent.height() * cell_height;
+    if width > u16::MAX as u32 || height > u16::MAX as u32 {
+        return Err(format!("the image would be {}x{} pixels", width, height));
+    }
+    if let Some(parent) = Path::new(path).parent() {
+        if !parent.as_os_str().is_empty() && !parent.is_dir() {
+            return Err(format!("no directory {}", parent.display()));
+        }
+    }
+
+    let mut image = Image::gen_image_color(width as u16, height as u16, Color::new(0., 0., 0., 1.));
+    // `Image::export_png` flips the image, draw it upside down
+    let mut put = |x: u32, y: u32, color: Color| image.set_pixel(x, height - 1 - y, color);
+    map.for_each_in(extent, |position, tile| {
+        let cell = position - IntVector2::new(extent.left(), extent.top());
+        let (left, top) = (cell.x as u32 * cell_width, cell.y as u32 * cell_height);
+        let sprite = match (sprites, tile.sprite_info()) {
+            (Some(sprites), TileSpriteInfo::SpriteSheet(name)) => sprites.images.get(name),
+            _ => None,
+        };
+        match sprite {
+            Some(sprite) => {
+                for y in 0..(sprite.height as u32).min(cell_height) {
+                    for x in 0..(sprite.width as u32).min(cell_width) {
+                        put(left + x, top + y, sprite.get_pixel(x, y));
+                    }
+                }
+            }
+            None => {
+                let color = tile_color(tile);
+                for y in 0..cell_height {
+                    for x in 0..cell_width {
+                        put(left + x, top + y, color);
+                    }
+                }
+            }
+        }
+    });
+
+    image.export_png(path);
+    Ok(())
+}
+
+fn build(
+    pipeline: &PipelineConfig,
+    options: &Options,
+    seed: u64,
+) -> Result<MapBuilder<TestTile>, String> {
+    let mut map_builder = MapBuilder::<TestTile>::new(options.extent)
+        .with_storage(GridStorage::chunked())
+        .with_seed(seed)
+        .with_depth(options.depth);
+    pipeline
+        .add_tiles(&mut map_builder, TestTile::from_kind)
+        .and_then(|_| BuilderRegistry::new().build(pipeline, &mut map_builder))
+        .map_err(|error| format!("invalid map pipeline: {}", error))?;
+    Ok(map_builder)
+}
+
+fn run(options: Options) -> Result<(), String> {
+    let pipeline_json = std::fs::read_to_string(&options.pipeline)
+        .map_err(|error| format!("{}: {}", options.pipeline, error))?;
+    let pipeline = PipelineConfig::from_json(&pipeline_json)
+        .map_err(|error| format!("{}: {}", options.pipeline, error))?;
+    let sprites = options.sprites.as_deref().map(Sprites::load).transpose()?;
+    let seeds = options.seeds.clone().unwrap_or_else(|| {
+        let seed = pipeline.seed.unwrap_or(0);
+        seed..seed + 1
+    });
+
+    let mut report = MetricsReport::new();
+    for seed in seeds {
+        let map_builder = build(&pipeline, &options, seed)?;
+        let metrics = MapMetrics::measure(&map_builder);
+        println!(
+            "seed {}: {} rooms, {:.1}% walkable, {} regions, {} dead ends",
+            seed,
+            metrics.rooms,
+            metrics.walkable_fraction * 100.,
+            metrics.regions,
+            metrics.dead_ends
+        );
+        report.add(metrics);
+        if options.stats_only || options.report.is_some() {
+            continue;
+        }
+
+        match &options.png {
+            Some(png) => {
+                let path = png.replace("{seed}", &seed.to_string());
+                draw_png(&map_builder.map, &options.extent, sprites.as_ref(), &path)?;
+                println!("written to {}", path);
+            }
+            None => println!("{}\n", map_builder.map.to_ascii(&options.extent)),
+        }
+    }
+
+    if let Some(path) = &options.report {
+        let contents = if path.ends_with(".csv") {
+            report.to_csv()
+        } else {
+            report.to_json().map_err(|error| error.to_string())?
+        };
+        std::fs::write(path, contents).map_err(|error| format!("{}: {}", path, error))?;
+        println!("report of {} maps written to {}", report.maps.len(), path);
+    }
+
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+
+    let options = match Options::parse(args.into_iter()) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match run(options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,10 +1,10 @@
 use bevy_ecs::system::Command;
+use rs_nonamerl::tiles::TestTile;
 use rs_nonamerl_core::prelude::GameMap;
 
 use crate::{
     components::{Interaction, Interactions, Position},
     resources::CurrentCellInfo,
-    tiles::TestTile,
 };
 
 #[derive(Debug, Clone)]
@@ -8,7 +8,7 @@ use bevy_ecs::{
 };
 use rs_nonamerl_core::{prelude::KeyInput, IntVector2};
 
-use crate::tiles::TestTile;
+use rs_nonamerl::tiles::TestTile;
 
 #[derive(Component, Default, Debug, Clone)]
 pub struct Position {
@@ -22,6 +22,12 @@ pub struct Player {}
 #[derive(Component, Default, Debug, Clone)]
 pub struct Enemy {}
 
+/// The depth of the level an entity lives on.
+#[derive(Component, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OnLevel {
+    pub depth: u32,
+}
+
 #[derive(Component, Default, Debug, Clone)]
 pub struct CharacterInfo {
     pub strength: i32,
@@ -110,6 +116,12 @@ pub struct MoveIntent {
     pub target: IntVector2,
 }
 
+/// Opens the door at `target`, or closes it if it is open.
+#[derive(Component, Default, Debug, Clone)]
+pub struct OpenIntent {
+    pub target: IntVector2,
+}
+
 #[derive(Component, Default, Debug, Clone)]
 pub struct PickIntent {
     pub item: Option<Entity>,
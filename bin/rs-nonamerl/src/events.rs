@@ -17,3 +17,7 @@ impl ChangeGameStateEvent {
 pub struct UpdateAvailableInteractionsEvent {
     pub position: Position,
 }
+
+/// The player wants to take the stairs they are standing on.
+#[derive(Debug, Event)]
+pub struct TakeStairsEvent;
@@ -0,0 +1,270 @@
+//! Replays how a level is built, one [`BuildHistory`] frame at a time, instead of playing it.
+//!
+//! ```text
+//! cargo run -- --history [--tiles-per-frame <n>] [--depth <depth>]
+//! ```
+//!
+//! Right and left go to the next and the previous frame, page down and page up to the next and
+//! the previous step, home and end to the first and the last frame. The mouse wheel zooms and
+//! the changes of the cell under the mouse are listed, to see which step wrote it last.
+
+use std::str::FromStr;
+
+use macroquad::prelude::*;
+use rs_nonamerl_core::{
+    prelude::{
+        BuildHistory, BuilderRegistry, GameMap, GridStorage, KeyInput, MapBuilder, PipelineConfig,
+        RenderOp, Renderer, SpriteContainer, TestCamera2D, Tile, UserInput, Viewport,
+    },
+    Dimension2, IntExtent2,
+};
+
+use rs_nonamerl::tiles::TestTile;
+
+/// What the command line asked the viewer for.
+pub struct HistoryOptions {
+    pub tiles_per_frame: Option<usize>,
+    pub depth: u32,
+}
+
+impl HistoryOptions {
+    /// The options of the viewer, `None` when the game is not started with `--history`.
+    pub fn from_args(args: &[String]) -> Option<Self> {
+        if !args.iter().any(|arg| arg == "--history") {
+            return None;
+        }
+        Some(Self {
+            tiles_per_frame: arg_value(args, "--tiles-per-frame"),
+            depth: arg_value(args, "--depth").unwrap_or(0),
+        })
+    }
+}
+
+fn arg_value<N: FromStr>(args: &[String], name: &str) -> Option<N> {
+    let index = args.iter().position(|arg| arg == name)?;
+    args.get(index + 1)?.parse().ok()
+}
+
+/// A map holding the tiles of the first `applied` frames of a history.
+struct HistoryViewer {
+    history: BuildHistory<TestTile>,
+    map: GameMap<TestTile>,
+    applied: usize,
+}
+
+impl HistoryViewer {
+    fn new(history: BuildHistory<TestTile>) -> Self {
+        Self {
+            history,
+            map: GameMap::with_storage(GridStorage::chunked()),
+            applied: 0,
+        }
+    }
+
+    fn forward(&mut self) {
+        if self.applied < self.history.len() {
+            self.history.apply(self.applied, &self.map);
+            self.show(self.applied);
+            self.applied += 1;
+        }
+    }
+
+    fn back(&mut self) {
+        if self.applied > 0 {
+            self.applied -= 1;
+            self.history.revert(self.applied, &self.map);
+            self.show(self.applied);
+        }
+    }
+
+    /// Applies the frames up to the end of the next step.
+    fn next_step(&mut self) {
+        let Some(step) = self.step_of(self.applied) else {
+            return;
+        };
+        while self.step_of(self.applied) == Some(step) {
+            self.forward();
+        }
+    }
+
+    /// Reverts the frames down to the start of the last applied step.
+    fn previous_step(&mut self) {
+        let Some(step) = self
+            .applied
+            .checked_sub(1)
+            .and_then(|frame| self.step_of(frame))
+        else {
+            return;
+        };
+        while self.applied > 0 && self.step_of(self.applied - 1) == Some(step) {
+            self.back();
+        }
+    }
+
+    fn step_of(&self, frame: usize) -> Option<usize> {
+        self.history
+            .frames()
+            .get(frame)
+            .map(|frame| frame.step_index)
+    }
+
+    /// The renderer shades the tiles the player hasn't seen, show the ones of `frame`.
+    fn show(&self, frame: usize) {
+        for change in self.history.frame(frame).changes.iter() {
+            self.map.set_visible(change.position, true);
+        }
+    }
+
+    /// The last applied frame, the one the changes are highlighted of.
+    fn current_frame(&self) -> Option<usize> {
+        self.applied.checked_sub(1)
+    }
+}
+
+fn glyph(tile: &Option<TestTile>) -> char {
+    tile.as_ref().map_or(' ', |tile| tile.glyph())
+}
+
+/// Builds the level of `seed` with `pipeline`, recording its history, and replays it until
+/// escape is pressed.
+pub async fn run_history_viewer(
+    pipeline: &PipelineConfig,
+    seed: u64,
+    options: HistoryOptions,
+    sprites: &SpriteContainer,
+    viewport: Viewport,
+) {
+    let mut history = BuildHistory::new();
+    if let Some(tiles_per_frame) = options.tiles_per_frame {
+        history = history.with_tiles_per_frame(tiles_per_frame);
+    }
+    let mut map_builder = MapBuilder::<TestTile>::new(IntExtent2::new(-100, -100, 200, 200))
+        .with_storage(GridStorage::chunked())
+        .with_seed(seed)
+        .with_depth(options.depth)
+        .with_history(history);
+    pipeline
+        .add_tiles(&mut map_builder, TestTile::from_kind)
+        .and_then(|_| BuilderRegistry::new().build(pipeline, &mut map_builder))
+        .unwrap_or_else(|error| panic!("invalid map pipeline: {}", error));
+    let history = map_builder.history.take().unwrap_or_default();
+    tracing::info!(
+        "history of seed {}: {} steps, {} frames",
+        seed,
+        history.steps(),
+        history.len()
+    );
+
+    let mut viewer = HistoryViewer::new(history);
+    let mut camera =
+        TestCamera2D::from_viewport(Vec2::default(), &viewport, 1.2, Dimension2::new(12, 12));
+    let renderer = Renderer::from_map_cell_size(camera.cell_size);
+    let mut user_input = UserInput::new();
+
+    loop {
+        user_input.update();
+        match user_input.key_input {
+            KeyInput::Right => viewer.forward(),
+            KeyInput::Left => viewer.back(),
+            KeyInput::Key(KeyCode::PageDown) => viewer.next_step(),
+            KeyInput::Key(KeyCode::PageUp) => viewer.previous_step(),
+            KeyInput::Key(KeyCode::Home) => {
+                while viewer.applied > 0 {
+                    viewer.back();
+                }
+            }
+            KeyInput::Key(KeyCode::End) => {
+                while viewer.applied < viewer.history.len() {
+                    viewer.forward();
+                }
+            }
+            KeyInput::Quit => break,
+            _ => {}
+        }
+        let scroll = user_input.mouse_state.scroll;
+        if scroll != 0. {
+            let zoom = if scroll > 0. { 1.1 } else { 1. / 1.1 };
+            camera.zoom_scale = (camera.zoom_scale * zoom).clamp(0.1, 4.);
+        }
+
+        // follow the changes of the current frame
+        let changes = viewer
+            .current_frame()
+            .map_or(&[][..], |frame| &viewer.history.frame(frame).changes[..]);
+        if !changes.is_empty() {
+            let sum = changes.iter().fold(Vec2::ZERO, |sum, change| {
+                sum + Vec2::new(change.position.x as f32, change.position.y as f32)
+            });
+            camera.position = sum / changes.len() as f32;
+        }
+        camera.update();
+
+        clear_background(DARKBROWN);
+        let visible_cells = camera.visible_tiles_extent;
+        let region = IntExtent2::new(
+            visible_cells.left(),
+            visible_cells.top(),
+            visible_cells.width() + 1,
+            visible_cells.height() + 1,
+        );
+        renderer.render_map_region(&camera, sprites, &viewer.map, &region);
+        let highlights: Vec<RenderOp<TestTile>> = changes
+            .iter()
+            .filter(|change| region.contains(change.position.x, change.position.y))
+            .map(|change| {
+                RenderOp::FillCell(
+                    change.position.x,
+                    change.position.y,
+                    Color::new(1., 0.8, 0., 0.35),
+                )
+            })
+            .collect();
+        renderer.batch_render(&camera, &viewport, sprites, &highlights);
+
+        let mut lines = vec![
+            format!("frame {}/{}", viewer.applied, viewer.history.len()),
+            match viewer.current_frame() {
+                Some(frame) => {
+                    let frame = viewer.history.frame(frame);
+                    format!(
+                        "step {}/{}: {}, {} tiles",
+                        frame.step_index + 1,
+                        viewer.history.steps(),
+                        frame.step,
+                        frame.changes.len()
+                    )
+                }
+                None => "before the first step".to_owned(),
+            },
+            "left/right: frame, page up/down: step, home/end, escape: quit".to_owned(),
+            String::new(),
+        ];
+        let (mouse_x, mouse_y) = mouse_position();
+        if viewport.contains_screen_point(mouse_x, mouse_y) {
+            let cell = camera.viewport_to_tile(Vec2::new(mouse_x, mouse_y));
+            lines.push(format!("cell {},{}", cell.x, cell.y));
+            for (frame, change) in viewer.history.changes_at(cell) {
+                let marker = if frame < viewer.applied { ' ' } else { '-' };
+                lines.push(format!(
+                    "{} frame {} {}: '{}' -> '{}'",
+                    marker,
+                    frame + 1,
+                    viewer.history.frame(frame).step,
+                    glyph(&change.before),
+                    glyph(&change.after)
+                ));
+            }
+        }
+        for (index, line) in lines.iter().enumerate() {
+            draw_text(
+                line,
+                viewport.x + viewport.width + 20.,
+                viewport.y + 30. + index as f32 * 24.,
+                24.,
+                WHITE,
+            );
+        }
+
+        next_frame().await
+    }
+}
@@ -0,0 +1,3 @@
+//! What the game and its tools share, e.g. the map preview in `src/bin`.
+
+pub mod tiles;
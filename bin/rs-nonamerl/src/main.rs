@@ -19,12 +19,13 @@ use rs_nonamerl_core::{
 mod commands;
 mod components;
 mod events;
+mod history_viewer;
 mod resources;
-mod tiles;
 
 use commands::*;
 use components::{CharacterInfo, *};
 use events::*;
+use history_viewer::*;
 use resources::*;
 
 mod systems;
@@ -51,6 +52,7 @@ pub struct FovData {
     pub fov_size: i32,
     pub fov_cells: HashSet<IntVector2>,
     pub current_fov_cells: HashSet<IntVector2>,
+    pub visibility: VisibilityMap,
 }
 
 impl Default for FovData {
@@ -59,6 +61,7 @@ impl Default for FovData {
             fov_size: 5,
             fov_cells: HashSet::new(),
             current_fov_cells: HashSet::new(),
+            visibility: VisibilityMap::default(),
         }
     }
 }
@@ -67,6 +70,25 @@ impl Default for FovData {
 pub struct LevelData {
     pub rooms: Vec<Room>,
     // pub corridors: Vec<Vec<IntVector2>>,
+    /// The rooms, the corridors between them and their roles.
+    pub room_graph: RoomGraph,
+    /// Where the vaults ask for enemies and items.
+    pub spawn_markers: Vec<SpawnMarker>,
+    /// The stairs to the levels above and below.
+    pub stairs: Vec<Stair>,
+    /// The seed the level was generated from: set `NONAMERL_SEED` to play the dungeon again.
+    pub seed: u64,
+    pub depth: u32,
+}
+
+/// Reads the seed from the `NONAMERL_SEED` environment variable, falling back to the seed of the
+/// pipeline and then to a random one.
+fn level_seed(pipeline_seed: Option<u64>) -> u64 {
+    std::env::var("NONAMERL_SEED")
+        .ok()
+        .and_then(|seed| seed.parse().ok())
+        .or(pipeline_seed)
+        .unwrap_or_else(::rand::random)
 }
 
 pub fn create_player(world: &mut World) {
@@ -149,13 +171,28 @@ async fn main() {
     world.insert_resource(sprite_container);
     world.insert_resource(MapCommands::default());
     world.insert_resource(EntityActionQueue::default());
-    world.insert_resource(LevelData::default());
+    let pipeline = PipelineConfig::from_file("data/config/pipeline.json");
+    let seed = level_seed(pipeline.seed);
+    tracing::info!("level seed: {}", seed);
+    rand::srand(seed);
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(options) = HistoryOptions::from_args(&args) {
+        let seed = Dungeon::new(seed).level_seed(options.depth);
+        let sprites = world.resource::<SpriteContainer>();
+        run_history_viewer(&pipeline, seed, options, sprites, viewport).await;
+        return;
+    }
+
+    world.insert_resource(Dungeon::new(seed));
+    world.insert_resource(LevelPipeline { config: pipeline });
     world.insert_resource(CurrentCellInfo::default());
     world.insert_resource(GameContext::default());
 
     // init events
     world.init_resource::<Events<ChangeGameStateEvent>>();
     world.init_resource::<Events<UpdateAvailableInteractionsEvent>>();
+    world.init_resource::<Events<TakeStairsEvent>>();
 
     create_player(&mut world);
 
@@ -163,8 +200,14 @@ async fn main() {
     let mut setup_schedule = Schedule::default();
     setup_schedule.add_systems(generate_world_map);
     setup_schedule.add_systems(setup_ui);
-    setup_schedule.add_systems(spawn_enemies.after(generate_world_map));
-    setup_schedule.add_systems(spawn_items.after(generate_world_map));
+
+    // run on every new level, once its map is built
+    let mut populate_schedule = Schedule::default();
+    populate_schedule.add_systems(spawn_enemies);
+    populate_schedule.add_systems(spawn_items);
+    world.insert_resource(PopulateSchedule {
+        schedule: populate_schedule,
+    });
 
     let mut input_schedule = Schedule::default();
     input_schedule.set_executor_kind(bevy_ecs::schedule::ExecutorKind::SingleThreaded);
@@ -182,15 +225,24 @@ async fn main() {
     // update_schedule.add_systems(process_actions.after(update_player_position));
 
     update_schedule.add_systems(update_camera);
+    update_schedule.add_systems(stream_world_chunks.after(update_camera));
     update_schedule.add_systems(
         update_fov
-            .after(update_camera)
+            .after(stream_world_chunks)
             .after(update_player_position),
     );
     update_schedule.add_systems(
-        (move_intent_system, pick_intent_system, drink_intent_system).after(update_player_position),
+        (
+            move_intent_system,
+            pick_intent_system,
+            drink_intent_system,
+            open_intent_system,
+        )
+            .after(update_player_position)
+            .after(stream_world_chunks),
     );
     update_schedule.add_systems(user_interact);
+    update_schedule.add_systems(take_stairs.after(update_player_position));
 
     let mut draw_schedule = Schedule::default();
     draw_schedule.set_executor_kind(bevy_ecs::schedule::ExecutorKind::SingleThreaded);
@@ -206,6 +258,7 @@ async fn main() {
 
     // Run the setup schedule once
     setup_schedule.run(&mut world);
+    populate_level(&mut world);
     loop {
         // Run the schedule once. If your app has a "loop", you would run this once per loop
 
@@ -1,12 +1,15 @@
 use std::{
+    collections::HashMap,
     fmt::Debug,
     sync::{Arc, Mutex},
 };
 
-use bevy_ecs::system::Resource;
+use bevy_ecs::{schedule::Schedule, system::Resource};
 use macroquad::ui::Skin;
+use rs_nonamerl::tiles::TestTile;
+use rs_nonamerl_core::prelude::{ChunkStreamer, GameMap, PipelineConfig};
 
-use crate::{components::Interaction, tiles::TestTile};
+use crate::{components::Interaction, LevelData};
 
 #[derive(Clone, Debug, Resource, Default)]
 pub struct GameContext {
@@ -26,6 +29,61 @@ impl Default for GameState {
     }
 }
 
+/// The recipe the levels are built from.
+#[derive(Clone, Debug, Resource, Default)]
+pub struct LevelPipeline {
+    pub config: PipelineConfig,
+}
+
+/// The systems spawning the enemies and the items of a new level, once its map is built.
+#[derive(Resource)]
+pub struct PopulateSchedule {
+    pub schedule: Schedule,
+}
+
+/// A level the player left, kept as it was to be entered again.
+pub struct StoredLevel {
+    pub map: GameMap<TestTile>,
+    pub level_data: LevelData,
+    /// Only the surface streams its chunks.
+    pub streamer: Option<ChunkStreamer<TestTile>>,
+}
+
+/// The levels of the dungeon. The level the player is on lives in the `GameMap` and
+/// `LevelData` resources, the other visited levels are stored here.
+#[derive(Resource, Default)]
+pub struct Dungeon {
+    /// The depth of the current level, 0 being the surface.
+    pub depth: u32,
+    /// The seed of the surface, the seeds of the other levels are derived from it.
+    pub seed: u64,
+    levels: HashMap<u32, StoredLevel>,
+}
+
+impl Dungeon {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            ..Default::default()
+        }
+    }
+
+    /// The seed of the level at `depth`.
+    pub fn level_seed(&self, depth: u32) -> u64 {
+        self.seed
+            .wrapping_add((depth as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15))
+    }
+
+    pub fn store(&mut self, depth: u32, level: StoredLevel) {
+        self.levels.insert(depth, level);
+    }
+
+    /// Removes the level at `depth` from the store, `None` if it was never visited.
+    pub fn take(&mut self, depth: u32) -> Option<StoredLevel> {
+        self.levels.remove(&depth)
+    }
+}
+
 #[derive(Clone, Debug, Resource, Default)]
 pub struct CurrentCellInfo {
     interactions: Arc<Mutex<Vec<Interaction>>>,
@@ -8,12 +8,10 @@ use macroquad::{
     prelude::{vec2, Vec2},
     ui::{root_ui, widgets},
 };
+use rs_nonamerl::tiles::TestTile;
 use rs_nonamerl_core::prelude::{GameMap, TestCamera2D, UserInput, Viewport};
 
-use crate::{
-    components::{Health, Player, Position},
-    tiles::TestTile,
-};
+use crate::components::{Health, Player, Position};
 
 pub fn debug_ui(
     user_input: Res<UserInput>,
@@ -5,14 +5,16 @@ use bevy_ecs::{
     system::{Query, Res},
 };
 use macroquad::prelude::Color;
-use rs_nonamerl_core::prelude::{
-    GameMap, RenderOp, Renderer, SpriteContainer, TestCamera2D, Viewport,
+use rs_nonamerl::tiles::TestTile;
+use rs_nonamerl_core::{
+    prelude::{GameMap, RenderOp, Renderer, SpriteContainer, TestCamera2D, Viewport},
+    IntExtent2,
 };
 use tracy_client::frame_mark;
 
 use crate::{
-    components::{Enemy, Player, Position, SpriteDrawInfo},
-    tiles::TestTile,
+    components::{Enemy, OnLevel, Player, Position, SpriteDrawInfo},
+    resources::Dungeon,
     FovData,
 };
 
@@ -21,17 +23,29 @@ pub fn draw_game_map(
     camera: Res<TestCamera2D>,
     viewport: Res<Viewport>,
     sprites: Res<SpriteContainer>,
+    fov_data: Res<FovData>,
 ) {
     let visibile_cells = camera.visible_tiles_extent;
 
     let renderer = Renderer::from_map_cell_size(camera.cell_size);
-    let mut map_batch = Vec::<RenderOp<TestTile>>::new();
+    // the visible extent is inclusive on both sides
+    let region = IntExtent2::new(
+        visibile_cells.left(),
+        visibile_cells.top(),
+        visibile_cells.width() + 1,
+        visibile_cells.height() + 1,
+    );
+    renderer.render_map_region(&camera, &sprites, &game_map, &region);
 
-    for x in visibile_cells.left()..=visibile_cells.right() {
-        for y in visibile_cells.top()..=visibile_cells.bottom() {
-            if let Some(tile) = game_map.get(x, y) {
-                map_batch.push(RenderOp::DrawTile(x, y, tile));
-            }
+    // dim the cells seen through smoke, foliage, ...
+    let mut map_batch = Vec::<RenderOp<TestTile>>::new();
+    for (position, intensity) in fov_data.visibility.iter() {
+        if *intensity > 0. && *intensity < 1. && region.contains(position.x, position.y) {
+            map_batch.push(RenderOp::FillCell(
+                position.x,
+                position.y,
+                Color::new(0., 0., 0., (1. - intensity) * 0.6),
+            ));
         }
     }
 
@@ -60,7 +74,8 @@ pub fn draw_player(
 }
 
 pub fn draw_enemies(
-    enemies_q: Query<(&Position, &SpriteDrawInfo), With<Enemy>>,
+    enemies_q: Query<(&Position, &SpriteDrawInfo, &OnLevel), With<Enemy>>,
+    dungeon: Res<Dungeon>,
     camera: Res<TestCamera2D>,
     viewport: Res<Viewport>,
     sprites: Res<SpriteContainer>,
@@ -68,7 +83,10 @@ pub fn draw_enemies(
     let renderer = Renderer::from_map_cell_size(camera.cell_size);
     let mut player_batch = Vec::<RenderOp<TestTile>>::new();
 
-    for (position, sprite_draw_info) in enemies_q.iter() {
+    for (position, sprite_draw_info, _) in enemies_q
+        .iter()
+        .filter(|(_, _, on_level)| on_level.depth == dungeon.depth)
+    {
         player_batch.push(RenderOp::DrawEntity(
             position.x,
             position.y,
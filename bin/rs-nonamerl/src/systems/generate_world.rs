@@ -1,134 +1,262 @@
 #![allow(dead_code)]
 use bevy_ecs::{
+    prelude::{Events, Mut},
     query::With,
     system::{Commands, Query, Res, ResMut},
     world::World,
 };
-use macroquad::prelude::KeyCode;
-use noise::{Fbm, Perlin};
-use rand::{seq::IteratorRandom, Rng};
+use macroquad::prelude::{KeyCode, Vec2};
+use rand::{rngs::StdRng, seq::IteratorRandom, Rng, SeedableRng};
+use rs_nonamerl::tiles::TestTile;
 use rs_nonamerl_core::{
     prelude::{
-        BuilderAlgoWithNoise, FillWithFloorBuilderAlgo, GameMap, KeyInput, MapBuilder, RoomBuilder,
+        BiomeBuilder, BiomeTable, BuilderRegistry, ChunkStreamer, GameMap, GridStorage, KeyInput,
+        MapBuilder, RoomTag, StairDirection, TestCamera2D, VisibilityMap,
     },
     IntExtent2, IntVector2,
 };
 
 use crate::{
     components::{
-        DrinkEffect, Enemy, Health, Interaction, Interactions, Item, ItemKind, ModHealth, Player,
-        Position, SpriteDrawInfo, UseKind,
+        DrinkEffect, Enemy, Health, Interaction, Interactions, Item, ItemKind, ModHealth, OnLevel,
+        Player, Position, SpriteDrawInfo, UseKind,
     },
-    resources::{GameContext, GameState},
-    tiles::{TestTile, TileKind},
-    LevelData,
+    events::TakeStairsEvent,
+    resources::{Dungeon, GameContext, GameState, LevelPipeline, PopulateSchedule, StoredLevel},
+    FovData, LevelData,
 };
 
 pub fn generate_world_map(world: &mut World) {
     println!("generate_world_map");
+    generate_level(world, 0);
+}
 
-    let mut map_builder = MapBuilder::<TestTile>::new(IntExtent2::new(-100, -100, 200, 200));
-    map_builder.add_tile(
-        "floor".to_owned(),
-        TestTile {
-            kind: TileKind::Floor,
-            visible: false,
-            ..Default::default()
-        },
-    );
-
-    map_builder.add_tile(
-        "wall".to_owned(),
-        TestTile {
-            kind: TileKind::Wall("wall"),
-            visible: false,
-            ..Default::default()
-        },
-    );
-    map_builder.add_tile(
-        "wall2".to_owned(),
-        TestTile {
-            kind: TileKind::Wall("wall2"),
-            visible: false,
-            ..Default::default()
-        },
-    );
+/// Builds the level at `depth` with the level pipeline and makes it the current one. The
+/// player arrives on the up stairs or, on the surface, in the room nearest to them.
+pub fn generate_level(world: &mut World, depth: u32) {
+    let seed = world.resource::<Dungeon>().level_seed(depth);
+    let pipeline = world.resource::<LevelPipeline>().config.clone();
+    let mut map_builder = MapBuilder::<TestTile>::new(IntExtent2::new(-100, -100, 200, 200))
+        .with_storage(GridStorage::chunked())
+        .with_seed(seed)
+        .with_depth(depth);
+    pipeline
+        .add_tiles(&mut map_builder, TestTile::from_kind)
+        .and_then(|_| BuilderRegistry::new().build(&pipeline, &mut map_builder))
+        .unwrap_or_else(|error| panic!("invalid map pipeline: {}", error));
+    let game_map = map_builder.build();
 
-    let mut noise = Fbm::<Perlin>::default();
+    if let Some(report) = &map_builder.connectivity_report {
+        tracing::info!(
+            "connectivity: {} regions found, {} tunnels dug",
+            report.regions_found,
+            report.tunnels.len()
+        );
+    }
 
-    noise.octaves = 6;
-    noise.frequency = 1.;
-    noise.lacunarity = 2.;
-    noise.persistence = 0.5;
+    // level_data.rooms = map_builder.rooms.clone();
 
-    let f = |x: i32, y: i32, value: f64| {
-        // println!("x: {}, y: {}, value: {}", x, y, value);
-        if value > 0. {
-            Some("wall2".to_owned())
-        } else {
-            None
+    let mut room_graph = map_builder.room_graph();
+    let mut player_query = world.query_filtered::<&mut Position, With<Player>>();
+    let mut player_position = player_query.single_mut(world);
+    let up_stairs = map_builder
+        .stairs
+        .iter()
+        .find(|stair| stair.direction == StairDirection::Up);
+    if let Some(stair) = up_stairs {
+        player_position.x = stair.position.x;
+        player_position.y = stair.position.y;
+    }
+    let start_position = IntVector2::new(player_position.x, player_position.y);
+    if let Some(start) = room_graph.nearest_room(start_position) {
+        room_graph.tag_rooms(start);
+        if up_stairs.is_none() {
+            let center = room_graph.room(start).center();
+            player_position.x = center.x;
+            player_position.y = center.y;
         }
-    };
-
-    let game_map = map_builder
-        .build_step(&FillWithFloorBuilderAlgo::new(
-            IntExtent2::new(-10, -10, 50, 50),
-            "floor",
-        ))
-        .build_step(&BuilderAlgoWithNoise::new(
-            &noise,
-            f,
-            IntExtent2::new(0, 0, 100, 100),
-        ))
-        // .build_step(&RandomWalkBuilder::new(IntVector2::new(0, 0)))
-        .build_step(&RoomBuilder::new())
-        .build();
-
-    // level_data.rooms = map_builder.rooms.clone();
+    }
 
     let level_data = LevelData {
         rooms: map_builder.rooms.clone(),
+        seed,
+        room_graph,
+        spawn_markers: map_builder.spawn_markers.clone(),
+        stairs: map_builder.stairs.clone(),
+        depth,
     };
+
+    // the surface goes on past the level, in chunks made of the same biomes
+    if depth == 0 {
+        let biomes =
+            BiomeTable::from_file("data/config/biomes.json").expect("Failed to load biome file");
+        let mut streamer =
+            ChunkStreamer::new(seed, move |map_builder: &mut MapBuilder<TestTile>| {
+                let extent = map_builder.extent;
+                map_builder.build_step(
+                    &BiomeBuilder::new(extent, biomes.clone()).with_noise_seed(seed as u32),
+                );
+            })
+            .with_tiles_from(&map_builder);
+        streamer.mark_loaded(&map_builder.extent);
+        world.insert_resource(streamer);
+    }
+
     world.insert_resource(game_map);
     world.insert_resource(level_data);
 }
 
+/// Spawns the enemies and the items of the current level.
+pub fn populate_level(world: &mut World) {
+    world.resource_scope(|world, mut schedule: Mut<PopulateSchedule>| {
+        schedule.schedule.run(world);
+    });
+}
+
+/// Moves the player to the level at the other end of the stairs they stand on. The level
+/// left is stored as it is, the level entered is restored or, on the first visit, generated
+/// and populated.
+pub fn take_stairs(world: &mut World) {
+    if world
+        .resource_mut::<Events<TakeStairsEvent>>()
+        .drain()
+        .count()
+        == 0
+    {
+        return;
+    }
+
+    let mut player_query = world.query_filtered::<&Position, With<Player>>();
+    let position = player_query.single(world);
+    let position = IntVector2::new(position.x, position.y);
+    let Some(stair) = world
+        .resource::<LevelData>()
+        .stairs
+        .iter()
+        .find(|stair| stair.position == position)
+        .copied()
+    else {
+        return;
+    };
+    let depth = world.resource::<Dungeon>().depth;
+    let (new_depth, arrival) = match stair.direction {
+        StairDirection::Up => (depth - 1, StairDirection::Down),
+        StairDirection::Down => (depth + 1, StairDirection::Up),
+    };
+    tracing::info!("taking the stairs from depth {} to {}", depth, new_depth);
+
+    // the cells in view now won't be when the player comes back
+    let map = world.remove_resource::<GameMap<TestTile>>().unwrap();
+    let mut fov_data = world.resource_mut::<FovData>();
+    for cell in fov_data.fov_cells.drain() {
+        map.set_visible(cell, false);
+    }
+    fov_data.visibility = VisibilityMap::default();
+    let level = StoredLevel {
+        map,
+        level_data: world.remove_resource::<LevelData>().unwrap(),
+        streamer: world.remove_resource::<ChunkStreamer<TestTile>>(),
+    };
+
+    let mut dungeon = world.resource_mut::<Dungeon>();
+    dungeon.store(depth, level);
+    dungeon.depth = new_depth;
+    match dungeon.take(new_depth) {
+        Some(level) => {
+            let arrival = level
+                .level_data
+                .stairs
+                .iter()
+                .find(|stair| stair.direction == arrival)
+                .map(|stair| stair.position);
+            world.insert_resource(level.map);
+            world.insert_resource(level.level_data);
+            if let Some(streamer) = level.streamer {
+                world.insert_resource(streamer);
+            }
+            if let Some(arrival) = arrival {
+                let mut player_query = world.query_filtered::<&mut Position, With<Player>>();
+                let mut player_position = player_query.single_mut(world);
+                player_position.x = arrival.x;
+                player_position.y = arrival.y;
+            }
+        }
+        None => {
+            generate_level(world, new_depth);
+            populate_level(world);
+        }
+    }
+
+    let mut player_query = world.query_filtered::<&Position, With<Player>>();
+    let position = player_query.single(world);
+    let position = Vec2::new(position.x as f32, position.y as f32);
+    world.resource_mut::<TestCamera2D>().position = position;
+}
+
+/// The streams of random numbers of the spawning systems, see [`spawn_rng`].
+const ENEMIES_STREAM: u64 = 1;
+const ITEMS_STREAM: u64 = 2;
+
+/// The random numbers of a spawning system on the level of `seed`: every `stream` draws its
+/// own numbers, so that where the enemies go says nothing of where the items go.
+fn spawn_rng(seed: u64, stream: u64) -> StdRng {
+    // splitmix64 of the level seed mixed with the stream
+    let mut z = seed ^ (stream << 32);
+    z = z.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    StdRng::seed_from_u64(z ^ (z >> 31))
+}
+
 pub fn spawn_enemies(
     level_data: Res<LevelData>,
     mut commands: Commands,
     player_query: Query<&Position, With<Player>>,
 ) {
     println!("spawn_enemies");
-    let rooms = &level_data.rooms;
-    let mut rng: rand::rngs::ThreadRng = rand::thread_rng();
-    let mut position = player_query.single().clone();
-    // let spawn_point = *rooms
-    //     .iter()
-    //     .skip(1)
-    //     .choose(&mut rng)
-    //     .unwrap()
-    //     .interior_cells()
-    //     .iter()
-    //     .choose(&mut rng)
-    //     .unwrap();
-
-    position.x += rng.gen_range(-5..5);
-    position.y += rng.gen_range(-5..5);
-
-    commands.spawn((
-        Position {
-            x: position.x,
-            y: position.y,
-        },
-        Enemy {},
-        SpriteDrawInfo {
-            sprite_info: "enemy01",
-        },
-        Health {
-            current: 100,
-            max: 100,
-        },
-    ));
+    let room_graph = &level_data.room_graph;
+    let mut rng = spawn_rng(level_data.seed, ENEMIES_STREAM);
+
+    // the boss guards the center of its room
+    let mut spawn_points: Vec<IntVector2> = room_graph
+        .rooms_with_tag(RoomTag::Boss)
+        .map(|room| room_graph.room(room).center())
+        .collect();
+    spawn_points.extend(
+        level_data
+            .spawn_markers
+            .iter()
+            .filter(|marker| marker.kind == "enemy")
+            .map(|marker| marker.position),
+    );
+
+    if spawn_points.is_empty() {
+        let position = player_query.single();
+        spawn_points.push(IntVector2::new(
+            position.x + rng.gen_range(-5..5),
+            position.y + rng.gen_range(-5..5),
+        ));
+    }
+
+    for spawn_point in spawn_points {
+        commands.spawn((
+            Position {
+                x: spawn_point.x,
+                y: spawn_point.y,
+            },
+            Enemy {},
+            OnLevel {
+                depth: level_data.depth,
+            },
+            SpriteDrawInfo {
+                sprite_info: "enemy01",
+            },
+            Health {
+                current: 100,
+                max: 100,
+            },
+        ));
+    }
 }
 
 pub fn spawn_items(
@@ -138,25 +266,48 @@ pub fn spawn_items(
     player_query: Query<&Position, With<Player>>,
     mut game_ctx: ResMut<GameContext>,
 ) {
-    // let rooms = &level_data.rooms;
-    let mut rng: rand::rngs::ThreadRng = rand::thread_rng();
-    let mut position = player_query.single().clone();
-    // let spawn_point = *rooms
-    //     .iter()
-    //     .skip(1)
-    //     .choose(&mut rng)
-    //     .unwrap()
-    //     .interior_cells()
-    //     .iter()
-    //     .choose(&mut rng)
-    //     .unwrap();
-
-    position.x += rng.gen_range(-1..1);
-    position.y += rng.gen_range(-1..1);
-
-    let items = game_map.items(IntVector2::new(position.x, position.y));
-    tracing::info!("items: {:?}", items);
+    let room_graph = &level_data.room_graph;
+    let mut rng = spawn_rng(level_data.seed, ITEMS_STREAM);
+
+    // a potion somewhere in every treasure room
+    let mut spawn_points: Vec<IntVector2> = room_graph
+        .rooms_with_tag(RoomTag::Treasure)
+        .filter_map(|room| {
+            room_graph
+                .room(room)
+                .interior_cells()
+                .into_iter()
+                .choose(&mut rng)
+        })
+        .collect();
+    spawn_points.extend(
+        level_data
+            .spawn_markers
+            .iter()
+            .filter(|marker| marker.kind == "item")
+            .map(|marker| marker.position),
+    );
 
+    if spawn_points.is_empty() {
+        let position = player_query.single();
+        spawn_points.push(IntVector2::new(
+            position.x + rng.gen_range(-1..1),
+            position.y + rng.gen_range(-1..1),
+        ));
+    }
+
+    for spawn_point in spawn_points {
+        spawn_potion(&mut commands, &game_map, spawn_point, level_data.depth);
+    }
+    game_ctx.state = GameState::PlayGame;
+}
+
+fn spawn_potion(
+    commands: &mut Commands,
+    game_map: &GameMap<TestTile>,
+    position: IntVector2,
+    depth: u32,
+) {
     let interactions = Interactions {
         interactions: vec![
             Interaction::new(KeyInput::Key(KeyCode::E), UseKind::Pick),
@@ -181,6 +332,7 @@ pub fn spawn_items(
                 name: "basic potion".to_owned(),
                 kind: ItemKind::Potion,
             },
+            OnLevel { depth },
             SpriteDrawInfo {
                 sprite_info: "item01",
             },
@@ -189,11 +341,43 @@ pub fn spawn_items(
         ))
         .id();
 
-    game_map.add_item(IntVector2::new(position.x, position.y), item_id);
+    game_map.add_item(position, item_id);
 
-    tracing::info!(
-        "items after add: {:?}",
-        game_map.items(IntVector2::new(position.x, position.y))
-    );
-    game_ctx.state = GameState::PlayGame;
+    tracing::info!("items at {:?}: {:?}", position, game_map.items(position));
+}
+
+/// Generates the chunks around the camera and the player as they get close to them, on the
+/// levels that stream their chunks.
+pub fn stream_world_chunks(
+    streamer: Option<ResMut<ChunkStreamer<TestTile>>>,
+    game_map: Res<GameMap<TestTile>>,
+    level_data: Res<LevelData>,
+    camera: Res<TestCamera2D>,
+    player_query: Query<&Position, With<Player>>,
+    mut commands: Commands,
+) {
+    let _span = tracy_client::span!("stream_world_chunks");
+    let Some(mut streamer) = streamer else {
+        return;
+    };
+    let position = player_query.single();
+    let player_area = IntExtent2::new(position.x, position.y, 1, 1);
+
+    match streamer.update(&game_map, &[camera.visible_tiles_extent, player_area]) {
+        Ok(update) if !update.is_empty() => {
+            tracing::debug!(
+                "chunks: {} generated, {} restored, {} evicted",
+                update.generated.len(),
+                update.restored.len(),
+                update.evicted.len()
+            );
+            for marker in update.spawn_markers.iter() {
+                if marker.kind == "item" {
+                    spawn_potion(&mut commands, &game_map, marker.position, level_data.depth);
+                }
+            }
+        }
+        Ok(_) => {}
+        Err(error) => tracing::error!("chunk streaming failed: {}", error),
+    }
 }
@@ -6,13 +6,13 @@ use bevy_ecs::{
 };
 use rs_nonamerl_core::prelude::GameMap;
 
+use rs_nonamerl::tiles::TestTile;
 use tracing::instrument;
 
 use crate::remove_item_from_cell;
 use crate::{
     components::{DrinkEffect, DrinkIntent, Health, Inventory, PickIntent, Player, Position},
     events::UpdateAvailableInteractionsEvent,
-    tiles::TestTile,
 };
 
 // #[derive(Debug, Clone)]
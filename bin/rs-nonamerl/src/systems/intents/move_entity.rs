@@ -4,12 +4,12 @@ use bevy_ecs::{
     system::{Command, Commands, Query, Res},
     world::World,
 };
+use rs_nonamerl::tiles::TestTile;
 use rs_nonamerl_core::{prelude::GameMap, IntVector2};
 
 use crate::{
-    components::{MoveIntent, Player, Position},
+    components::{MoveIntent, OpenIntent, Player, Position},
     events::UpdateAvailableInteractionsEvent,
-    tiles::TestTile,
     Walkable,
 };
 
@@ -108,16 +108,25 @@ pub fn move_intent_system(
             .map(|_| true)
             .unwrap_or_else(|| false);
 
+        // players open the closed doors they bump into
+        if is_player && game_map.is_open(intent.target) == Some(false) {
+            commands.entity(entity).insert(OpenIntent {
+                target: intent.target,
+            });
+            commands.entity(entity).remove::<MoveIntent>();
+            continue;
+        }
+
         // get target tile
-        let target_tile = game_map.get(intent.target.x, intent.target.y);
+        let target_walkable = game_map.with_tile(intent.target, |tile| tile.is_walkable());
 
-        if target_tile.is_none() {
+        if target_walkable.is_none() {
             continue;
             //TODO: handle this
         }
 
         // check if target tile is walkable
-        if target_tile.unwrap().is_walkable() {
+        if target_walkable.unwrap() {
             commands.add(MoveAction {
                 source: IntVector2::new(position.x, position.y),
                 target: intent.target,
@@ -0,0 +1,43 @@
+use bevy_ecs::{
+    prelude::Entity,
+    system::{Command, Commands, Query},
+    world::World,
+};
+use rs_nonamerl_core::{prelude::GameMap, IntVector2};
+
+use rs_nonamerl::tiles::TestTile;
+use tracing::instrument;
+
+use crate::components::OpenIntent;
+
+#[derive(Debug, Clone)]
+pub struct OpenAction {
+    pub entity: Entity,
+    pub target: IntVector2,
+}
+
+impl Command for OpenAction {
+    fn apply(self, world: &mut World) {
+        let game_map = world.resource::<GameMap<TestTile>>();
+        if let Some(open) = game_map.is_open(self.target) {
+            tracing::info!(
+                "entity {:?} {} the door at {:?}",
+                self.entity,
+                if open { "closes" } else { "opens" },
+                self.target
+            );
+            game_map.set_open(self.target, !open);
+        }
+    }
+}
+
+#[instrument(skip(commands, intents))]
+pub fn open_intent_system(intents: Query<(Entity, &OpenIntent)>, mut commands: Commands) {
+    for (entity, intent) in intents.iter() {
+        commands.add(OpenAction {
+            entity,
+            target: intent.target,
+        });
+        commands.entity(entity).remove::<OpenIntent>();
+    }
+}
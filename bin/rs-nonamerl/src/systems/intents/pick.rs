@@ -4,14 +4,14 @@ use bevy_ecs::{
     system::{Command, Commands, Query, Res},
     world::World,
 };
-use rs_nonamerl_core::prelude::GameMap;
+use rs_nonamerl_core::{prelude::GameMap, IntVector2};
 
+use rs_nonamerl::tiles::TestTile;
 use tracing::instrument;
 
 use crate::{
     components::{Inventory, PickIntent, Player, Position},
     events::UpdateAvailableInteractionsEvent,
-    tiles::TestTile,
 };
 
 #[derive(Debug, Clone)]
@@ -24,11 +24,10 @@ pub struct PickAction {
 impl Command for PickAction {
     fn apply(self, world: &mut World) {
         let game_map = world.get_resource_mut::<GameMap<TestTile>>().unwrap();
-        let mut tile = game_map
-            .get(self.tile_position.x, self.tile_position.y)
-            .unwrap();
-        tile.items.retain(|item| *item != self.item);
-        game_map.set(self.tile_position.x, self.tile_position.y, tile);
+        let tile_position = IntVector2::new(self.tile_position.x, self.tile_position.y);
+        game_map.with_tile_mut(tile_position, |tile| {
+            tile.items.retain(|item| *item != self.item)
+        });
         let (mut inventory) = world
             .query::<(&mut Inventory, With<Player>)>()
             .get_single_mut(world)
@@ -7,26 +7,27 @@ use bevy_ecs::{
 };
 use macroquad::prelude::{KeyCode, Vec2};
 
+use rs_nonamerl::tiles::TestTile;
 use rs_nonamerl_core::{
-    prelude::{FovOccluder, GameMap, KeyInput, MapCommand, MapCommands, TestCamera2D, UserInput},
+    prelude::{FieldOfView, GameMap, KeyInput, MapCommand, MapCommands, TestCamera2D, UserInput},
     IntVector2,
 };
 
 use crate::{
-    components::{DrinkIntent, MoveIntent, PickIntent, Player, Position, UseKind},
-    events::ChangeGameStateEvent,
+    components::{DrinkIntent, MoveIntent, OpenIntent, PickIntent, Player, Position, UseKind},
+    events::{ChangeGameStateEvent, TakeStairsEvent},
     resources::{CurrentCellInfo, GameContext, GameState},
-    tiles::TestTile,
     FovData,
 };
 
 pub fn update_player_position(
     user_input: Res<UserInput>,
     // mut action_queue: ResMut<EntityActionQueue>,
-    _game_map: Res<GameMap<TestTile>>,
+    game_map: Res<GameMap<TestTile>>,
     player_query: Query<(Entity, &Position), With<Player>>,
     mut commands: Commands,
     mut writer: EventWriter<ChangeGameStateEvent>,
+    mut stairs_writer: EventWriter<TakeStairsEvent>,
     game_ctx: Res<GameContext>,
 ) {
     let _span = tracy_client::span!();
@@ -70,6 +71,21 @@ pub fn update_player_position(
             if user_input.key_input == KeyInput::Key(KeyCode::I) {
                 writer.send(ChangeGameStateEvent::new(GameState::ShowInventory));
             }
+
+            // open or close a door next to the player
+            if user_input.key_input == KeyInput::Key(KeyCode::O) {
+                let door = [(1, 0), (-1, 0), (0, 1), (0, -1)]
+                    .into_iter()
+                    .map(|(x, y)| IntVector2::new(position.x + x, position.y + y))
+                    .find(|target| game_map.is_open(*target).is_some());
+                if let Some(target) = door {
+                    commands.entity(player_id).insert(OpenIntent { target });
+                }
+            }
+
+            if user_input.key_input == KeyInput::Key(KeyCode::Enter) {
+                stairs_writer.send(TakeStairsEvent);
+            }
         }
         GameState::ShowInventory => {
             if user_input.key_input == KeyInput::Quit {
@@ -197,46 +213,18 @@ pub fn update_fov(
 ) {
     let _span = tracy_client::span!("update_fov");
     let position = player_query.single();
-    let fov_size = fov_data.fov_size;
+    let fov = FieldOfView::new(fov_data.fov_size);
     let start_pos = IntVector2::new(position.x, position.y);
-    // loop over the border of a 5x5 grid centerd in the mouse position
-    for i in -fov_size..=fov_size {
-        for j in -fov_size..=fov_size {
-            if i == fov_size || i == -fov_size || j == fov_size || j == -fov_size {
-                let target = IntVector2::new(position.x + i, position.y + j);
-                let path = game_map.line(start_pos, target);
-                // println!("target: {:?}", target);
-                'outer: for tile in path.iter().map(|v| (v, game_map.get(v.x, v.y)))
-                //.take_while(|x| x.1.is_some())
-                {
-                    let (p, tile) = tile;
-                    // print!("p: {:?}, ", p);
-                    if fov_data.current_fov_cells.contains(p) {
-                        // println!("cell already in fov");
-                        continue;
-                    }
-
-                    let t = game_map.get(p.x, p.y);
-                    if let Some(tile) = t {
-                        // let tile = tile.unwrap();
-
-                        if !(tile.block_visibility() == TestTile::BLOCKED) {
-                            commands.add(MapCommand::SetVisited(*p, true));
-                            fov_data.current_fov_cells.insert(*p);
-                        } else {
-                            // println!("tile blocked");
-                            break 'outer;
-                        }
-                    } else {
-                        // println!("tile not found");
-                        break 'outer;
-                    }
-                }
-            }
 
-            // map_batch.push(RenderOp::HighlightCell(coords.x(), coords.y()));
-        }
-    }
+    fov_data.visibility = game_map.compute_visibility(&fov, start_pos);
+    fov_data.current_fov_cells = fov_data.visibility.cells();
+    commands.add_all(
+        fov_data
+            .current_fov_cells
+            .iter()
+            .map(|v| MapCommand::SetVisited(*v, true))
+            .collect(),
+    );
 
     let fov_cells_to_remove = fov_data.fov_cells.difference(&fov_data.current_fov_cells);
     let fov_cells_to_add = fov_data.current_fov_cells.difference(&fov_data.fov_cells);
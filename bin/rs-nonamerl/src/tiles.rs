@@ -1,21 +1,49 @@
 use bevy_ecs::prelude::Entity;
+use macroquad::prelude::Color;
 use rs_nonamerl_core::prelude::{
-    FovOccluder, ItemContainer, Tile, TileSpriteInfo, VisibilityOcclusion, Visible, Visited,
-    Walkable,
+    FovOccluder, ItemContainer, Openable, StairDirection, Tile, TileSpriteInfo,
+    VisibilityOcclusion, Visible, Visited, Walkable,
 };
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// The walls of the tileset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WallStyle {
+    Wall,
+    Wall2,
+}
+
+impl WallStyle {
+    /// The name of the sprite of the wall.
+    pub fn sprite_name(self) -> &'static str {
+        match self {
+            WallStyle::Wall => "wall",
+            WallStyle::Wall2 => "wall2",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TileKind {
     Grass,
     Floor,
-    Wall(&'static str),
+    Wall(WallStyle),
+    Water,
+    Tree,
+    Stairs(StairDirection),
+    Door { open: bool },
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// The tile of the game. Saves keep its kind and whether the player found it, the field of
+/// view and the items are restored by the game.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TestTile {
     pub kind: TileKind,
     pub visited: bool,
+    #[serde(skip)]
     pub visible: bool,
+    #[serde(skip)]
     pub items: Vec<Entity>,
 }
 
@@ -28,6 +56,23 @@ impl TestTile {
             items: Vec::new(),
         }
     }
+
+    /// The tile of a kind named in the palette of a map pipeline.
+    pub fn from_kind(kind: &str) -> Option<Self> {
+        let kind = match kind {
+            "grass" => TileKind::Grass,
+            "floor" => TileKind::Floor,
+            "water" => TileKind::Water,
+            "tree" => TileKind::Tree,
+            "stairs_up" => TileKind::Stairs(StairDirection::Up),
+            "stairs_down" => TileKind::Stairs(StairDirection::Down),
+            "door" => TileKind::Door { open: false },
+            "wall" => TileKind::Wall(WallStyle::Wall),
+            "wall2" => TileKind::Wall(WallStyle::Wall2),
+            _ => return None,
+        };
+        Some(Self::new(kind))
+    }
 }
 
 impl Default for TestTile {
@@ -44,9 +89,45 @@ impl Default for TestTile {
 impl Tile for TestTile {
     fn sprite_info(&self) -> TileSpriteInfo {
         match self.kind {
-            TileKind::Grass => TileSpriteInfo::SpriteSheet("grass"),
+            // the tileset has no sprites for the overworld yet
+            TileKind::Grass => TileSpriteInfo::Fill(Color::from_rgba(52, 101, 36, 255)),
             TileKind::Floor => TileSpriteInfo::SpriteSheet("floor"),
-            TileKind::Wall(wall_name) => TileSpriteInfo::SpriteSheet(wall_name),
+            TileKind::Wall(style) => TileSpriteInfo::SpriteSheet(style.sprite_name()),
+            TileKind::Water => TileSpriteInfo::Fill(Color::from_rgba(38, 70, 140, 255)),
+            TileKind::Tree => TileSpriteInfo::Fill(Color::from_rgba(20, 55, 20, 255)),
+            TileKind::Stairs(StairDirection::Up) => {
+                TileSpriteInfo::Fill(Color::from_rgba(200, 190, 150, 255))
+            }
+            TileKind::Stairs(StairDirection::Down) => {
+                TileSpriteInfo::Fill(Color::from_rgba(90, 80, 60, 255))
+            }
+            TileKind::Door { open: false } => {
+                TileSpriteInfo::Fill(Color::from_rgba(120, 72, 30, 255))
+            }
+            TileKind::Door { open: true } => {
+                TileSpriteInfo::Fill(Color::from_rgba(60, 36, 15, 255))
+            }
+        }
+    }
+
+    fn movement_cost(&self) -> f32 {
+        match self.kind {
+            TileKind::Tree => 2.,
+            _ => 1.,
+        }
+    }
+
+    fn glyph(&self) -> char {
+        match self.kind {
+            TileKind::Grass => '"',
+            TileKind::Floor => '.',
+            TileKind::Wall(_) => '#',
+            TileKind::Water => '~',
+            TileKind::Tree => 'T',
+            TileKind::Stairs(StairDirection::Up) => '<',
+            TileKind::Stairs(StairDirection::Down) => '>',
+            TileKind::Door { open: false } => '+',
+            TileKind::Door { open: true } => '/',
         }
     }
 }
@@ -71,14 +152,34 @@ impl Visited for TestTile {
 impl FovOccluder for TestTile {
     fn block_visibility(&self) -> VisibilityOcclusion {
         match self.kind {
-            TileKind::Wall(_) => TestTile::BLOCKED,
+            TileKind::Wall(_) | TileKind::Door { open: false } => TestTile::BLOCKED,
+            // forests let the player see a few cells in
+            TileKind::Tree => VisibilityOcclusion::new(0.5).unwrap(),
             _ => TestTile::VISIBLE,
         }
     }
 }
 impl Walkable for TestTile {
     fn is_walkable(&self) -> bool {
-        !matches!(self.kind, TileKind::Wall(_))
+        !matches!(
+            self.kind,
+            TileKind::Wall(_) | TileKind::Water | TileKind::Door { open: false }
+        )
+    }
+}
+
+impl Openable for TestTile {
+    fn is_open(&self) -> Option<bool> {
+        match self.kind {
+            TileKind::Door { open } => Some(open),
+            _ => None,
+        }
+    }
+
+    fn set_open(&mut self, open: bool) {
+        if let TileKind::Door { open: door } = &mut self.kind {
+            *door = open;
+        }
     }
 }
 
@@ -91,6 +192,10 @@ impl ItemContainer for TestTile {
         }
     }
 
+    fn item_count(&self) -> usize {
+        self.items.len()
+    }
+
     fn add_item(&mut self, item: Entity) {
         println!("add_item: {:?}", item);
         self.items.push(item);
@@ -100,3 +205,62 @@ impl ItemContainer for TestTile {
         self.items.retain(|i| *i != item);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rs_nonamerl_core::{
+        prelude::{BuilderRegistry, GridStorage, MapBuilder, MapSave, PipelineConfig},
+        IntExtent2,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_level_save_round_trip() {
+        let config = PipelineConfig::from_json(
+            r#"{
+                "palette": {
+                    "floor": "floor", "wall": "wall", "wall2": "wall2", "door": "door",
+                    "stairs_up": "stairs_up", "stairs_down": "stairs_down"
+                },
+                "steps": [
+                    { "step": "fill", "extent": [0, 0, 60, 40], "tile": "wall2" },
+                    { "step": "bsp", "min_leaf_size": 10 },
+                    { "step": "doors" },
+                    { "step": "stairs" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let mut map_builder = MapBuilder::<TestTile>::new(IntExtent2::new(0, 0, 60, 40))
+            .with_storage(GridStorage::chunked())
+            .with_seed(11);
+        config
+            .add_tiles(&mut map_builder, TestTile::from_kind)
+            .unwrap();
+        BuilderRegistry::new()
+            .build(&config, &mut map_builder)
+            .unwrap();
+
+        let position = map_builder.rooms[0].center();
+        map_builder.map.set_visited(position, true);
+        map_builder.map.set_visible(position, true);
+
+        let save = MapSave::from_builder(&map_builder);
+        let loaded = [
+            MapSave::<TestTile>::from_bytes(&save.to_bytes().unwrap()).unwrap(),
+            MapSave::<TestTile>::from_json(&save.to_json().unwrap()).unwrap(),
+        ];
+        for loaded in loaded {
+            assert_eq!(loaded.rooms.len(), map_builder.rooms.len());
+            assert_eq!(loaded.map.len(), map_builder.map.len());
+            for position in map_builder.extent.iter() {
+                let tile = map_builder.map.get_position(position).map(|tile| TestTile {
+                    visible: false,
+                    ..tile
+                });
+                assert_eq!(loaded.map.get_position(position), tile);
+            }
+        }
+    }
+}
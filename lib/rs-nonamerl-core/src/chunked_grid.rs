@@ -0,0 +1,419 @@
+use std::collections::HashMap;
+
+use morton_encoding::morton_encode;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    prelude::{bresenham_line, Plane},
+    IntExtent2, IntVector2,
+};
+
+pub const DEFAULT_CHUNK_SIZE: u32 = 32;
+
+/// How the cells of a chunk are laid out in memory.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChunkLayout {
+    /// One row after the other.
+    #[default]
+    RowMajor,
+    /// Z-order curve: cells that are close on the plane are close in memory too.
+    Morton,
+}
+
+/// A square block of `chunk_size * chunk_size` cells.
+#[derive(Clone, Debug)]
+pub struct Chunk<T> {
+    cells: Vec<Option<T>>,
+    len: usize,
+}
+
+impl<T: Clone> Chunk<T> {
+    fn new(chunk_size: i32) -> Self {
+        Self {
+            cells: vec![None; (chunk_size * chunk_size) as usize],
+            len: 0,
+        }
+    }
+
+    /// The number of cells of the chunk that hold a value.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// A dense implementation of [`Plane`]: cells are stored in fixed size chunks, allocated on
+/// the first write and keyed by chunk coordinate, so the plane is still unbounded.
+#[derive(Clone, Debug)]
+pub struct ChunkedGrid2D<T>
+where
+    T: Clone,
+{
+    chunk_size: i32,
+    layout: ChunkLayout,
+    chunks: HashMap<IntVector2, Chunk<T>>,
+    len: usize,
+}
+
+impl<T: Clone> ChunkedGrid2D<T> {
+    pub fn new() -> Self {
+        Self {
+            chunk_size: DEFAULT_CHUNK_SIZE as i32,
+            layout: ChunkLayout::default(),
+            chunks: HashMap::new(),
+            len: 0,
+        }
+    }
+
+    /// Sets the size of the side of a chunk. It must be a power of two, not greater than 256.
+    /// Existing chunks are discarded.
+    pub fn with_chunk_size(mut self, chunk_size: u32) -> Self {
+        assert!(
+            chunk_size.is_power_of_two() && chunk_size <= 256,
+            "chunk size must be a power of two not greater than 256"
+        );
+        self.chunk_size = chunk_size as i32;
+        self.chunks.clear();
+        self.len = 0;
+        self
+    }
+
+    /// Sets the memory layout of the chunks. Existing chunks are discarded.
+    pub fn with_layout(mut self, layout: ChunkLayout) -> Self {
+        self.layout = layout;
+        self.chunks.clear();
+        self.len = 0;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn chunk_size(&self) -> u32 {
+        self.chunk_size as u32
+    }
+
+    pub fn layout(&self) -> ChunkLayout {
+        self.layout
+    }
+
+    /// The coordinate of the chunk containing `position`.
+    pub fn chunk_coord(&self, position: IntVector2) -> IntVector2 {
+        IntVector2::new(
+            position.x.div_euclid(self.chunk_size),
+            position.y.div_euclid(self.chunk_size),
+        )
+    }
+
+    /// The cells covered by the chunk at `chunk_coord`.
+    pub fn chunk_extent(&self, chunk_coord: IntVector2) -> IntExtent2 {
+        IntExtent2::new(
+            chunk_coord.x * self.chunk_size,
+            chunk_coord.y * self.chunk_size,
+            self.chunk_size as u32,
+            self.chunk_size as u32,
+        )
+    }
+
+    pub fn chunk_coords(&self) -> impl Iterator<Item = &IntVector2> {
+        self.chunks.keys()
+    }
+
+    pub fn chunk(&self, chunk_coord: IntVector2) -> Option<&Chunk<T>> {
+        self.chunks.get(&chunk_coord)
+    }
+
+    pub fn insert_chunk(&mut self, chunk_coord: IntVector2, chunk: Chunk<T>) {
+        assert_eq!(
+            chunk.cells.len(),
+            (self.chunk_size * self.chunk_size) as usize,
+            "chunk size mismatch"
+        );
+        self.len += chunk.len;
+        if let Some(old) = self.chunks.insert(chunk_coord, chunk) {
+            self.len -= old.len;
+        }
+    }
+
+    pub fn remove_chunk(&mut self, chunk_coord: IntVector2) -> Option<Chunk<T>> {
+        let chunk = self.chunks.remove(&chunk_coord)?;
+        self.len -= chunk.len;
+        Some(chunk)
+    }
+
+    /// Empties a cell, dropping its chunk when it was the last cell holding a value.
+    pub fn remove(&mut self, position: IntVector2) -> Option<T> {
+        let index = self.local_index(position);
+        let chunk_coord = self.chunk_coord(position);
+        let chunk = self.chunks.get_mut(&chunk_coord)?;
+        let value = chunk.cells[index].take()?;
+        chunk.len -= 1;
+        self.len -= 1;
+        if chunk.is_empty() {
+            self.chunks.remove(&chunk_coord);
+        }
+        Some(value)
+    }
+
+    /// Iterates over the cells that hold a value, chunk by chunk in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (IntVector2, &T)> {
+        self.chunks.iter().flat_map(move |(coord, chunk)| {
+            let extent = self.chunk_extent(*coord);
+            (extent.top()..extent.bottom())
+                .flat_map(move |y| {
+                    (extent.left()..extent.right()).map(move |x| IntVector2::new(x, y))
+                })
+                .filter_map(move |pos| {
+                    chunk.cells[self.local_index(pos)]
+                        .as_ref()
+                        .map(|value| (pos, value))
+                })
+        })
+    }
+
+    /// Iterates over the cells of `extent` that hold a value, chunk by chunk.
+    pub fn iter_region<'a>(
+        &'a self,
+        extent: &IntExtent2,
+    ) -> impl Iterator<Item = (IntVector2, &'a T)> + 'a {
+        let extent = *extent;
+        let min = self.chunk_coord(IntVector2::new(extent.left(), extent.top()));
+        let max = self.chunk_coord(IntVector2::new(extent.right() - 1, extent.bottom() - 1));
+
+        (min.y..=max.y)
+            .flat_map(move |y| (min.x..=max.x).map(move |x| IntVector2::new(x, y)))
+            .filter_map(move |coord| self.chunks.get(&coord).map(|chunk| (coord, chunk)))
+            .flat_map(move |(coord, chunk)| {
+                let chunk_extent = self.chunk_extent(coord);
+                let left = chunk_extent.left().max(extent.left());
+                let right = chunk_extent.right().min(extent.right());
+                let top = chunk_extent.top().max(extent.top());
+                let bottom = chunk_extent.bottom().min(extent.bottom());
+
+                (top..bottom)
+                    .flat_map(move |y| (left..right).map(move |x| IntVector2::new(x, y)))
+                    .filter_map(move |pos| {
+                        chunk.cells[self.local_index(pos)]
+                            .as_ref()
+                            .map(|tile| (pos, tile))
+                    })
+            })
+    }
+
+    /// The index of `position` inside its chunk.
+    fn local_index(&self, position: IntVector2) -> usize {
+        let x = position.x.rem_euclid(self.chunk_size);
+        let y = position.y.rem_euclid(self.chunk_size);
+        match self.layout {
+            ChunkLayout::RowMajor => (y * self.chunk_size + x) as usize,
+            ChunkLayout::Morton => morton_encode([x as u8, y as u8]) as usize,
+        }
+    }
+}
+
+/// The chunks as they are in memory, see [`ChunkedGrid2D`]'s serde implementations.
+#[derive(Serialize, Deserialize)]
+struct ChunkedGridData<C> {
+    chunk_size: u32,
+    layout: ChunkLayout,
+    /// The coordinate of every chunk with its cells, in the order of `layout`.
+    chunks: Vec<([i32; 2], C)>,
+}
+
+/// The chunks are written whole, in the memory layout of the grid, which keeps large maps
+/// compact.
+impl<T: Clone + Serialize> Serialize for ChunkedGrid2D<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut chunks: Vec<(&IntVector2, &Chunk<T>)> = self.chunks.iter().collect();
+        chunks.sort_by_key(|(coord, _)| (coord.y, coord.x));
+        ChunkedGridData {
+            chunk_size: self.chunk_size as u32,
+            layout: self.layout,
+            chunks: chunks
+                .into_iter()
+                .map(|(coord, chunk)| ([coord.x, coord.y], chunk.cells.as_slice()))
+                .collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, T: Clone + Deserialize<'de>> Deserialize<'de> for ChunkedGrid2D<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = ChunkedGridData::<Vec<Option<T>>>::deserialize(deserializer)?;
+        if !data.chunk_size.is_power_of_two() || data.chunk_size > 256 {
+            return Err(de::Error::custom(format!(
+                "invalid chunk size {}",
+                data.chunk_size
+            )));
+        }
+
+        let mut grid = Self::new()
+            .with_chunk_size(data.chunk_size)
+            .with_layout(data.layout);
+        for ([x, y], cells) in data.chunks {
+            if cells.len() != (data.chunk_size * data.chunk_size) as usize {
+                return Err(de::Error::custom(format!(
+                    "chunk [{}, {}] has {} cells",
+                    x,
+                    y,
+                    cells.len()
+                )));
+            }
+            let len = cells.iter().filter(|cell| cell.is_some()).count();
+            grid.insert_chunk(IntVector2::new(x, y), Chunk { cells, len });
+        }
+        Ok(grid)
+    }
+}
+
+impl<T: Clone> Default for ChunkedGrid2D<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone> std::fmt::Display for ChunkedGrid2D<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "ChunkedGrid2D [{:?} cells in {:?} chunks]",
+            self.len,
+            self.chunks.len()
+        )
+    }
+}
+
+impl<T: Clone> Plane<T> for ChunkedGrid2D<T> {
+    fn at(&self, position: IntVector2) -> Option<&T> {
+        self.chunks
+            .get(&self.chunk_coord(position))
+            .and_then(|chunk| chunk.cells[self.local_index(position)].as_ref())
+    }
+
+    fn at_mut(&mut self, position: IntVector2) -> Option<&mut T> {
+        let index = self.local_index(position);
+        self.chunks
+            .get_mut(&self.chunk_coord(position))
+            .and_then(|chunk| chunk.cells[index].as_mut())
+    }
+
+    fn put(&mut self, pos: IntVector2, value: T) {
+        let index = self.local_index(pos);
+        let chunk_size = self.chunk_size;
+        let chunk = self
+            .chunks
+            .entry(self.chunk_coord(pos))
+            .or_insert_with(|| Chunk::new(chunk_size));
+
+        if chunk.cells[index].replace(value).is_none() {
+            chunk.len += 1;
+            self.len += 1;
+        }
+    }
+
+    fn neighbors(&self, pos: IntVector2) -> Vec<IntVector2> {
+        let mut neighbors = vec![];
+        for x in -1..=1 {
+            for y in -1..=1 {
+                if x == 0 && y == 0 {
+                    continue;
+                }
+                let neighbor = IntVector2::new(pos.x + x, pos.y + y);
+                if self.at(neighbor).is_some() {
+                    neighbors.push(neighbor);
+                }
+            }
+        }
+        neighbors
+    }
+
+    fn line(&self, start: IntVector2, end: IntVector2) -> Vec<IntVector2> {
+        bresenham_line(start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunked_grid() {
+        for layout in [ChunkLayout::RowMajor, ChunkLayout::Morton] {
+            let mut grid = ChunkedGrid2D::<i32>::new()
+                .with_chunk_size(4)
+                .with_layout(layout);
+
+            grid.put(IntVector2::new(0, 0), 1);
+            grid.put(IntVector2::new(-1, 0), 2);
+            grid.put(IntVector2::new(3, 3), 3);
+            grid.put(IntVector2::new(4, -5), 4);
+            grid.put(IntVector2::new(0, 0), 5);
+
+            assert_eq!(grid.len(), 4);
+            assert_eq!(grid.chunk_coords().count(), 3);
+            assert_eq!(grid.at(IntVector2::new(0, 0)), Some(&5));
+            assert_eq!(grid.at(IntVector2::new(-1, 0)), Some(&2));
+            assert_eq!(grid.at(IntVector2::new(3, 3)), Some(&3));
+            assert_eq!(grid.at(IntVector2::new(4, -5)), Some(&4));
+            assert_eq!(grid.at(IntVector2::new(1, 1)), None);
+            assert_eq!(grid.at(IntVector2::new(100, 100)), None);
+
+            *grid.at_mut(IntVector2::new(3, 3)).unwrap() = 6;
+            assert_eq!(grid.at(IntVector2::new(3, 3)), Some(&6));
+            assert_eq!(grid.neighbors(IntVector2::new(0, 1)).len(), 2);
+        }
+    }
+
+    #[test]
+    fn test_iter_region() {
+        let mut grid = ChunkedGrid2D::<i32>::new().with_chunk_size(4);
+        for pos in IntExtent2::new(-6, -6, 12, 12).iter() {
+            grid.put(pos, pos.x * 100 + pos.y);
+        }
+
+        let extent = IntExtent2::new(-3, -2, 6, 5);
+        let mut cells: Vec<(IntVector2, i32)> = grid
+            .iter_region(&extent)
+            .map(|(pos, value)| (pos, *value))
+            .collect();
+        cells.sort_by_key(|(pos, _)| (pos.y, pos.x));
+
+        let expected: Vec<(IntVector2, i32)> = extent
+            .iter()
+            .map(|pos| (pos, pos.x * 100 + pos.y))
+            .collect();
+        assert_eq!(cells, expected);
+    }
+
+    #[test]
+    fn test_remove_chunk() {
+        let mut grid = ChunkedGrid2D::<i32>::new().with_chunk_size(8);
+        grid.put(IntVector2::new(1, 1), 1);
+        grid.put(IntVector2::new(2, 1), 2);
+        grid.put(IntVector2::new(9, 1), 3);
+
+        let chunk = grid.remove_chunk(IntVector2::new(0, 0)).unwrap();
+        assert_eq!(chunk.len(), 2);
+        assert_eq!(grid.len(), 1);
+        assert_eq!(grid.at(IntVector2::new(1, 1)), None);
+
+        grid.insert_chunk(IntVector2::new(0, 0), chunk);
+        assert_eq!(grid.len(), 3);
+        assert_eq!(grid.at(IntVector2::new(2, 1)), Some(&2));
+
+        assert_eq!(grid.remove(IntVector2::new(9, 1)), Some(3));
+        assert_eq!(grid.remove(IntVector2::new(9, 1)), None);
+        assert_eq!(grid.chunk_coords().count(), 1);
+        assert_eq!(grid.len(), 2);
+    }
+}
@@ -0,0 +1,429 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    prelude::{bresenham_line, FovOccluder, Plane},
+    IntVector2,
+};
+
+/// The metric used to decide if a cell is within the radius of a field of view.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum DistanceMetric {
+    /// `max(|dx|, |dy|)`: the field of view is a square.
+    Chebyshev,
+    /// `|dx| + |dy|`: the field of view is a diamond.
+    Manhattan,
+    /// `sqrt(dx^2 + dy^2)`: the field of view is a circle.
+    #[default]
+    Euclidean,
+}
+
+impl DistanceMetric {
+    /// Returns true if `delta` is within `radius` according to the metric.
+    pub fn within(&self, delta: IntVector2, radius: i32) -> bool {
+        let (dx, dy) = (delta.x.abs(), delta.y.abs());
+        match self {
+            DistanceMetric::Chebyshev => dx.max(dy) <= radius,
+            DistanceMetric::Manhattan => dx + dy <= radius,
+            DistanceMetric::Euclidean => dx * dx + dy * dy <= radius * radius,
+        }
+    }
+}
+
+/// Symmetric recursive shadowcasting.
+///
+/// If a cell `B` is visible from a cell `A`, then `A` is visible from `B`. Walls are
+/// visible when lit, floors only when their center is in view. Cells that are missing
+/// from the plane block the view and are never returned.
+///
+/// See <https://www.albertford.com/shadowcasting/> for a description of the algorithm.
+///
+/// Only tiles returning [`FovOccluder::BLOCKED`] stop the scan. Tiles with a fractional
+/// [`VisibilityOcclusion`](crate::prelude::VisibilityOcclusion) are taken into account by
+/// [`FieldOfView::compute_with_intensity`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FieldOfView {
+    pub radius: i32,
+    pub metric: DistanceMetric,
+    /// Once the opacity accumulated along the ray to a cell reaches this value, the cell is
+    /// no longer visible.
+    pub opacity_threshold: f32,
+}
+
+impl FieldOfView {
+    pub fn new(radius: i32) -> Self {
+        Self {
+            radius,
+            metric: DistanceMetric::default(),
+            opacity_threshold: 0.9,
+        }
+    }
+
+    pub fn with_metric(mut self, metric: DistanceMetric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    pub fn with_opacity_threshold(mut self, opacity_threshold: f32) -> Self {
+        self.opacity_threshold = opacity_threshold;
+        self
+    }
+
+    /// Computes the set of cells visible from `origin`.
+    pub fn compute<T: FovOccluder, P: Plane<T>>(
+        &self,
+        plane: &P,
+        origin: IntVector2,
+    ) -> HashSet<IntVector2> {
+        let mut visible = HashSet::new();
+
+        if plane.at(origin).is_some() {
+            visible.insert(origin);
+        }
+
+        for quadrant in Quadrant::ALL {
+            let scanner = QuadrantScanner {
+                fov: self,
+                plane,
+                origin,
+                quadrant,
+                _marker: std::marker::PhantomData,
+            };
+            scanner.scan(Row::first(), &mut visible);
+        }
+
+        visible
+    }
+
+    /// Computes the cells visible from `origin` together with how much of the light reaches
+    /// them.
+    ///
+    /// The intensity of a cell is the product of the transmittance of the partially occluding
+    /// tiles between `origin` and the cell, so it is 1 when nothing is in the way. Cells whose
+    /// accumulated opacity reaches `opacity_threshold` are dropped.
+    pub fn compute_with_intensity<T: FovOccluder, P: Plane<T>>(
+        &self,
+        plane: &P,
+        origin: IntVector2,
+    ) -> VisibilityMap {
+        let cells = self
+            .compute(plane, origin)
+            .into_iter()
+            .filter_map(|pos| {
+                let intensity = Self::ray_transmittance(plane, origin, pos);
+                (1. - intensity < self.opacity_threshold).then_some((pos, intensity))
+            })
+            .collect();
+
+        VisibilityMap { cells }
+    }
+
+    fn ray_transmittance<T: FovOccluder, P: Plane<T>>(
+        plane: &P,
+        origin: IntVector2,
+        target: IntVector2,
+    ) -> f32 {
+        bresenham_line(origin, target)
+            .iter()
+            .filter(|pos| **pos != origin && **pos != target)
+            .filter_map(|pos| plane.at(*pos))
+            .map(|tile| tile.block_visibility())
+            // fully blocking tiles have already been handled by the shadowcasting
+            .filter(|occlusion| *occlusion != T::BLOCKED)
+            .map(|occlusion| occlusion.transmittance())
+            .product()
+    }
+}
+
+/// The result of [`FieldOfView::compute_with_intensity`]: the visible cells and the
+/// intensity (between 0 and 1) each of them is seen with.
+#[derive(Debug, Clone, Default)]
+pub struct VisibilityMap {
+    cells: HashMap<IntVector2, f32>,
+}
+
+impl VisibilityMap {
+    pub fn is_visible(&self, position: IntVector2) -> bool {
+        self.cells.contains_key(&position)
+    }
+
+    /// Returns the intensity of the cell, 0 if the cell is not visible.
+    pub fn intensity(&self, position: IntVector2) -> f32 {
+        self.cells.get(&position).copied().unwrap_or(0.)
+    }
+
+    pub fn cells(&self) -> HashSet<IntVector2> {
+        self.cells.keys().copied().collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&IntVector2, &f32)> {
+        self.cells.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+}
+
+/// A rational slope `num / den`, with `den > 0`.
+#[derive(Debug, Copy, Clone)]
+struct Slope {
+    num: i32,
+    den: i32,
+}
+
+impl Slope {
+    fn new(num: i32, den: i32) -> Self {
+        Self { num, den }
+    }
+
+    /// The slope of the left edge of the cell at (`depth`, `col`).
+    fn of_cell(depth: i32, col: i32) -> Self {
+        Self::new(2 * col - 1, 2 * depth)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Row {
+    depth: i32,
+    start: Slope,
+    end: Slope,
+}
+
+impl Row {
+    fn first() -> Self {
+        Self {
+            depth: 1,
+            start: Slope::new(-1, 1),
+            end: Slope::new(1, 1),
+        }
+    }
+
+    fn next(&self) -> Self {
+        Self {
+            depth: self.depth + 1,
+            ..*self
+        }
+    }
+
+    /// `round_ties_up(depth * start)`
+    fn min_col(&self) -> i32 {
+        (2 * self.depth * self.start.num + self.start.den).div_euclid(2 * self.start.den)
+    }
+
+    /// `round_ties_down(depth * end)`
+    fn max_col(&self) -> i32 {
+        -(self.end.den - 2 * self.depth * self.end.num).div_euclid(2 * self.end.den)
+    }
+
+    fn is_symmetric(&self, col: i32) -> bool {
+        col * self.start.den >= self.depth * self.start.num
+            && col * self.end.den <= self.depth * self.end.num
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Quadrant {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Quadrant {
+    const ALL: [Quadrant; 4] = [
+        Quadrant::North,
+        Quadrant::East,
+        Quadrant::South,
+        Quadrant::West,
+    ];
+
+    fn transform(&self, origin: IntVector2, depth: i32, col: i32) -> IntVector2 {
+        match self {
+            Quadrant::North => IntVector2::new(origin.x + col, origin.y - depth),
+            Quadrant::South => IntVector2::new(origin.x + col, origin.y + depth),
+            Quadrant::East => IntVector2::new(origin.x + depth, origin.y + col),
+            Quadrant::West => IntVector2::new(origin.x - depth, origin.y + col),
+        }
+    }
+}
+
+struct QuadrantScanner<'a, T, P> {
+    fov: &'a FieldOfView,
+    plane: &'a P,
+    origin: IntVector2,
+    quadrant: Quadrant,
+    _marker: std::marker::PhantomData<T>,
+}
+
+impl<'a, T: FovOccluder, P: Plane<T>> QuadrantScanner<'a, T, P> {
+    fn is_opaque(&self, pos: IntVector2) -> bool {
+        match self.plane.at(pos) {
+            Some(tile) => tile.block_visibility() == T::BLOCKED,
+            None => true,
+        }
+    }
+
+    fn scan(&self, mut row: Row, visible: &mut HashSet<IntVector2>) {
+        if row.depth > self.fov.radius {
+            return;
+        }
+
+        let mut prev_opaque: Option<bool> = None;
+        for col in row.min_col()..=row.max_col() {
+            let pos = self.quadrant.transform(self.origin, row.depth, col);
+            let opaque = self.is_opaque(pos);
+
+            if (opaque || row.is_symmetric(col))
+                && self.fov.metric.within(pos - self.origin, self.fov.radius)
+                && self.plane.at(pos).is_some()
+            {
+                visible.insert(pos);
+            }
+
+            match (prev_opaque, opaque) {
+                (Some(true), false) => row.start = Slope::of_cell(row.depth, col),
+                (Some(false), true) => {
+                    let mut next_row = row.next();
+                    next_row.end = Slope::of_cell(row.depth, col);
+                    self.scan(next_row, visible);
+                }
+                _ => {}
+            }
+            prev_opaque = Some(opaque);
+        }
+
+        if prev_opaque == Some(false) {
+            self.scan(row.next(), visible);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{grid_from_rows, TestTile};
+
+    #[test]
+    fn test_open_room_is_fully_visible() {
+        let grid = grid_from_rows(&[
+            "#####", //
+            "#...#", //
+            "#...#", //
+            "#...#", //
+            "#####",
+        ]);
+
+        let visible = FieldOfView::new(10).compute(&grid, IntVector2::new(2, 2));
+        assert_eq!(visible.len(), 25);
+    }
+
+    #[test]
+    fn test_walls_cast_shadows() {
+        let grid = grid_from_rows(&[
+            ".......", //
+            ".......", //
+            "...#...", //
+            ".......", //
+        ]);
+
+        let visible = FieldOfView::new(10).compute(&grid, IntVector2::new(3, 3));
+        assert!(visible.contains(&IntVector2::new(3, 2)));
+        assert!(!visible.contains(&IntVector2::new(3, 1)));
+        assert!(!visible.contains(&IntVector2::new(3, 0)));
+        assert!(visible.contains(&IntVector2::new(0, 0)));
+    }
+
+    #[test]
+    fn test_fov_is_symmetric() {
+        let grid = grid_from_rows(&[
+            "..........", //
+            "..#....#..", //
+            "....#.....", //
+            ".#.....#..", //
+            "......#...", //
+            "..#.......", //
+        ]);
+
+        let fov = FieldOfView::new(20);
+        let floors: Vec<IntVector2> = (0..10)
+            .flat_map(|x| (0..6).map(move |y| IntVector2::new(x, y)))
+            .filter(|p| grid.at(*p) == Some(&TestTile::Floor))
+            .collect();
+
+        for a in floors.iter() {
+            let from_a = fov.compute(&grid, *a);
+            for b in floors.iter() {
+                let from_b = fov.compute(&grid, *b);
+                assert_eq!(from_a.contains(b), from_b.contains(a), "{:?} {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_radius_and_metric() {
+        let grid = grid_from_rows(&[
+            ".......", //
+            ".......", //
+            ".......", //
+            ".......", //
+            ".......", //
+            ".......", //
+            ".......", //
+        ]);
+        let origin = IntVector2::new(3, 3);
+
+        let square = FieldOfView::new(2)
+            .with_metric(DistanceMetric::Chebyshev)
+            .compute(&grid, origin);
+        assert_eq!(square.len(), 25);
+
+        let diamond = FieldOfView::new(2)
+            .with_metric(DistanceMetric::Manhattan)
+            .compute(&grid, origin);
+        assert_eq!(diamond.len(), 13);
+        assert!(!diamond.contains(&IntVector2::new(1, 1)));
+    }
+
+    #[test]
+    fn test_partial_occlusion_attenuates() {
+        let grid = grid_from_rows(&[
+            "........", //
+            ".~......", //
+            "........", //
+        ]);
+        let origin = IntVector2::new(0, 1);
+
+        let visibility = FieldOfView::new(10).compute_with_intensity(&grid, origin);
+        assert_eq!(visibility.intensity(IntVector2::new(1, 1)), 1.);
+        assert_eq!(visibility.intensity(IntVector2::new(5, 1)), 0.5);
+        assert_eq!(visibility.intensity(IntVector2::new(1, 0)), 1.);
+
+        let visibility = FieldOfView::new(10)
+            .with_opacity_threshold(0.5)
+            .compute_with_intensity(&grid, origin);
+        assert!(visibility.is_visible(IntVector2::new(1, 1)));
+        assert!(!visibility.is_visible(IntVector2::new(5, 1)));
+    }
+
+    #[test]
+    fn test_opacity_accumulates() {
+        let grid = grid_from_rows(&[
+            ".~~~~.", //
+        ]);
+        let origin = IntVector2::new(0, 0);
+
+        let visibility = FieldOfView::new(10)
+            .with_opacity_threshold(1.)
+            .compute_with_intensity(&grid, origin);
+        assert_eq!(visibility.intensity(IntVector2::new(3, 0)), 0.25);
+        assert_eq!(visibility.intensity(IntVector2::new(5, 0)), 0.0625);
+
+        let visibility = FieldOfView::new(10).compute_with_intensity(&grid, origin);
+        assert!(visibility.is_visible(IntVector2::new(4, 0)));
+        assert!(!visibility.is_visible(IntVector2::new(5, 0)));
+    }
+}
@@ -1,9 +1,10 @@
 #![allow(dead_code)]
 
 use morton_encoding::{morton_decode, morton_encode};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashMap;
 
-use crate::IntVector2;
+use crate::{IntExtent2, IntVector2};
 
 /// A generic struct representing a Plane (Lattice) in 2D space. It is a plane where coordinates
 /// are integers and the content of each cell is generic of type T.
@@ -78,6 +79,51 @@ impl<T: Clone> LatticeGrid2D<T> {
     pub fn is_empty(&self) -> bool {
         self.data.is_empty()
     }
+
+    pub fn remove(&mut self, position: IntVector2) -> Option<T> {
+        self.data.remove(&position)
+    }
+
+    /// Iterates over the cells that hold a value, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (IntVector2, &T)> {
+        self.data.iter().map(|(pos, value)| (*pos, value))
+    }
+
+    /// Iterates over the cells of `extent` that hold a value, row by row.
+    pub fn iter_region<'a>(
+        &'a self,
+        extent: &IntExtent2,
+    ) -> impl Iterator<Item = (IntVector2, &'a T)> + 'a {
+        let extent = *extent;
+        (extent.top()..extent.bottom())
+            .flat_map(move |y| (extent.left()..extent.right()).map(move |x| IntVector2::new(x, y)))
+            .filter_map(move |pos| self.data.get(&pos).map(|tile| (pos, tile)))
+    }
+}
+
+/// The cells are written row by row, as `[[x, y], value]` pairs.
+impl<T: Clone + Serialize> Serialize for LatticeGrid2D<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut cells: Vec<(&IntVector2, &T)> = self.data.iter().collect();
+        cells.sort_by_key(|(pos, _)| (pos.y, pos.x));
+        serializer.collect_seq(
+            cells
+                .into_iter()
+                .map(|(pos, value)| ([pos.x, pos.y], value)),
+        )
+    }
+}
+
+impl<'de, T: Clone + Deserialize<'de>> Deserialize<'de> for LatticeGrid2D<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let cells = Vec::<([i32; 2], T)>::deserialize(deserializer)?;
+        Ok(Self {
+            data: cells
+                .into_iter()
+                .map(|([x, y], value)| (IntVector2::new(x, y), value))
+                .collect(),
+        })
+    }
 }
 
 impl<T: Clone> Default for LatticeGrid2D<T> {
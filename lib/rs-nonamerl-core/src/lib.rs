@@ -5,17 +5,26 @@ mod geometry;
 
 mod action;
 mod camera;
+mod chunked_grid;
+mod fov;
 mod map;
+mod path;
 mod renderer;
 mod sprite;
 mod tile;
 mod user_input;
 
+#[cfg(test)]
+mod test_utils;
+
 pub mod prelude {
     pub use crate::action::*;
     pub use crate::camera::*;
+    pub use crate::chunked_grid::*;
+    pub use crate::fov::*;
     pub use crate::geometry::*;
     pub use crate::map::*;
+    pub use crate::path::*;
     pub use crate::renderer::*;
     pub use crate::sprite::*;
     pub use crate::tile::*;
@@ -0,0 +1,96 @@
+use crate::{
+    prelude::{Plane, Tile},
+    IntExtent2, IntVector2,
+};
+
+use super::GameMap;
+
+impl<T: Tile> GameMap<T> {
+    /// Draws the tiles of `extent` with their [`Tile::glyph`], one line per row. Empty cells
+    /// are spaces and the spaces at the end of the lines are trimmed, so the result can be
+    /// read back with [`GameMap::from_ascii`].
+    pub fn to_ascii(&self, extent: &IntExtent2) -> String {
+        let grid = self.read();
+        (extent.top()..extent.bottom())
+            .map(|y| {
+                let row: String = (extent.left()..extent.right())
+                    .map(|x| {
+                        grid.at(IntVector2::new(x, y))
+                            .map_or(' ', |tile| tile.glyph())
+                    })
+                    .collect();
+                row.trim_end().to_owned()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Builds a map from lines of characters, the first character of the first line being at
+    /// `origin`. `legend` gives the tile of a character, the characters it doesn't know, like
+    /// spaces, leave their cell empty.
+    pub fn from_ascii(ascii: &str, origin: IntVector2, legend: impl Fn(char) -> Option<T>) -> Self {
+        let map = Self::new();
+        {
+            let mut grid = map.write();
+            for (y, line) in ascii.lines().enumerate() {
+                for (x, c) in line.chars().enumerate() {
+                    if let Some(tile) = legend(c) {
+                        grid.put(origin + IntVector2::new(x as i32, y as i32), tile);
+                    }
+                }
+            }
+        }
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestTile;
+
+    use super::super::{CaveBuilder, MapBuilder};
+
+    #[test]
+    fn test_ascii_round_trip() {
+        let ascii = "\
+#####
+#.~.#
+#./.+
+#####   #";
+        let map = GameMap::from_ascii(ascii, IntVector2::new(-2, 3), TestTile::from_char);
+        assert_eq!(map.len(), 21);
+        assert_eq!(map.get(-2, 3), Some(TestTile::Wall));
+        assert_eq!(map.get(0, 5), Some(TestTile::Door { open: true }));
+        assert_eq!(map.get(3, 6), None);
+
+        assert_eq!(map.to_ascii(&IntExtent2::new(-2, 3, 9, 4)), ascii);
+        // cells outside of the extent are left out
+        assert_eq!(map.to_ascii(&IntExtent2::new(-1, 4, 3, 3)), ".~.\n./.\n###");
+    }
+
+    #[test]
+    fn test_ascii_snapshot() {
+        let mut map_builder =
+            MapBuilder::<TestTile>::new(IntExtent2::new(0, 0, 24, 10)).with_seed(4);
+        map_builder.add_tile("floor".to_owned(), TestTile::Floor);
+        map_builder.add_tile("wall".to_owned(), TestTile::Wall);
+        map_builder.build_step(&CaveBuilder::new(IntExtent2::new(0, 0, 24, 10)));
+
+        let snapshot = map_builder.map.to_ascii(&map_builder.extent);
+        assert_eq!(
+            snapshot,
+            "\
+########################
+##...###################
+#......#################
+#.......################
+#.......################
+#......#################
+##.....#################
+##......################
+##......################
+###....#################"
+        );
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{prelude::Tile, IntExtent2};
+
+use super::{MapBuilder, MapBuilderAlgorithm, SpawnMarker};
+
+/// The noise fields a [`BiomeBuilder`] samples.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Climate {
+    Elevation,
+    Moisture,
+    Temperature,
+}
+
+impl Climate {
+    pub const ALL: [Climate; 3] = [Climate::Elevation, Climate::Moisture, Climate::Temperature];
+}
+
+/// The `Fbm` parameters of a noise field. The noise is sampled at map coordinates, so
+/// `frequency` is roughly the inverse of the size of a biome, in cells.
+#[derive(Debug, Copy, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct NoiseLayer {
+    pub octaves: usize,
+    pub frequency: f64,
+    pub lacunarity: f64,
+    pub persistence: f64,
+}
+
+impl Default for NoiseLayer {
+    fn default() -> Self {
+        Self {
+            octaves: 4,
+            frequency: 0.02,
+            lacunarity: 2.,
+            persistence: 0.5,
+        }
+    }
+}
+
+impl NoiseLayer {
+    fn fbm(&self, seed: u32) -> Fbm<Perlin> {
+        Fbm::<Perlin>::new(seed)
+            .set_octaves(self.octaves)
+            .set_frequency(self.frequency)
+            .set_lacunarity(self.lacunarity)
+            .set_persistence(self.persistence)
+    }
+}
+
+/// Something put on a cell of a biome, in place of its tile or as a spawn marker.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Decoration {
+    /// The chance of a cell to get the decoration.
+    pub probability: f64,
+    #[serde(default)]
+    pub tile: Option<String>,
+    #[serde(default)]
+    pub spawn: Option<String>,
+}
+
+/// A row of a [`BiomeTable`]: the cells whose climate values are all in range get `tile`.
+/// Values go from 0 to 1, ranges include both ends and are `[0, 1]` when missing.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Biome {
+    pub name: String,
+    pub tile: String,
+    #[serde(default)]
+    pub ranges: HashMap<Climate, (f64, f64)>,
+    #[serde(default)]
+    pub decorations: Vec<Decoration>,
+}
+
+impl Biome {
+    pub fn contains(&self, climate: &HashMap<Climate, f64>) -> bool {
+        self.ranges.iter().all(|(layer, (min, max))| {
+            climate
+                .get(layer)
+                .is_some_and(|value| *min <= *value && *value <= *max)
+        })
+    }
+}
+
+/// The noise fields and the biomes of a [`BiomeBuilder`], usually loaded from a JSON file.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct BiomeTable {
+    /// The parameters of every noise field, the missing ones use [`NoiseLayer::default`].
+    #[serde(default)]
+    pub layers: HashMap<Climate, NoiseLayer>,
+    /// The first biome containing a cell wins.
+    pub biomes: Vec<Biome>,
+}
+
+impl BiomeTable {
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    /// Reads a biome table, a file that is not a valid table is an `InvalidData` error.
+    pub fn from_file(config_path: &str) -> std::io::Result<Self> {
+        let config_content = std::fs::read_to_string(config_path)?;
+        Ok(Self::from_json(&config_content)?)
+    }
+
+    pub fn layer(&self, climate: Climate) -> NoiseLayer {
+        self.layers.get(&climate).copied().unwrap_or_default()
+    }
+
+    /// The biome of a cell with the given climate values.
+    pub fn classify(&self, climate: &HashMap<Climate, f64>) -> Option<&Biome> {
+        self.biomes.iter().find(|biome| biome.contains(climate))
+    }
+}
+
+/// Overworld builder: every cell of `extent` gets the tile of its biome.
+///
+/// Elevation, moisture and temperature are sampled from their own fractal noise and the
+/// first biome of the table matching the three values is used; cells matching no biome are
+/// left untouched. The decorations of the biome are then tried in order, the first one that
+/// succeeds replaces the tile and/or adds a marker to `MapBuilder::spawn_markers`.
+#[derive(Debug, Clone)]
+pub struct BiomeBuilder<T>
+where
+    T: Tile,
+{
+    extent: IntExtent2,
+    table: BiomeTable,
+    noise_seed: Option<u32>,
+    _marker: std::marker::PhantomData<T>,
+}
+
+impl<T> BiomeBuilder<T>
+where
+    T: Tile,
+{
+    pub fn new(extent: IntExtent2, table: BiomeTable) -> Self {
+        Self {
+            extent,
+            table,
+            noise_seed: None,
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Sets the parameters of a noise field.
+    pub fn with_layer(mut self, climate: Climate, layer: NoiseLayer) -> Self {
+        self.table.layers.insert(climate, layer);
+        self
+    }
+
+    /// Sets the seed of the noise fields, by default the map seed. Builders sharing a noise
+    /// seed agree on the biome of every cell, whatever their extent.
+    pub fn with_noise_seed(mut self, noise_seed: u32) -> Self {
+        self.noise_seed = Some(noise_seed);
+        self
+    }
+}
+
+impl<T: Tile> MapBuilderAlgorithm<T> for BiomeBuilder<T> {
+    fn build<'a>(&self, map_builder: &'a mut MapBuilder<T>) -> &'a mut MapBuilder<T> {
+        let seed = self.noise_seed.unwrap_or(map_builder.seed() as u32);
+        let fields: Vec<(Climate, Fbm<Perlin>)> = Climate::ALL
+            .iter()
+            .enumerate()
+            .map(|(i, climate)| {
+                let layer = self.table.layer(*climate);
+                (*climate, layer.fbm(seed.wrapping_add(i as u32)))
+            })
+            .collect();
+
+        let mut climate = HashMap::<Climate, f64>::new();
+        for pos in self.extent.iter() {
+            for (layer, noise) in fields.iter() {
+                // fbm is roughly in [-1, 1]
+                let value = noise.get([pos.x as f64, pos.y as f64]);
+                climate.insert(*layer, ((value + 1.) / 2.).clamp(0., 1.));
+            }
+            let Some(biome) = self.table.classify(&climate) else {
+                continue;
+            };
+
+            let mut tile_name = biome.tile.as_str();
+            for decoration in biome.decorations.iter() {
+                if map_builder
+                    .rng()
+                    .gen_bool(decoration.probability.clamp(0., 1.))
+                {
+                    if let Some(decoration_tile) = &decoration.tile {
+                        tile_name = decoration_tile;
+                    }
+                    if let Some(kind) = &decoration.spawn {
+                        map_builder.spawn_markers.push(SpawnMarker {
+                            position: pos,
+                            kind: kind.clone(),
+                        });
+                    }
+                    break;
+                }
+            }
+            let tile = map_builder.tile(tile_name);
+            map_builder.map.set(pos.x, pos.y, tile);
+        }
+
+        map_builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_utils::TestTile, IntVector2};
+
+    const TABLE: &str = r#"{
+        "layers": { "elevation": { "frequency": 0.05 } },
+        "biomes": [
+            { "name": "lake", "tile": "smoke", "ranges": { "elevation": [0.0, 0.45] } },
+            { "name": "mountain", "tile": "wall", "ranges": { "elevation": [0.6, 1.0] } },
+            {
+                "name": "forest",
+                "tile": "floor",
+                "decorations": [
+                    { "probability": 0.3, "tile": "wall" },
+                    { "probability": 0.5, "spawn": "enemy" }
+                ]
+            }
+        ]
+    }"#;
+
+    fn map_builder(seed: u64) -> MapBuilder<TestTile> {
+        let mut map_builder =
+            MapBuilder::<TestTile>::new(IntExtent2::new(0, 0, 60, 60)).with_seed(seed);
+        map_builder.add_tile("floor".to_owned(), TestTile::Floor);
+        map_builder.add_tile("wall".to_owned(), TestTile::Wall);
+        map_builder.add_tile("smoke".to_owned(), TestTile::Smoke);
+        map_builder
+    }
+
+    #[test]
+    fn test_classify() {
+        let table = BiomeTable::from_json(TABLE).unwrap();
+        assert_eq!(table.layer(Climate::Elevation).frequency, 0.05);
+        assert_eq!(table.layer(Climate::Moisture), NoiseLayer::default());
+
+        let climate = |elevation: f64| {
+            HashMap::from([
+                (Climate::Elevation, elevation),
+                (Climate::Moisture, 0.5),
+                (Climate::Temperature, 0.5),
+            ])
+        };
+        let name = |elevation| table.classify(&climate(elevation)).unwrap().name.as_str();
+        assert_eq!(name(0.1), "lake");
+        assert_eq!(name(0.5), "forest");
+        assert_eq!(name(0.9), "mountain");
+    }
+
+    #[test]
+    fn test_biomes() {
+        let table = BiomeTable::from_json(TABLE).unwrap();
+        let mut map_builder = map_builder(3);
+        let extent = map_builder.extent;
+        map_builder.build_step(&BiomeBuilder::new(extent, table.clone()).with_noise_seed(9));
+
+        let count = |tile: TestTile| {
+            extent
+                .iter()
+                .filter(|pos| map_builder.map.get(pos.x, pos.y) == Some(tile))
+                .count()
+        };
+        assert_eq!(
+            count(TestTile::Floor) + count(TestTile::Wall) + count(TestTile::Smoke),
+            (extent.width() * extent.height()) as usize
+        );
+        assert!(count(TestTile::Floor) > 0);
+        assert!(count(TestTile::Smoke) > 0);
+        assert!(!map_builder.spawn_markers.is_empty());
+        assert!(map_builder.spawn_markers.iter().all(|marker| map_builder
+            .map
+            .get(marker.position.x, marker.position.y)
+            == Some(TestTile::Floor)));
+
+        // the noise seed decides the biomes, wherever the extent is
+        let mut other = self::map_builder(4);
+        let area = IntExtent2::new(30, 30, 40, 40);
+        other.build_step(&BiomeBuilder::new(area, table).with_noise_seed(9));
+        let is_lake = |map_builder: &MapBuilder<TestTile>, pos: IntVector2| {
+            map_builder.map.get(pos.x, pos.y) == Some(TestTile::Smoke)
+        };
+        assert!(IntExtent2::new(30, 30, 30, 30)
+            .iter()
+            .all(|pos| is_lake(&other, pos) == is_lake(&map_builder, pos)));
+    }
+}
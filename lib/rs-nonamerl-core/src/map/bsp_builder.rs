@@ -0,0 +1,291 @@
+use rand::Rng;
+
+use crate::{prelude::Tile, Dimension2, IntExtent2, IntVector2};
+
+use super::{
+    carve_corridor, CorridorStyle, MapBuilder, MapBuilderAlgorithm, Room, RoomConnection, RoomShape,
+};
+
+/// The smallest leaf holding a room of 3x3 cells and the one cell gap around it.
+const MIN_LEAF_SIZE: u32 = 5;
+
+/// Binary space partitioning dungeon builder.
+///
+/// `MapBuilder::extent` is split recursively until the leaves can't be split without getting
+/// smaller than `min_leaf_size`; every leaf gets one room and the rooms of sibling leaves are
+/// connected by a corridor, so every room is reachable. An extent too small for a room gets
+/// none.
+#[derive(Debug, Clone)]
+pub struct BspBuilder<T>
+where
+    T: Tile,
+{
+    min_leaf_size: u32,
+    min_room_size: u32,
+    corridor_style: CorridorStyle,
+    corridor_width: u32,
+    /// The shapes of the rooms, with their weights.
+    shapes: Vec<(RoomShape, f64)>,
+    _marker: std::marker::PhantomData<T>,
+}
+
+impl<T> BspBuilder<T>
+where
+    T: Tile,
+{
+    pub fn new() -> Self {
+        Self {
+            min_leaf_size: 16,
+            min_room_size: 6,
+            corridor_style: CorridorStyle::LShaped,
+            corridor_width: 1,
+            shapes: vec![(RoomShape::Rectangle, 1.)],
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Sets the smallest side a leaf can have after a split. It is raised to 5, the smallest
+    /// leaf a room fits in.
+    pub fn with_min_leaf_size(mut self, min_leaf_size: u32) -> Self {
+        self.min_leaf_size = min_leaf_size.max(MIN_LEAF_SIZE);
+        self
+    }
+
+    /// Sets the smallest side of a room, walls included. It is clamped to the leaf size.
+    pub fn with_min_room_size(mut self, min_room_size: u32) -> Self {
+        self.min_room_size = min_room_size;
+        self
+    }
+
+    pub fn with_corridor_style(mut self, corridor_style: CorridorStyle) -> Self {
+        self.corridor_style = corridor_style;
+        self
+    }
+
+    pub fn with_corridor_width(mut self, corridor_width: u32) -> Self {
+        self.corridor_width = corridor_width;
+        self
+    }
+
+    /// Sets the shapes of the rooms, each picked with a probability proportional to its weight.
+    pub fn with_shapes(mut self, shapes: Vec<(RoomShape, f64)>) -> Self {
+        self.shapes = shapes;
+        self
+    }
+
+    /// Splits `extent` and places the rooms of its leaves in `rooms`, pushing the corridors
+    /// joining sibling leaves in `corridors`.
+    fn partition(
+        &self,
+        map_builder: &mut MapBuilder<T>,
+        extent: IntExtent2,
+        rooms: &mut Vec<Room>,
+        corridors: &mut Vec<(usize, usize)>,
+    ) {
+        // only the whole extent can be that small, splits never make such a leaf
+        if extent.width() < MIN_LEAF_SIZE || extent.height() < MIN_LEAF_SIZE {
+            return;
+        }
+
+        let can_split_x = extent.width() >= 2 * self.min_leaf_size;
+        let can_split_y = extent.height() >= 2 * self.min_leaf_size;
+
+        let split_x = match (can_split_x, can_split_y) {
+            (false, false) => {
+                rooms.push(self.place_room(map_builder, extent));
+                return;
+            }
+            (true, false) => true,
+            (false, true) => false,
+            // prefer cutting the longest side, to avoid long and thin leaves
+            (true, true) => match extent.width().cmp(&extent.height()) {
+                std::cmp::Ordering::Greater => true,
+                std::cmp::Ordering::Less => false,
+                std::cmp::Ordering::Equal => map_builder.rng().gen_bool(0.5),
+            },
+        };
+
+        let (first, second) = if split_x {
+            let cut = map_builder
+                .rng()
+                .gen_range(self.min_leaf_size..=extent.width() - self.min_leaf_size);
+            (
+                IntExtent2::new(extent.left(), extent.top(), cut, extent.height()),
+                IntExtent2::new(
+                    extent.left() + cut as i32,
+                    extent.top(),
+                    extent.width() - cut,
+                    extent.height(),
+                ),
+            )
+        } else {
+            let cut = map_builder
+                .rng()
+                .gen_range(self.min_leaf_size..=extent.height() - self.min_leaf_size);
+            (
+                IntExtent2::new(extent.left(), extent.top(), extent.width(), cut),
+                IntExtent2::new(
+                    extent.left(),
+                    extent.top() + cut as i32,
+                    extent.width(),
+                    extent.height() - cut,
+                ),
+            )
+        };
+
+        let first_room = rooms.len();
+        self.partition(map_builder, first, rooms, corridors);
+        let second_room = rooms.len();
+        self.partition(map_builder, second, rooms, corridors);
+
+        // join the two halves through a random room of each
+        let from = map_builder.rng().gen_range(first_room..second_room);
+        let to = map_builder.rng().gen_range(second_room..rooms.len());
+        corridors.push((from, to));
+    }
+
+    /// Places a room of random size and position inside `leaf`, leaving a one cell gap on
+    /// every side so that rooms of nearby leaves never touch. `leaf` is at least
+    /// `MIN_LEAF_SIZE` cells wide and high.
+    fn place_room(&self, map_builder: &mut MapBuilder<T>, leaf: IntExtent2) -> Room {
+        let max_width = leaf.width() - 2;
+        let max_height = leaf.height() - 2;
+        let min_width = self.min_room_size.clamp(3, max_width);
+        let min_height = self.min_room_size.clamp(3, max_height);
+
+        let rng = map_builder.rng();
+        let width = rng.gen_range(min_width..=max_width);
+        let height = rng.gen_range(min_height..=max_height);
+        let x = leaf.left() + 1 + rng.gen_range(0..=max_width - width) as i32;
+        let y = leaf.top() + 1 + rng.gen_range(0..=max_height - height) as i32;
+
+        let shape = RoomShape::choose(rng, &self.shapes);
+        Room::shaped(
+            rng,
+            IntVector2::new(x, y),
+            Dimension2::new(width, height),
+            shape,
+        )
+    }
+}
+
+impl<T> Default for BspBuilder<T>
+where
+    T: Tile,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Tile> MapBuilderAlgorithm<T> for BspBuilder<T> {
+    fn build<'a>(&self, map_builder: &'a mut MapBuilder<T>) -> &'a mut MapBuilder<T> {
+        let mut rooms = Vec::<Room>::new();
+        let mut corridors = Vec::<(usize, usize)>::new();
+
+        let extent = map_builder.extent;
+        self.partition(map_builder, extent, &mut rooms, &mut corridors);
+
+        let floor = map_builder.tile("floor");
+        let wall = map_builder.tile("wall");
+
+        rooms.iter().for_each(|room| {
+            room.cells().iter().for_each(|pos| {
+                map_builder.map.set(pos.x, pos.y, floor.clone());
+            });
+            room.border_cells().iter().for_each(|pos| {
+                map_builder.map.set(pos.x, pos.y, wall.clone());
+            });
+        });
+
+        let mut connections = Vec::<RoomConnection>::new();
+        for (from, to) in corridors {
+            let corridor = self
+                .corridor_style
+                .path(map_builder, &rooms[from], &rooms[to], &rooms);
+            carve_corridor(map_builder, &corridor, self.corridor_width, &floor);
+            connections.push(RoomConnection {
+                from,
+                to,
+                cells: corridor,
+            });
+        }
+
+        map_builder.rooms = rooms;
+        map_builder.connections = connections;
+
+        map_builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{prelude::AStar, test_utils::TestTile};
+
+    fn build(extent: IntExtent2, min_leaf_size: u32) -> MapBuilder<TestTile> {
+        let mut map_builder = MapBuilder::<TestTile>::new(extent).with_seed(7);
+        map_builder.add_tile("floor".to_owned(), TestTile::Floor);
+        map_builder.add_tile("wall".to_owned(), TestTile::Wall);
+        map_builder.build_step(&BspBuilder::new().with_min_leaf_size(min_leaf_size));
+        map_builder
+    }
+
+    fn assert_rooms_apart(map_builder: &MapBuilder<TestTile>) {
+        let extent = map_builder.extent;
+        let rooms = &map_builder.rooms;
+        assert_eq!(map_builder.connections.len(), rooms.len() - 1);
+        for (i, room) in rooms.iter().enumerate() {
+            assert!(room
+                .cells()
+                .iter()
+                .all(|cell| extent.contains(cell.x, cell.y)));
+            assert!(rooms[i + 1..].iter().all(|other| !room.intersects(other)));
+        }
+    }
+
+    #[test]
+    fn test_bsp_rooms() {
+        let map_builder = build(IntExtent2::new(-30, -20, 90, 70), 12);
+        assert_rooms_apart(&map_builder);
+
+        let rooms = &map_builder.rooms;
+        assert!(rooms.len() >= 4);
+
+        // every room can be reached from the first one
+        let astar = AStar::new();
+        for room in rooms.iter().skip(1) {
+            assert!(map_builder
+                .map
+                .find_path(&astar, rooms[0].center(), room.center())
+                .is_some());
+        }
+    }
+
+    #[test]
+    fn test_bsp_small_leaves() {
+        // a leaf of 0 cells used to be split forever, leaves smaller than 5 cells got rooms
+        // spilling out of them
+        for min_leaf_size in [0, 1, 3] {
+            let map_builder = build(IntExtent2::new(0, 0, 40, 30), min_leaf_size);
+            assert!(map_builder.rooms.len() >= 16);
+            assert_rooms_apart(&map_builder);
+        }
+    }
+
+    #[test]
+    fn test_bsp_small_extent() {
+        // no room fits, a room of 3x3 cells used to be placed outside of the extent
+        for (width, height) in [(1, 1), (4, 30), (40, 4)] {
+            let map_builder = build(IntExtent2::new(0, 0, width, height), 5);
+            assert!(map_builder.rooms.is_empty());
+            assert!(map_builder.connections.is_empty());
+            assert!(map_builder.map.is_empty());
+        }
+
+        // the smallest extent with a room
+        let map_builder = build(IntExtent2::new(0, 0, 5, 5), 5);
+        assert_eq!(map_builder.rooms.len(), 1);
+        assert_rooms_apart(&map_builder);
+    }
+}
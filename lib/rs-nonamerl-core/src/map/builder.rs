@@ -1,10 +1,13 @@
 use std::collections::{HashMap, HashSet};
 
-use rand::seq::{IteratorRandom, SliceRandom};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
 
 use crate::{prelude::Tile, IntExtent2, IntVector2};
 
-use super::{GameMap, Room};
+use super::{
+    step_name, BuildHistory, ConnectivityReport, GameMap, GridStorage, Room, RoomConnection,
+    RoomGraph, SpawnMarker, Stair,
+};
 
 pub trait MapBuilderAlgorithm<T: Tile> {
     /// Builds a map using the given `MapBuilder`.
@@ -25,20 +28,89 @@ pub struct MapBuilder<T: Tile> {
     pub map: GameMap<T>,
     pub extent: IntExtent2,
     pub rooms: Vec<Room>,
+    /// The corridors between `rooms`.
+    pub connections: Vec<RoomConnection>,
+    /// Where the building steps asked for something to be spawned.
+    pub spawn_markers: Vec<SpawnMarker>,
+    /// The stairs placed by a [`StairsBuilder`](super::StairsBuilder) step.
+    pub stairs: Vec<Stair>,
+    /// What the last [`ConnectivityBuilder`](super::ConnectivityBuilder) step changed.
+    pub connectivity_report: Option<ConnectivityReport>,
+    /// The rooms and vaults the building steps tried to place and gave up on, e.g. rooms of a
+    /// [`RoomBuilder`](super::RoomBuilder) overlapping the ones already placed.
+    pub failed_placements: usize,
+    /// What every step changed, when the builder was created with [`MapBuilder::with_history`].
+    pub history: Option<BuildHistory<T>>,
     /// The different types of tiles that can be used to build the map.
     pub(super) tiles: HashMap<String, T>,
+    seed: u64,
+    /// How deep in the dungeon the map is, 0 being the surface.
+    depth: u32,
+    /// The random number generator every building step draws from.
+    rng: StdRng,
 }
 
 impl<T: Tile> MapBuilder<T> {
+    /// Creates a builder with a random seed. Use [`MapBuilder::with_seed`] to get a
+    /// reproducible map.
     pub fn new(extent: IntExtent2) -> Self {
+        let seed = rand::thread_rng().gen();
         Self {
             map: GameMap::<T>::new(),
             tiles: HashMap::new(),
             rooms: Vec::new(),
+            connections: Vec::new(),
+            spawn_markers: Vec::new(),
+            stairs: Vec::new(),
+            connectivity_report: None,
+            failed_placements: 0,
+            history: None,
             extent,
+            seed,
+            depth: 0,
+            rng: StdRng::seed_from_u64(seed),
         }
     }
 
+    /// Reseeds the random number generator: the same seed and the same building steps always
+    /// produce the same map.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Sets the depth of the level: building steps can use it to make deeper levels harder.
+    pub fn with_depth(mut self, depth: u32) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// The random number generator building steps must use instead of `rand::thread_rng()`.
+    pub fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng
+    }
+
+    /// Replaces the map being built with an empty one using `storage`.
+    pub fn with_storage(mut self, storage: GridStorage<T>) -> Self {
+        self.map = GameMap::with_storage(storage);
+        self
+    }
+
+    /// Records the changes of every following step in `history`, to replay them in a viewer.
+    pub fn with_history(mut self, history: BuildHistory<T>) -> Self {
+        self.history = Some(history);
+        self
+    }
+
     pub fn add_tile(&mut self, name: String, tile: T) {
         self.tiles.insert(name, tile);
     }
@@ -47,8 +119,34 @@ impl<T: Tile> MapBuilder<T> {
         self.tiles.get(name)
     }
 
-    pub fn build_step(&mut self, algorithm: &impl MapBuilderAlgorithm<T>) -> &mut Self {
-        algorithm.build(self)
+    /// A copy of the tile added as `name`.
+    ///
+    /// Panics if there is no such tile: the building steps call it with the names they were
+    /// given, which [`BuilderRegistry::build`](super::BuilderRegistry::build) checks first.
+    pub fn tile(&self, name: &str) -> T {
+        self.get_tile(name)
+            .unwrap_or_else(|| panic!("unknown tile {}", name))
+            .clone()
+    }
+
+    pub fn build_step<A: MapBuilderAlgorithm<T>>(&mut self, algorithm: &A) -> &mut Self {
+        if self.history.is_none() {
+            return algorithm.build(self);
+        }
+
+        self.map.start_journal();
+        algorithm.build(self);
+        let changes = self.map.take_journal();
+        if let Some(history) = &mut self.history {
+            history.record(&step_name::<A>(), changes);
+        }
+        self
+    }
+
+    /// The graph of the rooms and of the corridors connecting them. Rooms are not tagged,
+    /// see [`RoomGraph::tag_rooms`].
+    pub fn room_graph(&self) -> RoomGraph {
+        RoomGraph::new(self.rooms.clone(), self.connections.clone())
     }
 
     pub fn build(&mut self) -> GameMap<T> {
@@ -81,10 +179,10 @@ impl<'a, T: Tile> MapBuilderAlgorithm<T> for FillWithFloorBuilderAlgo<'a, T> {
         for x in self.extent.left()..self.extent.right() {
             for y in self.extent.top()..self.extent.bottom() {
                 // let tile = match (x + y).abs().rem_euclid(2) == 0 {
-                //     true => map_builder.tiles[self.tile_name].clone(),
+                //     true => map_builder.tile(self.tile_name),
                 //     false => map_builder.tiles["wall"].clone(),
                 // };
-                let tile = map_builder.tiles[self.tile_name].clone();
+                let tile = map_builder.tile(self.tile_name);
                 map_builder.map.set(x, y, tile);
             }
         }
@@ -113,19 +211,20 @@ impl<T: Tile> RandomWalkBuilder<T> {
 
 impl<T: Tile> MapBuilderAlgorithm<T> for RandomWalkBuilder<T> {
     fn build<'a>(&self, map_builder: &'a mut MapBuilder<T>) -> &'a mut MapBuilder<T> {
-        let mut rng = rand::thread_rng();
         // let _pos = self.start_pos;
 
         let mut current_pos = self.start_pos;
 
         let mut visited = HashSet::<IntVector2>::new();
+        // keeps the insertion order, so that picking a random visited cell is reproducible
+        let mut walk = Vec::<IntVector2>::new();
         let directions = ["up", "down", "left", "right"];
         // generate a random walk
         while visited.len() < 100 {
             let mut next_pos = current_pos;
 
             // randomly choose a direction
-            let direction = directions.choose(&mut rng).unwrap();
+            let direction = directions.choose(map_builder.rng()).unwrap();
             //let direction = directions[dir];
 
             match *direction {
@@ -138,15 +237,16 @@ impl<T: Tile> MapBuilderAlgorithm<T> for RandomWalkBuilder<T> {
 
             if !visited.insert(next_pos) {
                 // select random element from visited
-                current_pos = *visited.iter().choose(&mut rng).unwrap();
+                current_pos = *walk.choose(map_builder.rng()).unwrap();
             } else {
+                walk.push(next_pos);
                 current_pos = next_pos;
             }
         }
         // println!("visited: {:?}", visited);
         //map_builder.map_tiles.tiles = visited.clone();
-        visited.iter().for_each(|pos| {
-            let tile = map_builder.tiles.get("floor").unwrap().clone();
+        walk.iter().for_each(|pos| {
+            let tile = map_builder.tile("floor");
 
             map_builder.map.set(pos.x, pos.y, tile);
         });
@@ -155,3 +255,42 @@ impl<T: Tile> MapBuilderAlgorithm<T> for RandomWalkBuilder<T> {
         map_builder
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{prelude::RoomBuilder, test_utils::TestTile};
+
+    fn build_with_seed(seed: u64) -> MapBuilder<TestTile> {
+        let mut map_builder =
+            MapBuilder::<TestTile>::new(IntExtent2::new(0, 0, 80, 80)).with_seed(seed);
+        map_builder.add_tile("floor".to_owned(), TestTile::Floor);
+        map_builder.add_tile("wall".to_owned(), TestTile::Wall);
+        map_builder
+            .build_step(&RandomWalkBuilder::new(IntVector2::new(40, 40)))
+            .build_step(&RoomBuilder::new());
+        map_builder
+    }
+
+    #[test]
+    fn test_same_seed_same_map() {
+        let a = build_with_seed(42);
+        let b = build_with_seed(42);
+        assert_eq!(a.seed(), 42);
+
+        let extent = IntExtent2::new(-120, -120, 240, 240);
+        assert_eq!(a.map.len(), b.map.len());
+        assert!(extent
+            .iter()
+            .all(|pos| a.map.get(pos.x, pos.y) == b.map.get(pos.x, pos.y)));
+        assert_eq!(
+            a.rooms.iter().map(|room| room.center()).collect::<Vec<_>>(),
+            b.rooms.iter().map(|room| room.center()).collect::<Vec<_>>()
+        );
+
+        let c = build_with_seed(43);
+        assert!(extent
+            .iter()
+            .any(|pos| a.map.get(pos.x, pos.y) != c.map.get(pos.x, pos.y)));
+    }
+}
@@ -0,0 +1,230 @@
+use rand::Rng;
+
+use crate::{prelude::Tile, IntExtent2, IntVector2};
+
+use super::{MapBuilder, MapBuilderAlgorithm};
+
+/// Cave builder based on cellular automata.
+///
+/// The extent is filled with random walls, then smoothed `iterations` times: a floor cell
+/// becomes a wall when at least `birth_limit` of its 8 neighbors are walls, a wall survives
+/// when at least `survival_limit` of its neighbors are walls. Cells outside the extent count
+/// as walls. Finally only the largest connected floor region is kept.
+#[derive(Debug, Clone)]
+pub struct CaveBuilder<T>
+where
+    T: Tile,
+{
+    extent: IntExtent2,
+    fill_ratio: f64,
+    birth_limit: u8,
+    survival_limit: u8,
+    iterations: u32,
+    floor_tile: String,
+    wall_tile: String,
+    _marker: std::marker::PhantomData<T>,
+}
+
+impl<T> CaveBuilder<T>
+where
+    T: Tile,
+{
+    pub fn new(extent: IntExtent2) -> Self {
+        Self {
+            extent,
+            fill_ratio: 0.45,
+            birth_limit: 5,
+            survival_limit: 4,
+            iterations: 5,
+            floor_tile: "floor".to_owned(),
+            wall_tile: "wall".to_owned(),
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Sets the probability of a cell to start as a wall.
+    pub fn with_fill_ratio(mut self, fill_ratio: f64) -> Self {
+        self.fill_ratio = fill_ratio.clamp(0., 1.);
+        self
+    }
+
+    pub fn with_birth_limit(mut self, birth_limit: u8) -> Self {
+        self.birth_limit = birth_limit;
+        self
+    }
+
+    pub fn with_survival_limit(mut self, survival_limit: u8) -> Self {
+        self.survival_limit = survival_limit;
+        self
+    }
+
+    pub fn with_iterations(mut self, iterations: u32) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    /// Sets the names of the tiles, as registered with [`MapBuilder::add_tile`].
+    pub fn with_tiles(mut self, floor_tile: &str, wall_tile: &str) -> Self {
+        self.floor_tile = floor_tile.to_owned();
+        self.wall_tile = wall_tile.to_owned();
+        self
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x >= self.extent.width() as i32 || y >= self.extent.height() as i32 {
+            return None;
+        }
+        Some((y * self.extent.width() as i32 + x) as usize)
+    }
+
+    fn wall_neighbors(&self, walls: &[bool], x: i32, y: i32) -> u8 {
+        let mut count = 0;
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                if dx == 0 && dy == 0 {
+                    continue;
+                }
+                if self.index(x + dx, y + dy).is_none_or(|i| walls[i]) {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    fn step(&self, walls: &[bool]) -> Vec<bool> {
+        let mut next = walls.to_vec();
+        for y in 0..self.extent.height() as i32 {
+            for x in 0..self.extent.width() as i32 {
+                let i = self.index(x, y).unwrap();
+                let neighbors = self.wall_neighbors(walls, x, y);
+                next[i] = if walls[i] {
+                    neighbors >= self.survival_limit
+                } else {
+                    neighbors >= self.birth_limit
+                };
+            }
+        }
+        next
+    }
+
+    /// Turns into walls every floor cell that is not part of the largest region of
+    /// orthogonally connected floor cells.
+    fn keep_largest_region(&self, walls: &mut [bool]) {
+        let mut region_of = vec![usize::MAX; walls.len()];
+        let mut sizes = Vec::<usize>::new();
+
+        for start in 0..walls.len() {
+            if walls[start] || region_of[start] != usize::MAX {
+                continue;
+            }
+
+            let region = sizes.len();
+            let mut size = 0;
+            let mut stack = vec![start];
+            region_of[start] = region;
+            while let Some(i) = stack.pop() {
+                size += 1;
+                let x = (i % self.extent.width() as usize) as i32;
+                let y = (i / self.extent.width() as usize) as i32;
+                for (dx, dy) in [(0, -1), (1, 0), (0, 1), (-1, 0)] {
+                    if let Some(j) = self.index(x + dx, y + dy) {
+                        if !walls[j] && region_of[j] == usize::MAX {
+                            region_of[j] = region;
+                            stack.push(j);
+                        }
+                    }
+                }
+            }
+            sizes.push(size);
+        }
+
+        let Some(largest) = (0..sizes.len()).max_by_key(|region| sizes[*region]) else {
+            return;
+        };
+        for (i, wall) in walls.iter_mut().enumerate() {
+            if !*wall && region_of[i] != largest {
+                *wall = true;
+            }
+        }
+    }
+}
+
+impl<T: Tile> MapBuilderAlgorithm<T> for CaveBuilder<T> {
+    fn build<'a>(&self, map_builder: &'a mut MapBuilder<T>) -> &'a mut MapBuilder<T> {
+        let floor = map_builder.tile(&self.floor_tile);
+        let wall = map_builder.tile(&self.wall_tile);
+
+        let cells = (self.extent.width() * self.extent.height()) as usize;
+        let mut walls: Vec<bool> = (0..cells)
+            .map(|_| map_builder.rng().gen_bool(self.fill_ratio))
+            .collect();
+
+        for _ in 0..self.iterations {
+            walls = self.step(&walls);
+        }
+        self.keep_largest_region(&mut walls);
+
+        for (i, is_wall) in walls.iter().enumerate() {
+            let position = IntVector2::new(
+                self.extent.left() + (i % self.extent.width() as usize) as i32,
+                self.extent.top() + (i / self.extent.width() as usize) as i32,
+            );
+            let tile = if *is_wall {
+                wall.clone()
+            } else {
+                floor.clone()
+            };
+            map_builder.map.set(position.x, position.y, tile);
+        }
+
+        map_builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        prelude::{DijkstraMap, Walkable},
+        test_utils::TestTile,
+    };
+
+    #[test]
+    fn test_single_cave() {
+        let extent = IntExtent2::new(-20, -10, 60, 40);
+        let mut map_builder = MapBuilder::<TestTile>::new(extent).with_seed(3);
+        map_builder.add_tile("floor".to_owned(), TestTile::Floor);
+        map_builder.add_tile("wall".to_owned(), TestTile::Wall);
+        map_builder.build_step(&CaveBuilder::new(extent));
+
+        let floors: Vec<IntVector2> = extent
+            .iter()
+            .filter(|pos| map_builder.map.get(pos.x, pos.y).unwrap().is_walkable())
+            .collect();
+        assert!(floors.len() > (extent.width() * extent.height()) as usize / 4);
+
+        // a single region: every floor cell is reachable from any other
+        let distances = map_builder
+            .map
+            .dijkstra_map(DijkstraMap::new(), &floors[..1]);
+        assert_eq!(distances.len(), floors.len());
+    }
+
+    #[test]
+    fn test_full_fill_ratio() {
+        let extent = IntExtent2::new(0, 0, 10, 10);
+        let mut map_builder = MapBuilder::<TestTile>::new(extent);
+        map_builder.add_tile("floor".to_owned(), TestTile::Floor);
+        map_builder.add_tile("rock".to_owned(), TestTile::Wall);
+        map_builder.build_step(
+            &CaveBuilder::new(extent)
+                .with_fill_ratio(1.)
+                .with_tiles("floor", "rock"),
+        );
+
+        assert!(extent
+            .iter()
+            .all(|pos| map_builder.map.get(pos.x, pos.y) == Some(TestTile::Wall)));
+    }
+}
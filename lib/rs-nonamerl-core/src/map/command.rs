@@ -1,6 +1,9 @@
 use bevy_ecs::{prelude::Entity, system::Resource};
 
-use crate::{prelude::Tile, IntVector2};
+use crate::{
+    prelude::{Plane, Tile},
+    IntVector2,
+};
 
 use super::GameMap;
 
@@ -8,6 +11,7 @@ use super::GameMap;
 pub enum MapCommand {
     SetVisited(IntVector2, bool),
     SetVisible(IntVector2, bool),
+    SetOpen(IntVector2, bool),
     AddItem(IntVector2, Entity),
     RemoveItem(IntVector2, Entity),
 }
@@ -40,20 +44,35 @@ impl MapCommands {
         self.commands.clear();
     }
 
+    /// Applies all the queued commands to `map` under a single write lock.
     pub fn process_commands<T: Tile>(&mut self, map: &mut GameMap<T>) {
+        let mut grid = map.write();
         for command in self.commands.iter() {
             match command {
                 MapCommand::SetVisited(pos, visited) => {
-                    map.set_visited(*pos, *visited);
+                    if let Some(tile) = grid.at_mut(*pos) {
+                        tile.set_visited(*visited);
+                    }
                 }
                 MapCommand::SetVisible(pos, visible) => {
-                    map.set_visible(*pos, *visible);
+                    if let Some(tile) = grid.at_mut(*pos) {
+                        tile.set_visible(*visible);
+                    }
+                }
+                MapCommand::SetOpen(pos, open) => {
+                    if let Some(tile) = grid.at_mut(*pos) {
+                        tile.set_open(*open);
+                    }
                 }
                 MapCommand::AddItem(pos, item) => {
-                    map.add_item(*pos, *item);
+                    if let Some(tile) = grid.at_mut(*pos) {
+                        tile.add_item(*item);
+                    }
                 }
                 MapCommand::RemoveItem(pos, item) => {
-                    map.remove_item(*pos, *item);
+                    if let Some(tile) = grid.at_mut(*pos) {
+                        tile.remove_item(*item);
+                    }
                 }
             }
         }
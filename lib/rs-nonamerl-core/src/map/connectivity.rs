@@ -0,0 +1,378 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::Deserialize;
+
+use crate::{
+    prelude::{AStar, Connectivity, Heuristic, Plane, Tile},
+    IntExtent2, IntVector2,
+};
+
+use super::{MapBuilder, MapBuilderAlgorithm};
+
+/// The walkable cells of an extent, labelled by connected region.
+///
+/// Regions are numbered from 0 in scan order (row by row), so the labelling of a given map is
+/// always the same.
+#[derive(Debug, Clone, Default)]
+pub struct Regions {
+    labels: HashMap<IntVector2, usize>,
+    cells: Vec<Vec<IntVector2>>,
+}
+
+impl Regions {
+    /// Flood-fills the walkable tiles of `extent`. Cells outside `extent` are ignored.
+    pub fn label<T: Tile, P: Plane<T>>(
+        plane: &P,
+        extent: &IntExtent2,
+        connectivity: Connectivity,
+    ) -> Self {
+        Self::label_with(plane, extent, connectivity, |tile| tile.is_walkable())
+    }
+
+    /// Same as [`Regions::label`], but the tiles that can be crossed are given by `passable`,
+    /// e.g. to count closed doors in.
+    pub fn label_with<T, P, F>(
+        plane: &P,
+        extent: &IntExtent2,
+        connectivity: Connectivity,
+        passable: F,
+    ) -> Self
+    where
+        P: Plane<T>,
+        F: Fn(&T) -> bool,
+    {
+        let is_walkable =
+            |pos: IntVector2| extent.contains(pos.x, pos.y) && plane.at(pos).is_some_and(&passable);
+
+        let mut regions = Self::default();
+        for start in extent.iter() {
+            if regions.labels.contains_key(&start) || !is_walkable(start) {
+                continue;
+            }
+
+            let region = regions.cells.len();
+            let mut cells = vec![start];
+            let mut stack = vec![start];
+            regions.labels.insert(start, region);
+            while let Some(pos) = stack.pop() {
+                for offset in connectivity.offsets() {
+                    let next = pos + *offset;
+                    if !regions.labels.contains_key(&next) && is_walkable(next) {
+                        regions.labels.insert(next, region);
+                        cells.push(next);
+                        stack.push(next);
+                    }
+                }
+            }
+            regions.cells.push(cells);
+        }
+
+        regions
+    }
+
+    /// The region `position` belongs to, if it is a walkable cell.
+    pub fn region_of(&self, position: IntVector2) -> Option<usize> {
+        self.labels.get(&position).copied()
+    }
+
+    /// The cells of `region`.
+    pub fn cells(&self, region: usize) -> &[IntVector2] {
+        &self.cells[region]
+    }
+
+    /// The number of regions.
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// The size of every region, indexed by region.
+    pub fn sizes(&self) -> Vec<usize> {
+        self.cells.iter().map(|cells| cells.len()).collect()
+    }
+
+    /// The region with the most cells. Ties go to the first region.
+    pub fn largest(&self) -> Option<usize> {
+        (0..self.len())
+            .rev()
+            .max_by_key(|region| self.cells[*region].len())
+    }
+}
+
+/// What [`ConnectivityBuilder`] does with the regions that are not connected to the largest
+/// one.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectivityRepair {
+    /// Turns them into walls.
+    Cull,
+    /// Digs a corridor from each of them to the largest region.
+    #[default]
+    Tunnel,
+}
+
+/// What a [`ConnectivityBuilder`] step found and changed.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectivityReport {
+    /// The number of regions before the repair.
+    pub regions_found: usize,
+    /// The size of the largest region before the repair.
+    pub largest_region_size: usize,
+    /// The cells turned into walls.
+    pub culled_cells: Vec<IntVector2>,
+    /// The cells dug, one list per tunnel.
+    pub tunnels: Vec<Vec<IntVector2>>,
+}
+
+impl ConnectivityReport {
+    /// Whether the step didn't change the map.
+    pub fn is_unchanged(&self) -> bool {
+        self.culled_cells.is_empty() && self.tunnels.is_empty()
+    }
+}
+
+/// A building step that makes the walkable part of `MapBuilder::extent` a single region.
+///
+/// The regions are found with orthogonal connectivity, so that the result is connected for
+/// every kind of movement. The report of the last run is stored in
+/// `MapBuilder::connectivity_report`.
+#[derive(Debug, Clone)]
+pub struct ConnectivityBuilder<T>
+where
+    T: Tile,
+{
+    repair: ConnectivityRepair,
+    floor_tile: String,
+    wall_tile: String,
+    /// The cost of digging through a cell, relative to walking on a walkable one.
+    dig_cost: f32,
+    _marker: std::marker::PhantomData<T>,
+}
+
+impl<T> ConnectivityBuilder<T>
+where
+    T: Tile,
+{
+    pub fn new(repair: ConnectivityRepair) -> Self {
+        Self {
+            repair,
+            floor_tile: "floor".to_owned(),
+            wall_tile: "wall".to_owned(),
+            dig_cost: 3.,
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Sets the names of the tiles used to dig tunnels and to cull regions.
+    pub fn with_tiles(mut self, floor_tile: &str, wall_tile: &str) -> Self {
+        self.floor_tile = floor_tile.to_owned();
+        self.wall_tile = wall_tile.to_owned();
+        self
+    }
+
+    /// Sets the cost of digging through a cell: higher values make tunnels follow existing
+    /// walkable cells more.
+    pub fn with_dig_cost(mut self, dig_cost: f32) -> Self {
+        self.dig_cost = dig_cost;
+        self
+    }
+
+    fn cull(
+        &self,
+        map_builder: &mut MapBuilder<T>,
+        regions: &Regions,
+        main: usize,
+    ) -> Vec<IntVector2> {
+        let wall = map_builder.tile(&self.wall_tile);
+
+        let mut culled = Vec::<IntVector2>::new();
+        for region in (0..regions.len()).filter(|region| *region != main) {
+            for pos in regions.cells(region) {
+                map_builder.map.set(pos.x, pos.y, wall.clone());
+                culled.push(*pos);
+            }
+        }
+        culled
+    }
+
+    fn tunnel(
+        &self,
+        map_builder: &mut MapBuilder<T>,
+        regions: &Regions,
+        main: usize,
+    ) -> Vec<Vec<IntVector2>> {
+        let floor = map_builder.tile(&self.floor_tile);
+        let extent = map_builder.extent;
+        let astar = AStar::new()
+            .with_connectivity(Connectivity::Four)
+            .with_heuristic(Heuristic::Manhattan);
+
+        let mut connected_regions = HashSet::from([main]);
+        let mut connected = regions.cells(main).to_vec();
+        let mut dug_cells = HashSet::<IntVector2>::new();
+        let mut tunnels = Vec::<Vec<IntVector2>>::new();
+        for region in 0..regions.len() {
+            // an earlier tunnel may already go through this region
+            if connected_regions.contains(&region) {
+                continue;
+            }
+
+            let start = regions.cells(region)[0];
+            let goal = *connected
+                .iter()
+                .min_by_key(|pos| (pos.x - start.x).abs() + (pos.y - start.y).abs())
+                .unwrap();
+            let path = astar
+                .search(start, goal, |pos| {
+                    if !extent.contains(pos.x, pos.y) {
+                        None
+                    } else if regions.region_of(pos).is_some() || dug_cells.contains(&pos) {
+                        Some(1.)
+                    } else {
+                        Some(self.dig_cost)
+                    }
+                })
+                .unwrap_or_default();
+
+            let mut dug = Vec::<IntVector2>::new();
+            for pos in path {
+                if regions.region_of(pos).is_none() && dug_cells.insert(pos) {
+                    map_builder.map.set(pos.x, pos.y, floor.clone());
+                    dug.push(pos);
+                }
+                // the tunnel joins every region it goes through or touches
+                let neighbors = Connectivity::Four
+                    .offsets()
+                    .iter()
+                    .map(|offset| pos + *offset);
+                for neighbor in neighbors.chain([pos]) {
+                    if let Some(touched) = regions.region_of(neighbor) {
+                        if connected_regions.insert(touched) {
+                            connected.extend_from_slice(regions.cells(touched));
+                        }
+                    }
+                }
+            }
+            connected.extend_from_slice(&dug);
+            tunnels.push(dug);
+        }
+        tunnels
+    }
+}
+
+impl<T> Default for ConnectivityBuilder<T>
+where
+    T: Tile,
+{
+    fn default() -> Self {
+        Self::new(ConnectivityRepair::default())
+    }
+}
+
+impl<T: Tile> MapBuilderAlgorithm<T> for ConnectivityBuilder<T> {
+    fn build<'a>(&self, map_builder: &'a mut MapBuilder<T>) -> &'a mut MapBuilder<T> {
+        let regions = Regions::label(
+            &*map_builder.map.read(),
+            &map_builder.extent,
+            Connectivity::Four,
+        );
+
+        let mut report = ConnectivityReport {
+            regions_found: regions.len(),
+            ..Default::default()
+        };
+
+        if let Some(main) = regions.largest() {
+            report.largest_region_size = regions.cells(main).len();
+            match self.repair {
+                ConnectivityRepair::Cull => {
+                    report.culled_cells = self.cull(map_builder, &regions, main);
+                }
+                ConnectivityRepair::Tunnel => {
+                    report.tunnels = self.tunnel(map_builder, &regions, main);
+                }
+            }
+        }
+
+        map_builder.connectivity_report = Some(report);
+
+        map_builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{map_from_rows, TestTile};
+
+    const ROWS: [&str; 5] = [
+        "..#....#.", //
+        "..#....#.", //
+        "####..###", //
+        "..#......", //
+        "..#......", //
+    ];
+
+    fn map_builder() -> MapBuilder<TestTile> {
+        let mut map_builder = MapBuilder::<TestTile>::new(IntExtent2::new(0, 0, 9, 5));
+        map_builder.map = map_from_rows(&ROWS);
+        map_builder.add_tile("floor".to_owned(), TestTile::Floor);
+        map_builder.add_tile("wall".to_owned(), TestTile::Wall);
+        map_builder
+    }
+
+    #[test]
+    fn test_label_regions() {
+        let map_builder = map_builder();
+        let regions = map_builder
+            .map
+            .regions(&map_builder.extent, Connectivity::Four);
+
+        assert_eq!(regions.len(), 4);
+        assert_eq!(regions.sizes(), vec![4, 22, 2, 4]);
+        assert_eq!(regions.largest(), Some(1));
+        assert_eq!(
+            regions.region_of(IntVector2::new(4, 4)),
+            regions.region_of(IntVector2::new(3, 0))
+        );
+        assert_eq!(regions.region_of(IntVector2::new(2, 0)), None);
+    }
+
+    #[test]
+    fn test_cull() {
+        let mut map_builder = map_builder();
+        map_builder.build_step(&ConnectivityBuilder::new(ConnectivityRepair::Cull));
+
+        let report = map_builder.connectivity_report.clone().unwrap();
+        assert_eq!(report.regions_found, 4);
+        assert_eq!(report.largest_region_size, 22);
+        assert_eq!(report.culled_cells.len(), 10);
+        assert_eq!(
+            map_builder
+                .map
+                .regions(&map_builder.extent, Connectivity::Four)
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_tunnel() {
+        let mut map_builder = map_builder();
+        map_builder.build_step(&ConnectivityBuilder::new(ConnectivityRepair::Tunnel));
+
+        let report = map_builder.connectivity_report.clone().unwrap();
+        assert_eq!(report.regions_found, 4);
+        assert!(!report.is_unchanged());
+        assert!(report.culled_cells.is_empty());
+
+        let regions = map_builder
+            .map
+            .regions(&map_builder.extent, Connectivity::Four);
+        assert_eq!(regions.len(), 1);
+        assert_eq!(regions.cells(0).len(), 32 + report.tunnels.concat().len());
+    }
+}
@@ -0,0 +1,307 @@
+use std::collections::HashSet;
+
+use rand::{seq::SliceRandom, Rng};
+use serde::Deserialize;
+
+use crate::{
+    prelude::{bresenham_line, AStar, Connectivity, Heuristic, Tile},
+    IntExtent2, IntVector2,
+};
+
+use super::{MapBuilder, Room};
+
+/// How a corridor between two rooms is drawn.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CorridorStyle {
+    /// A straight line between the centers of the rooms.
+    Straight,
+    /// A horizontal and a vertical segment, in random order.
+    #[default]
+    LShaped,
+    /// A random walk that drifts towards the target room.
+    Drunken,
+    /// The shortest path that doesn't cross any other room.
+    AStar,
+}
+
+impl CorridorStyle {
+    /// The cells of a corridor from the center of `from` to the center of `to`. `rooms` are
+    /// the rooms of the map, which the `AStar` style goes around; when there is no way around
+    /// them it falls back to an L-shaped corridor.
+    pub fn path<T: Tile>(
+        &self,
+        map_builder: &mut MapBuilder<T>,
+        from: &Room,
+        to: &Room,
+        rooms: &[Room],
+    ) -> Vec<IntVector2> {
+        let start = from.center();
+        let end = to.center();
+        let extent = map_builder.extent;
+
+        match self {
+            CorridorStyle::Straight => bresenham_line(start, end),
+            CorridorStyle::LShaped => l_shaped_path(map_builder.rng(), start, end),
+            CorridorStyle::Drunken => drunken_path(map_builder.rng(), &extent, start, end),
+            CorridorStyle::AStar => {
+                let from_cells: HashSet<IntVector2> = from.cells().into_iter().collect();
+                let to_cells: HashSet<IntVector2> = to.cells().into_iter().collect();
+                let blocked: HashSet<IntVector2> = rooms
+                    .iter()
+                    .flat_map(|room| room.cells())
+                    .filter(|cell| !from_cells.contains(cell) && !to_cells.contains(cell))
+                    .collect();
+
+                AStar::new()
+                    .with_connectivity(Connectivity::Four)
+                    .with_heuristic(Heuristic::Manhattan)
+                    .search(start, end, |pos| {
+                        (extent.contains(pos.x, pos.y) && !blocked.contains(&pos)).then_some(1.)
+                    })
+                    .unwrap_or_else(|| l_shaped_path(map_builder.rng(), start, end))
+            }
+        }
+    }
+}
+
+/// A horizontal and a vertical segment from `start` to `end`; which one comes first is
+/// chosen at random.
+pub fn l_shaped_path(rng: &mut impl Rng, start: IntVector2, end: IntVector2) -> Vec<IntVector2> {
+    let corner = if rng.gen_bool(0.5) {
+        IntVector2::new(end.x, start.y)
+    } else {
+        IntVector2::new(start.x, end.y)
+    };
+
+    let mut cells = Vec::<IntVector2>::new();
+    for (from, to) in [(start, corner), (corner, end)] {
+        let step = (to - from).signum();
+        let mut pos = from;
+        while pos != to {
+            cells.push(pos);
+            pos += step;
+        }
+    }
+    cells.push(end);
+
+    cells
+}
+
+/// A random walk from `start` to `end` that stays inside `extent`. Most steps get closer to
+/// `end`; if it takes too long the walk is completed with an L-shaped corridor.
+fn drunken_path(
+    rng: &mut impl Rng,
+    extent: &IntExtent2,
+    start: IntVector2,
+    end: IntVector2,
+) -> Vec<IntVector2> {
+    const DIRECTIONS: [IntVector2; 4] = [
+        IntVector2::new(0, -1),
+        IntVector2::new(1, 0),
+        IntVector2::new(0, 1),
+        IntVector2::new(-1, 0),
+    ];
+
+    let max_steps = 10 * ((end - start).abs().element_sum() + 1);
+    let mut cells = vec![start];
+    let mut pos = start;
+    for _ in 0..max_steps {
+        if pos == end {
+            return cells;
+        }
+
+        let towards = [
+            IntVector2::new((end.x - pos.x).signum(), 0),
+            IntVector2::new(0, (end.y - pos.y).signum()),
+        ];
+        let direction = if rng.gen_bool(0.6) {
+            *towards
+                .iter()
+                .filter(|direction| **direction != IntVector2::ZERO)
+                .collect::<Vec<_>>()
+                .choose(rng)
+                .unwrap()
+        } else {
+            DIRECTIONS.choose(rng).unwrap()
+        };
+
+        let next = pos + *direction;
+        if extent.contains(next.x, next.y) {
+            pos = next;
+            cells.push(pos);
+        }
+    }
+
+    cells.extend(l_shaped_path(rng, pos, end).into_iter().skip(1));
+    cells
+}
+
+/// Sets `tile` on `path`, making it `width` cells wide.
+pub fn carve_corridor<T: Tile>(
+    map_builder: &mut MapBuilder<T>,
+    path: &[IntVector2],
+    width: u32,
+    tile: &T,
+) {
+    let width = width.max(1) as i32;
+    let from = -(width - 1) / 2;
+    let to = from + width;
+    for cell in path {
+        for dx in from..to {
+            for dy in from..to {
+                map_builder.map.set(cell.x + dx, cell.y + dy, tile.clone());
+            }
+        }
+    }
+}
+
+/// Chooses which rooms to connect: the minimum spanning tree of the room centers, so that every
+/// room is reachable, plus some extra edges to make loops. Each of the edges between a room and
+/// its three nearest rooms that isn't already in the tree is added with probability
+/// `extra_edges`. The returned pairs are indices into `rooms`.
+pub fn room_connections(
+    rng: &mut impl Rng,
+    rooms: &[Room],
+    extra_edges: f64,
+) -> Vec<(usize, usize)> {
+    let distance = |a: usize, b: usize| {
+        let delta = rooms[a].center() - rooms[b].center();
+        delta.x * delta.x + delta.y * delta.y
+    };
+
+    // Prim's algorithm
+    let mut edges = Vec::<(usize, usize)>::new();
+    let mut in_tree = vec![false; rooms.len()];
+    let mut best: Vec<Option<(i32, usize)>> = vec![None; rooms.len()];
+    let mut current = 0;
+    for _ in 1..rooms.len() {
+        in_tree[current] = true;
+        for other in (0..rooms.len()).filter(|other| !in_tree[*other]) {
+            let d = distance(current, other);
+            if best[other].is_none_or(|(best_d, _)| d < best_d) {
+                best[other] = Some((d, current));
+            }
+        }
+
+        let (next, (_, parent)) = (0..rooms.len())
+            .filter(|room| !in_tree[*room])
+            .filter_map(|room| best[room].map(|b| (room, b)))
+            .min_by_key(|(room, (d, _))| (*d, *room))
+            .unwrap();
+        edges.push((parent.min(next), parent.max(next)));
+        current = next;
+    }
+
+    let mut candidates = Vec::<(usize, usize)>::new();
+    for room in 0..rooms.len() {
+        let mut others: Vec<usize> = (0..rooms.len()).filter(|other| *other != room).collect();
+        others.sort_by_key(|other| (distance(room, *other), *other));
+        for other in others.into_iter().take(3) {
+            let edge = (room.min(other), room.max(other));
+            if !edges.contains(&edge) && !candidates.contains(&edge) {
+                candidates.push(edge);
+            }
+        }
+    }
+    for edge in candidates {
+        if rng.gen_bool(extra_edges.clamp(0., 1.)) {
+            edges.push(edge);
+        }
+    }
+
+    edges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{prelude::Plane, test_utils::TestTile, Dimension2};
+
+    fn map_builder() -> MapBuilder<TestTile> {
+        let mut map_builder =
+            MapBuilder::<TestTile>::new(IntExtent2::new(0, 0, 60, 30)).with_seed(1);
+        map_builder.add_tile("floor".to_owned(), TestTile::Floor);
+        map_builder.add_tile("wall".to_owned(), TestTile::Wall);
+        map_builder
+    }
+
+    #[test]
+    fn test_corridor_styles() {
+        let rooms = vec![
+            Room::new(IntVector2::new(2, 2), Dimension2::new(6, 6)),
+            Room::new(IntVector2::new(22, 2), Dimension2::new(8, 26)),
+            Room::new(IntVector2::new(45, 20), Dimension2::new(8, 6)),
+        ];
+
+        for style in [
+            CorridorStyle::Straight,
+            CorridorStyle::LShaped,
+            CorridorStyle::Drunken,
+            CorridorStyle::AStar,
+        ] {
+            let mut map_builder = map_builder();
+            let path = style.path(&mut map_builder, &rooms[0], &rooms[2], &rooms);
+            assert_eq!(path.first(), Some(&rooms[0].center()));
+            assert_eq!(path.last(), Some(&rooms[2].center()));
+            assert!(path
+                .iter()
+                .all(|cell| map_builder.extent.contains(cell.x, cell.y)));
+
+            if style == CorridorStyle::AStar {
+                // goes around the room in the middle
+                let middle = rooms[1].cells();
+                assert!(path.iter().all(|cell| !middle.contains(cell)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_corridor_width() {
+        let mut map_builder = map_builder();
+        let path = l_shaped_path(
+            map_builder.rng(),
+            IntVector2::new(10, 10),
+            IntVector2::new(20, 10),
+        );
+        carve_corridor(&mut map_builder, &path, 3, &TestTile::Floor);
+
+        let grid = map_builder.map.read();
+        for y in 8..13 {
+            let expected = (9..12).contains(&y);
+            assert_eq!(grid.at(IntVector2::new(15, y)).is_some(), expected);
+        }
+    }
+
+    #[test]
+    fn test_room_connections() {
+        let rooms: Vec<Room> = (0..8)
+            .map(|i| {
+                Room::new(
+                    IntVector2::new(i * 7 % 50, i * 13 % 40),
+                    Dimension2::new(5, 5),
+                )
+            })
+            .collect();
+        let mut map_builder = map_builder();
+
+        let tree = room_connections(map_builder.rng(), &rooms, 0.);
+        assert_eq!(tree.len(), rooms.len() - 1);
+        // every room is reached by the tree
+        let mut reached = HashSet::from([0]);
+        while reached.len() < rooms.len() {
+            let before = reached.len();
+            for (a, b) in tree.iter() {
+                if reached.contains(a) || reached.contains(b) {
+                    reached.insert(*a);
+                    reached.insert(*b);
+                }
+            }
+            assert!(reached.len() > before);
+        }
+
+        let with_loops = room_connections(map_builder.rng(), &rooms, 1.);
+        assert!(with_loops.len() > tree.len());
+        assert_eq!(with_loops[..tree.len()], tree[..]);
+    }
+}
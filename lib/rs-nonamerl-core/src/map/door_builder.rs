@@ -0,0 +1,135 @@
+use std::collections::HashSet;
+
+use rand::Rng;
+
+use crate::{prelude::Tile, IntVector2};
+
+use super::{MapBuilder, MapBuilderAlgorithm};
+
+/// A building step that puts doors where corridors go through the walls of `MapBuilder::rooms`.
+///
+/// A door goes on the walkable cells of `Room::border_cells` that are chokepoints: walkable on
+/// two opposite sides and blocked on the other two. Corridors wider than a cell have no
+/// chokepoint, so they get no door.
+#[derive(Debug, Clone)]
+pub struct DoorBuilder<T>
+where
+    T: Tile,
+{
+    door_tile: String,
+    /// The chance of every chokepoint to get a door.
+    probability: f64,
+    _marker: std::marker::PhantomData<T>,
+}
+
+impl<T> DoorBuilder<T>
+where
+    T: Tile,
+{
+    pub fn new() -> Self {
+        Self {
+            door_tile: "door".to_owned(),
+            probability: 1.,
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    pub fn with_tile(mut self, door_tile: &str) -> Self {
+        self.door_tile = door_tile.to_owned();
+        self
+    }
+
+    pub fn with_probability(mut self, probability: f64) -> Self {
+        self.probability = probability.clamp(0., 1.);
+        self
+    }
+}
+
+impl<T: Tile> Default for DoorBuilder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Tile> MapBuilderAlgorithm<T> for DoorBuilder<T> {
+    fn build<'a>(&self, map_builder: &'a mut MapBuilder<T>) -> &'a mut MapBuilder<T> {
+        let door = map_builder.tile(&self.door_tile);
+        let walkable = |map_builder: &MapBuilder<T>, x: i32, y: i32| {
+            map_builder
+                .map
+                .with_tile(IntVector2::new(x, y), |tile| tile.is_walkable())
+                .unwrap_or(false)
+        };
+
+        let mut checked = HashSet::new();
+        for room in map_builder.rooms.clone().iter() {
+            for cell in room.border_cells() {
+                if !checked.insert(cell) || !walkable(map_builder, cell.x, cell.y) {
+                    continue;
+                }
+                let horizontal = walkable(map_builder, cell.x - 1, cell.y)
+                    && walkable(map_builder, cell.x + 1, cell.y);
+                let vertical = walkable(map_builder, cell.x, cell.y - 1)
+                    && walkable(map_builder, cell.x, cell.y + 1);
+                let blocked_horizontal = !walkable(map_builder, cell.x - 1, cell.y)
+                    && !walkable(map_builder, cell.x + 1, cell.y);
+                let blocked_vertical = !walkable(map_builder, cell.x, cell.y - 1)
+                    && !walkable(map_builder, cell.x, cell.y + 1);
+                let chokepoint =
+                    (horizontal && blocked_vertical) || (vertical && blocked_horizontal);
+
+                if chokepoint && map_builder.rng().gen_bool(self.probability) {
+                    map_builder.map.set(cell.x, cell.y, door.clone());
+                }
+            }
+        }
+
+        map_builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        prelude::Walkable,
+        test_utils::{map_from_rows, TestTile},
+        Dimension2, IntExtent2,
+    };
+
+    use super::super::Room;
+
+    #[test]
+    fn test_doors() {
+        // the corridor on the right goes through the wall, the one at the bottom is too wide
+        let rows = [
+            "#######", //
+            "#.....#",
+            "#........",
+            "#.....#",
+            "###..##",
+            "   ..  ",
+        ];
+        let mut map_builder = MapBuilder::<TestTile>::new(IntExtent2::new(0, 0, 9, 6));
+        map_builder.add_tile("door".to_owned(), TestTile::Door { open: false });
+        map_builder.map = map_from_rows(&rows);
+        map_builder
+            .rooms
+            .push(Room::new(IntVector2::new(0, 0), Dimension2::new(7, 5)));
+
+        map_builder.build_step(&DoorBuilder::new());
+        assert_eq!(
+            map_builder.map.get(6, 2),
+            Some(TestTile::Door { open: false })
+        );
+        assert_eq!(map_builder.map.get(3, 4), Some(TestTile::Floor));
+        assert_eq!(map_builder.map.get(4, 4), Some(TestTile::Floor));
+
+        // doors block the way until opened
+        assert_eq!(map_builder.map.is_open(IntVector2::new(6, 2)), Some(false));
+        assert!(!map_builder.map.get(6, 2).unwrap().is_walkable());
+        map_builder.map.set_open(IntVector2::new(6, 2), true);
+        assert!(map_builder.map.get(6, 2).unwrap().is_walkable());
+        assert_eq!(map_builder.map.is_open(IntVector2::new(3, 4)), None);
+    }
+}
@@ -0,0 +1,233 @@
+use crate::{prelude::Tile, IntVector2};
+
+use super::GameMap;
+
+/// A cell written by a building step, with what it held before and after. `None` is an empty
+/// cell.
+#[derive(Debug, Clone)]
+pub struct TileChange<T> {
+    pub position: IntVector2,
+    pub before: Option<T>,
+    pub after: Option<T>,
+}
+
+/// The cells a building step changed, or a part of them, see
+/// [`BuildHistory::with_tiles_per_frame`].
+#[derive(Debug, Clone)]
+pub struct HistoryFrame<T> {
+    /// The name of the type of the step, e.g. `RoomBuilder`.
+    pub step: String,
+    /// The index of the step among the steps of the map, frames of the same step share it.
+    pub step_index: usize,
+    /// The changes in the order the step made them.
+    pub changes: Vec<TileChange<T>>,
+}
+
+/// What every [`MapBuilder::build_step`](super::MapBuilder::build_step) changed in the map, to
+/// replay the building of a map step by step.
+///
+/// Only the tiles written with [`GameMap::set`] and [`GameMap::remove`] are recorded, which
+/// is how the building steps of this module write the map.
+#[derive(Debug, Clone, Default)]
+pub struct BuildHistory<T> {
+    frames: Vec<HistoryFrame<T>>,
+    steps: usize,
+    tiles_per_frame: Option<usize>,
+}
+
+impl<T: Tile> BuildHistory<T> {
+    /// A history with one frame per step.
+    pub fn new() -> Self {
+        Self {
+            frames: Vec::new(),
+            steps: 0,
+            tiles_per_frame: None,
+        }
+    }
+
+    /// Splits the changes of a step into frames of `tiles_per_frame` changes, to watch a step
+    /// at work.
+    pub fn with_tiles_per_frame(mut self, tiles_per_frame: usize) -> Self {
+        self.tiles_per_frame = Some(tiles_per_frame.max(1));
+        self
+    }
+
+    pub fn frames(&self) -> &[HistoryFrame<T>] {
+        &self.frames
+    }
+
+    pub fn frame(&self, frame: usize) -> &HistoryFrame<T> {
+        &self.frames[frame]
+    }
+
+    /// The number of frames.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// The number of recorded steps, frames can be fewer when steps changed nothing.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Adds the changes of a step. A step that changed nothing gets no frame.
+    pub(super) fn record(&mut self, step: &str, changes: Vec<TileChange<T>>) {
+        let step_index = self.steps;
+        self.steps += 1;
+
+        let mut changes = changes.into_iter().peekable();
+        while changes.peek().is_some() {
+            let frame_changes = match self.tiles_per_frame {
+                Some(tiles_per_frame) => changes.by_ref().take(tiles_per_frame).collect(),
+                None => changes.by_ref().collect(),
+            };
+            self.frames.push(HistoryFrame {
+                step: step.to_owned(),
+                step_index,
+                changes: frame_changes,
+            });
+        }
+    }
+
+    /// Writes the changes of `frame` to `map`, which must hold the tiles of the frames before.
+    pub fn apply(&self, frame: usize, map: &GameMap<T>) {
+        for change in self.frames[frame].changes.iter() {
+            Self::write(map, change.position, &change.after);
+        }
+    }
+
+    /// Undoes the changes of `frame` in `map`, which must hold the tiles up to `frame`.
+    pub fn revert(&self, frame: usize, map: &GameMap<T>) {
+        for change in self.frames[frame].changes.iter().rev() {
+            Self::write(map, change.position, &change.before);
+        }
+    }
+
+    /// The map as it was after the first `frames` frames, starting from an empty map.
+    pub fn replay(&self, frames: usize) -> GameMap<T> {
+        let map = GameMap::new();
+        for frame in 0..frames.min(self.len()) {
+            self.apply(frame, &map);
+        }
+        map
+    }
+
+    /// Every change of the cell at `position`, with the index of its frame: to find which
+    /// step filled a corridor back in.
+    pub fn changes_at(
+        &self,
+        position: IntVector2,
+    ) -> impl Iterator<Item = (usize, &TileChange<T>)> + '_ {
+        self.frames
+            .iter()
+            .enumerate()
+            .flat_map(move |(index, frame)| {
+                frame
+                    .changes
+                    .iter()
+                    .filter(move |change| change.position == position)
+                    .map(move |change| (index, change))
+            })
+    }
+
+    fn write(map: &GameMap<T>, position: IntVector2, tile: &Option<T>) {
+        match tile {
+            Some(tile) => map.set(position.x, position.y, tile.clone()),
+            None => {
+                map.remove(position);
+            }
+        }
+    }
+}
+
+/// The name of a building step type, without its module path and generic parameters.
+pub(super) fn step_name<A: ?Sized>() -> String {
+    let name = std::any::type_name::<A>();
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name).to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        prelude::{FillWithFloorBuilderAlgo, MapBuilder, RoomBuilder},
+        test_utils::TestTile,
+        IntExtent2,
+    };
+
+    fn build(history: BuildHistory<TestTile>) -> MapBuilder<TestTile> {
+        let extent = IntExtent2::new(0, 0, 60, 40);
+        let mut map_builder = MapBuilder::<TestTile>::new(extent)
+            .with_seed(7)
+            .with_history(history);
+        map_builder.add_tile("floor".to_owned(), TestTile::Floor);
+        map_builder.add_tile("wall".to_owned(), TestTile::Wall);
+        map_builder
+            .build_step(&FillWithFloorBuilderAlgo::new(
+                IntExtent2::new(0, 0, 10, 10),
+                "wall",
+            ))
+            .build_step(&RoomBuilder::new());
+        map_builder
+    }
+
+    fn assert_same_tiles(a: &GameMap<TestTile>, b: &GameMap<TestTile>) {
+        assert_eq!(a.len(), b.len());
+        for (position, tile) in a.read().iter() {
+            assert_eq!(b.get_position(position), Some(*tile));
+        }
+    }
+
+    #[test]
+    fn test_history_steps() {
+        let map_builder = build(BuildHistory::new());
+        let history = map_builder.history.as_ref().unwrap();
+        assert_eq!(history.steps(), 2);
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.frame(0).step, "FillWithFloorBuilderAlgo");
+        assert_eq!(history.frame(0).changes.len(), 100);
+        assert_eq!(history.frame(1).step, "RoomBuilder");
+        assert_eq!(history.frame(1).step_index, 1);
+
+        assert_same_tiles(&history.replay(history.len()), &map_builder.map);
+
+        // undoing the rooms leaves the walls of the first step
+        let map = history.replay(history.len());
+        history.revert(1, &map);
+        assert_same_tiles(&map, &history.replay(1));
+        assert_eq!(map.len(), 100);
+        history.revert(0, &map);
+        assert!(map.is_empty());
+
+        let position = history.frame(1).changes[0].position;
+        let changes: Vec<usize> = history
+            .changes_at(position)
+            .map(|(frame, _)| frame)
+            .collect();
+        assert_eq!(changes.last(), Some(&1));
+    }
+
+    #[test]
+    fn test_history_tiles_per_frame() {
+        let whole = build(BuildHistory::new());
+        let split = build(BuildHistory::new().with_tiles_per_frame(30));
+        let history = split.history.as_ref().unwrap();
+
+        // 100 walls in 4 frames, then the rooms
+        assert_eq!(history.steps(), 2);
+        assert!(history.frames()[..4]
+            .iter()
+            .all(|frame| frame.step_index == 0));
+        assert_eq!(history.frame(3).changes.len(), 10);
+        assert!(history.frames()[4..]
+            .iter()
+            .all(|frame| frame.step_index == 1 && frame.changes.len() <= 30));
+
+        assert_same_tiles(&history.replay(history.len()), &whole.map);
+    }
+}
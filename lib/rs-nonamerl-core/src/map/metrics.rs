@@ -0,0 +1,302 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::prelude::{AStar, Connectivity, Tile};
+
+use super::{MapBuilder, Regions};
+
+/// Numbers describing a built map, to tune the building steps with more than a look at the
+/// result.
+///
+/// Tiles that can be opened count as walkable: a closed door doesn't cut a level in two.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MapMetrics {
+    pub seed: u64,
+    pub depth: u32,
+    /// The number of `MapBuilder::rooms`.
+    pub rooms: usize,
+    /// The fraction of the cells of the extent that are walkable.
+    pub walkable_fraction: f64,
+    /// The number of connected walkable regions.
+    pub regions: usize,
+    /// The fraction of the walkable cells that belong to the largest region.
+    pub largest_region_share: f64,
+    /// The walkable cells with a single walkable neighbor.
+    pub dead_ends: usize,
+    /// The average number of steps between the centers of the rooms joined by a corridor,
+    /// `None` when no corridor could be walked.
+    pub average_room_path: Option<f64>,
+    /// See `MapBuilder::failed_placements`.
+    pub failed_placements: usize,
+}
+
+impl MapMetrics {
+    pub fn measure<T: Tile>(map_builder: &MapBuilder<T>) -> Self {
+        let extent = map_builder.extent;
+        let passable = |tile: &T| tile.is_walkable() || tile.is_open().is_some();
+        let grid = map_builder.map.read();
+
+        let regions = Regions::label_with(&*grid, &extent, Connectivity::Four, passable);
+        let sizes = regions.sizes();
+        let walkable: usize = sizes.iter().sum();
+        let largest_region = sizes.iter().max().copied().unwrap_or(0);
+
+        let dead_ends = (0..regions.len())
+            .flat_map(|region| regions.cells(region).iter())
+            .filter(|cell| {
+                Connectivity::Four
+                    .offsets()
+                    .iter()
+                    .filter(|offset| regions.region_of(**cell + **offset).is_some())
+                    .count()
+                    == 1
+            })
+            .count();
+
+        let astar = AStar::new().with_connectivity(Connectivity::Four);
+        let paths: Vec<usize> = map_builder
+            .connections
+            .iter()
+            .filter_map(|connection| {
+                let from = map_builder.rooms.get(connection.from)?.center();
+                let to = map_builder.rooms.get(connection.to)?.center();
+                astar
+                    .find_path_with_cost(&*grid, from, to, |position, tile| {
+                        (extent.contains(position.x, position.y) && passable(tile))
+                            .then(|| tile.movement_cost())
+                    })
+                    .map(|path| path.len() - 1)
+            })
+            .collect();
+
+        let cells = extent.width() as usize * extent.height() as usize;
+        Self {
+            seed: map_builder.seed(),
+            depth: map_builder.depth(),
+            rooms: map_builder.rooms.len(),
+            walkable_fraction: walkable as f64 / cells.max(1) as f64,
+            regions: regions.len(),
+            largest_region_share: largest_region as f64 / walkable.max(1) as f64,
+            dead_ends,
+            average_room_path: (!paths.is_empty())
+                .then(|| paths.iter().sum::<usize>() as f64 / paths.len() as f64),
+            failed_placements: map_builder.failed_placements,
+        }
+    }
+}
+
+/// Reads a metric of a map, `None` when the map has no value for it.
+type Metric = fn(&MapMetrics) -> Option<f64>;
+
+/// The metrics of a report, by name, in the order of the columns of [`MetricsReport::to_csv`].
+const METRICS: [(&str, Metric); 8] = [
+    ("rooms", |metrics| Some(metrics.rooms as f64)),
+    ("walkable_fraction", |metrics| {
+        Some(metrics.walkable_fraction)
+    }),
+    ("regions", |metrics| Some(metrics.regions as f64)),
+    ("largest_region_share", |metrics| {
+        Some(metrics.largest_region_share)
+    }),
+    ("dead_ends", |metrics| Some(metrics.dead_ends as f64)),
+    ("average_room_path", |metrics| metrics.average_room_path),
+    ("failed_placements", |metrics| {
+        Some(metrics.failed_placements as f64)
+    }),
+    ("depth", |metrics| Some(metrics.depth as f64)),
+];
+
+/// How the values of a metric are spread over the maps of a [`MetricsReport`]. The
+/// percentiles are nearest-rank.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Distribution {
+    /// The number of maps with a value.
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub median: f64,
+    pub p10: f64,
+    pub p90: f64,
+}
+
+impl Distribution {
+    /// The distribution of `values`, `None` when there are none.
+    pub fn new(mut values: Vec<f64>) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(f64::total_cmp);
+        let percentile = |p: f64| {
+            let rank = (p * values.len() as f64).ceil() as usize;
+            values[rank.clamp(1, values.len()) - 1]
+        };
+
+        Some(Self {
+            count: values.len(),
+            min: values[0],
+            max: values[values.len() - 1],
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+            median: percentile(0.5),
+            p10: percentile(0.1),
+            p90: percentile(0.9),
+        })
+    }
+}
+
+/// The metrics of a batch of maps, usually built with the same steps and different seeds.
+#[derive(Debug, Clone, Default)]
+pub struct MetricsReport {
+    pub maps: Vec<MapMetrics>,
+}
+
+impl MetricsReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, metrics: MapMetrics) {
+        self.maps.push(metrics);
+    }
+
+    /// The distribution of every metric over the maps, by metric name.
+    pub fn summary(&self) -> BTreeMap<&'static str, Distribution> {
+        METRICS
+            .iter()
+            .filter_map(|(name, value)| {
+                Distribution::new(self.maps.iter().filter_map(value).collect())
+                    .map(|distribution| (*name, distribution))
+            })
+            .collect()
+    }
+
+    /// The metrics of every map and their [`MetricsReport::summary`].
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        #[derive(Serialize)]
+        struct Report<'a> {
+            summary: BTreeMap<&'static str, Distribution>,
+            maps: &'a [MapMetrics],
+        }
+
+        serde_json::to_string_pretty(&Report {
+            summary: self.summary(),
+            maps: &self.maps,
+        })
+    }
+
+    /// One line per map, with the seed and then the metrics. Missing values are left empty.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("seed");
+        for (name, _) in METRICS.iter() {
+            csv.push(',');
+            csv.push_str(name);
+        }
+        csv.push('\n');
+
+        for metrics in self.maps.iter() {
+            csv.push_str(&metrics.seed.to_string());
+            for (_, value) in METRICS.iter() {
+                csv.push(',');
+                if let Some(value) = value(metrics) {
+                    csv.push_str(&value.to_string());
+                }
+            }
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_utils::{map_from_rows, TestTile},
+        Dimension2, IntExtent2, IntVector2,
+    };
+
+    use super::super::{Room, RoomConnection};
+
+    #[test]
+    fn test_map_metrics() {
+        // two rooms joined by a corridor through a closed door, and a dead end going north
+        let rows = [
+            "   .      ",
+            "#####.####",
+            "#...#.#..#",
+            "#...#....#",
+            "#...+..#.#",
+            "##########",
+            "  ..      ",
+        ];
+        let mut map_builder =
+            MapBuilder::<TestTile>::new(IntExtent2::new(0, 0, 10, 7)).with_seed(9);
+        map_builder.map = map_from_rows(&rows);
+        map_builder.rooms = vec![
+            Room::new(IntVector2::new(0, 1), Dimension2::new(5, 5)),
+            Room::new(IntVector2::new(6, 1), Dimension2::new(4, 5)),
+        ];
+        map_builder.connections = vec![RoomConnection {
+            from: 0,
+            to: 1,
+            cells: Vec::new(),
+        }];
+        map_builder.failed_placements = 3;
+
+        let metrics = MapMetrics::measure(&map_builder);
+        assert_eq!(metrics.seed, 9);
+        assert_eq!(metrics.rooms, 2);
+        // the rooms through the door, the single cell at the top and the pair at the bottom
+        assert_eq!(metrics.regions, 3);
+        assert_eq!(metrics.walkable_fraction, 24. / 70.);
+        assert_eq!(metrics.largest_region_share, 21. / 24.);
+        // the corridor going north, the alcove of the room on the right and the pair at the bottom
+        assert_eq!(metrics.dead_ends, 4);
+        assert_eq!(metrics.average_room_path, Some(8.));
+        assert_eq!(metrics.failed_placements, 3);
+    }
+
+    #[test]
+    fn test_metrics_report() {
+        let metrics = |seed: u64, rooms: usize, average_room_path: Option<f64>| MapMetrics {
+            seed,
+            depth: 0,
+            rooms,
+            walkable_fraction: 0.5,
+            regions: 1,
+            largest_region_share: 1.,
+            dead_ends: 0,
+            average_room_path,
+            failed_placements: 0,
+        };
+        let mut report = MetricsReport::new();
+        for (seed, rooms) in [3, 1, 4, 1, 5, 9, 2, 6, 5, 3].into_iter().enumerate() {
+            report.add(metrics(seed as u64, rooms, None));
+        }
+        report.maps[0].average_room_path = Some(12.5);
+
+        let summary = report.summary();
+        let rooms = &summary["rooms"];
+        assert_eq!(rooms.count, 10);
+        assert_eq!((rooms.min, rooms.max, rooms.mean), (1., 9., 3.9));
+        assert_eq!((rooms.p10, rooms.median, rooms.p90), (1., 3., 6.));
+        assert_eq!(summary["average_room_path"].count, 1);
+
+        let csv = report.to_csv();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some(
+                "seed,rooms,walkable_fraction,regions,largest_region_share,dead_ends,\
+                 average_room_path,failed_placements,depth"
+            )
+        );
+        assert_eq!(lines.next(), Some("0,3,0.5,1,1,0,12.5,0,0"));
+        assert_eq!(lines.next(), Some("1,1,0.5,1,1,0,,0,0"));
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(json["maps"].as_array().unwrap().len(), 10);
+        assert_eq!(json["summary"]["rooms"]["median"], 3.);
+    }
+}
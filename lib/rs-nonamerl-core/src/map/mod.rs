@@ -1,36 +1,83 @@
-use std::sync::{Arc, RwLock};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
 
 use bevy_ecs::{prelude::Entity, system::Resource};
 
 use crate::{
-    prelude::LatticeGrid2D, prelude::Plane, tile::Tile, Dimension2, IntExtent2, IntVector2,
+    prelude::{AStar, Connectivity, DijkstraMap, FieldOfView, Plane, VisibilityMap},
+    tile::Tile,
+    Dimension2, IntExtent2, IntVector2,
 };
 
+mod ascii;
+mod biome_builder;
+mod bsp_builder;
 mod builder;
+mod cave_builder;
 mod command;
+mod connectivity;
+mod corridor;
+mod door_builder;
+mod history;
+mod metrics;
 mod room;
 mod room_builder;
+mod room_graph;
+mod save;
+mod stairs;
+mod storage;
+mod streaming;
+mod vault;
+mod wfc_builder;
 
 mod noise_builder;
+mod pipeline;
 
+pub use biome_builder::*;
+pub use bsp_builder::*;
 pub use builder::*;
+pub use cave_builder::*;
 pub use command::*;
+pub use connectivity::*;
+pub use corridor::*;
+pub use door_builder::*;
+pub use history::*;
+pub use metrics::*;
 pub use room::*;
 pub use room_builder::*;
+pub use room_graph::*;
+pub use save::*;
+pub use stairs::*;
+pub use storage::*;
+pub use streaming::*;
+pub use vault::*;
+pub use wfc_builder::*;
 
 pub use noise_builder::*;
+pub use pipeline::*;
 
 #[derive(Debug, Clone, Resource)]
 pub struct GameMap<T: Tile> {
-    pub grid: Arc<RwLock<LatticeGrid2D<T>>>,
+    pub grid: Arc<RwLock<GridStorage<T>>>,
     pub size: Dimension2,
+    /// The changes made by [`GameMap::set`] and [`GameMap::remove`], while a building step
+    /// with a [`BuildHistory`] runs.
+    journal: Option<Arc<Mutex<Vec<TileChange<T>>>>>,
 }
 
 impl<T: Tile> GameMap<T> {
     pub fn new() -> Self {
+        Self::with_storage(GridStorage::sparse())
+    }
+
+    /// Creates an empty map keeping its tiles in `storage`.
+    pub fn with_storage(storage: GridStorage<T>) -> Self {
         Self {
-            grid: Arc::new(RwLock::new(LatticeGrid2D::new())),
+            grid: Arc::new(RwLock::new(storage)),
             size: Dimension2::new(0, 0),
+            journal: None,
         }
     }
 
@@ -53,7 +100,82 @@ impl<T: Tile> GameMap<T> {
     }
 
     pub fn set(&self, x: i32, y: i32, tile: T) {
-        self.grid.write().unwrap().put(IntVector2::new(x, y), tile);
+        let position = IntVector2::new(x, y);
+        let mut grid = self.grid.write().unwrap();
+        if let Some(journal) = &self.journal {
+            journal.lock().unwrap().push(TileChange {
+                position,
+                before: grid.at(position).cloned(),
+                after: Some(tile.clone()),
+            });
+        }
+        grid.put(position, tile);
+    }
+
+    /// Empties a cell, returning its tile.
+    pub fn remove(&self, position: IntVector2) -> Option<T> {
+        let removed = self.grid.write().unwrap().remove(position);
+        if let Some(journal) = &self.journal {
+            journal.lock().unwrap().push(TileChange {
+                position,
+                before: removed.clone(),
+                after: None,
+            });
+        }
+        removed
+    }
+
+    /// Starts recording the changes of [`GameMap::set`] and [`GameMap::remove`].
+    pub(crate) fn start_journal(&mut self) {
+        self.journal = Some(Arc::new(Mutex::new(Vec::new())));
+    }
+
+    /// Stops recording, returning the changes since [`GameMap::start_journal`].
+    pub(crate) fn take_journal(&mut self) -> Vec<TileChange<T>> {
+        self.journal
+            .take()
+            .map(|journal| std::mem::take(&mut *journal.lock().unwrap()))
+            .unwrap_or_default()
+    }
+
+    /// Calls `f` with a reference to the tile at `position`, without cloning it.
+    pub fn with_tile<R>(&self, position: IntVector2, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.grid.read().unwrap().at(position).map(f)
+    }
+
+    /// Calls `f` with a mutable reference to the tile at `position`, modifying it in place.
+    pub fn with_tile_mut<R>(&self, position: IntVector2, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        self.grid.write().unwrap().at_mut(position).map(f)
+    }
+
+    /// Calls `f` for every tile of `extent`, holding the read lock once for the whole region.
+    pub fn for_each_in(&self, extent: &IntExtent2, mut f: impl FnMut(IntVector2, &T)) {
+        let grid = self.grid.read().unwrap();
+        for (position, tile) in grid.iter_region(extent) {
+            f(position, tile);
+        }
+    }
+
+    /// Calls `f` for every tile of `extent`, holding the write lock once for the whole region.
+    pub fn for_each_in_mut(&self, extent: &IntExtent2, mut f: impl FnMut(IntVector2, &mut T)) {
+        let mut grid = self.grid.write().unwrap();
+        for position in extent.iter() {
+            if let Some(tile) = grid.at_mut(position) {
+                f(position, tile);
+            }
+        }
+    }
+
+    /// Locks the map for reading. The guard can be used as a [`Plane`] to run several
+    /// queries under the same lock.
+    pub fn read(&self) -> RwLockReadGuard<'_, GridStorage<T>> {
+        self.grid.read().unwrap()
+    }
+
+    /// Locks the map for writing. The guard can be used as a [`Plane`] to modify several
+    /// tiles under the same lock.
+    pub fn write(&self) -> RwLockWriteGuard<'_, GridStorage<T>> {
+        self.grid.write().unwrap()
     }
 
     // pub fn size(&self) -> Dimension2 {
@@ -69,22 +191,14 @@ impl<T: Tile> GameMap<T> {
     }
 
     pub fn add_item(&self, position: IntVector2, item: Entity) {
-        if let Some(tile) = self.grid.write().unwrap().at_mut(position) {
-            tile.add_item(item);
-        }
+        self.with_tile_mut(position, |tile| tile.add_item(item));
     }
     pub fn items(&self, position: IntVector2) -> Option<Vec<Entity>> {
-        if let Some(tile) = self.grid.write().unwrap().at_mut(position) {
-            tile.items()
-        } else {
-            None
-        }
+        self.with_tile(position, |tile| tile.items()).flatten()
     }
 
-    fn remove_item(&self, position: IntVector2, item: Entity) {
-        if let Some(tile) = self.grid.write().unwrap().at_mut(position) {
-            tile.remove_item(item);
-        }
+    pub fn remove_item(&self, position: IntVector2, item: Entity) {
+        self.with_tile_mut(position, |tile| tile.remove_item(item));
     }
 
     pub fn iter_over_visible_tiles<'a>(&'a self, extent: &'a IntExtent2) -> MapVisibleTilesIter<T> {
@@ -92,19 +206,69 @@ impl<T: Tile> GameMap<T> {
     }
 
     pub fn set_visited(&self, position: IntVector2, visited: bool) {
-        if let Some(tile) = self.grid.write().unwrap().at_mut(position) {
-            tile.set_visited(visited);
-        }
+        self.with_tile_mut(position, |tile| tile.set_visited(visited));
     }
 
     pub fn set_visible(&self, position: IntVector2, visible: bool) {
-        if let Some(tile) = self.grid.write().unwrap().at_mut(position) {
-            tile.set_visible(visible);
-        }
+        self.with_tile_mut(position, |tile| tile.set_visible(visible));
     }
+
+    /// Whether the tile at `position` is open, `None` if there is no tile or it can't be opened.
+    pub fn is_open(&self, position: IntVector2) -> Option<bool> {
+        self.with_tile(position, |tile| tile.is_open()).flatten()
+    }
+
+    pub fn set_open(&self, position: IntVector2, open: bool) {
+        self.with_tile_mut(position, |tile| tile.set_open(open));
+    }
+
     pub fn line(&self, start: IntVector2, end: IntVector2) -> Vec<IntVector2> {
         self.grid.read().unwrap().line(start, end)
     }
+
+    /// Returns the cells visible from `origin`, holding the read lock for the whole computation.
+    pub fn compute_fov(&self, fov: &FieldOfView, origin: IntVector2) -> HashSet<IntVector2> {
+        fov.compute(&*self.grid.read().unwrap(), origin)
+    }
+
+    /// Same as [`GameMap::compute_fov`], but partially occluding tiles attenuate the view.
+    pub fn compute_visibility(&self, fov: &FieldOfView, origin: IntVector2) -> VisibilityMap {
+        fov.compute_with_intensity(&*self.grid.read().unwrap(), origin)
+    }
+
+    /// Finds a walkable path from `start` to `goal`, see [`AStar::find_path`].
+    pub fn find_path(
+        &self,
+        astar: &AStar,
+        start: IntVector2,
+        goal: IntVector2,
+    ) -> Option<Vec<IntVector2>> {
+        astar.find_path(&*self.grid.read().unwrap(), start, goal)
+    }
+
+    /// Computes the distance from `goals` with the settings of `dijkstra_map`, see
+    /// [`DijkstraMap::compute`].
+    pub fn dijkstra_map(&self, dijkstra_map: DijkstraMap, goals: &[IntVector2]) -> DijkstraMap {
+        dijkstra_map.compute(&*self.grid.read().unwrap(), goals)
+    }
+
+    /// Labels the connected regions of walkable tiles of `extent`, see [`Regions::label`].
+    pub fn regions(&self, extent: &IntExtent2, connectivity: Connectivity) -> Regions {
+        Regions::label(&*self.grid.read().unwrap(), extent, connectivity)
+    }
+
+    /// Returns the walkable cells of `extent` that have not been visited yet, e.g. the goals of
+    /// an auto-explore [`DijkstraMap`].
+    pub fn unvisited_cells(&self, extent: &IntExtent2) -> Vec<IntVector2> {
+        let grid = self.grid.read().unwrap();
+        extent
+            .iter()
+            .filter(|pos| {
+                grid.at(*pos)
+                    .is_some_and(|tile| tile.is_walkable() && !tile.is_visited())
+            })
+            .collect()
+    }
 }
 
 impl<T: Tile> Default for GameMap<T> {
@@ -159,3 +323,71 @@ impl<'a, T: Tile> Iterator for MapVisibleTilesIter<'a, T> {
         self.next()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{prelude::Openable, test_utils::TestTile};
+
+    fn maps() -> [GameMap<TestTile>; 2] {
+        [
+            GameMap::with_storage(GridStorage::sparse()),
+            GameMap::with_storage(GridStorage::chunked()),
+        ]
+    }
+
+    #[test]
+    fn test_tile_access_in_place() {
+        for map in maps() {
+            map.set(-3, 2, TestTile::Door { open: false });
+            map.set(40, -20, TestTile::Door { open: false });
+
+            assert_eq!(
+                map.with_tile(IntVector2::new(-3, 2), |tile| tile.is_open()),
+                Some(Some(false))
+            );
+            assert_eq!(map.with_tile(IntVector2::new(0, 0), |_| ()), None);
+
+            map.with_tile_mut(IntVector2::new(-3, 2), |tile| tile.set_open(true));
+            assert_eq!(map.get(-3, 2), Some(TestTile::Door { open: true }));
+            assert_eq!(map.with_tile_mut(IntVector2::new(0, 0), |_| ()), None);
+
+            // only the tiles of the extent are changed
+            map.for_each_in_mut(&IntExtent2::new(-5, 0, 10, 5), |_, tile| {
+                tile.set_open(false)
+            });
+            assert_eq!(map.get(-3, 2), Some(TestTile::Door { open: false }));
+            map.for_each_in_mut(&IntExtent2::new(30, -30, 20, 20), |_, tile| {
+                tile.set_open(true)
+            });
+            assert_eq!(map.get(40, -20), Some(TestTile::Door { open: true }));
+            assert_eq!(map.len(), 2);
+        }
+    }
+
+    #[test]
+    fn test_region_iteration() {
+        let extent = IntExtent2::new(-20, -20, 40, 40);
+        let region = IntExtent2::new(-18, -5, 30, 11);
+        for map in maps() {
+            for position in extent.iter() {
+                if (position.x + position.y).rem_euclid(3) == 0 {
+                    map.set(position.x, position.y, TestTile::Wall);
+                }
+            }
+
+            let mut visited = Vec::new();
+            map.for_each_in(&region, |position, tile| {
+                assert_eq!(*tile, TestTile::Wall);
+                visited.push(position);
+            });
+            visited.sort_by_key(|position| (position.y, position.x));
+
+            let expected: Vec<IntVector2> = region
+                .iter()
+                .filter(|position| (position.x + position.y).rem_euclid(3) == 0)
+                .collect();
+            assert_eq!(visited, expected);
+        }
+    }
+}
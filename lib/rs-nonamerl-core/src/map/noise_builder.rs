@@ -43,9 +43,7 @@ impl<T: Tile, N: NoiseFn<f64, 2>, F: Fn(i32, i32, f64) -> Option<String>> MapBui
                 let tile = (self.f)(x, y, value);
 
                 if let Some(tile_name) = tile {
-                    map_builder
-                        .map
-                        .set(x, y, map_builder.tiles[tile_name.as_str()].clone());
+                    map_builder.map.set(x, y, map_builder.tile(&tile_name));
                 }
             }
         }
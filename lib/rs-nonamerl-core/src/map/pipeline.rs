@@ -0,0 +1,990 @@
+use std::{collections::HashMap, fmt, rc::Rc};
+
+use noise::{Fbm, MultiFractal, Perlin};
+use rand::Rng;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{Map, Value};
+
+use crate::{prelude::Tile, IntExtent2, IntVector2};
+
+use super::{
+    BiomeBuilder, BiomeTable, BspBuilder, BuilderAlgoWithNoise, CaveBuilder, ConnectivityBuilder,
+    ConnectivityRepair, CorridorStyle, DoorBuilder, FillWithFloorBuilderAlgo, MapBuilder,
+    MapBuilderAlgorithm, RandomWalkBuilder, RoomBuilder, RoomShape, StairsBuilder, VaultBuilder,
+    VaultConfig, VaultPlacement, WfcBuilder, WfcSample,
+};
+
+/// A level recipe: the tiles, the seed and the building steps, usually loaded from a JSON file.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PipelineConfig {
+    /// The seed of the map, when missing the game picks one.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// The tiles the steps can use, by name: the value is the kind of tile, as understood by
+    /// the game, see [`PipelineConfig::add_tiles`].
+    #[serde(default)]
+    pub palette: HashMap<String, String>,
+    pub steps: Vec<PipelineStep>,
+}
+
+/// A building step of a [`PipelineConfig`].
+#[derive(Debug, Clone, Deserialize)]
+pub struct PipelineStep {
+    /// The name of the step in the [`BuilderRegistry`].
+    pub step: String,
+    /// The step is skipped on levels shallower than this.
+    #[serde(default)]
+    pub min_depth: Option<u32>,
+    /// The step is skipped on levels deeper than this.
+    #[serde(default)]
+    pub max_depth: Option<u32>,
+    /// Added to the numeric parameters of the step once per level of depth, e.g.
+    /// `{ "count": 1 }` places one more vault on every level.
+    #[serde(default)]
+    pub per_depth: Map<String, Value>,
+    /// The other fields, passed to the step.
+    #[serde(flatten)]
+    pub params: Map<String, Value>,
+}
+
+/// Why a [`PipelineConfig`] can't be built.
+#[derive(Debug)]
+pub enum PipelineError {
+    /// No step with this name is registered.
+    UnknownStep(String),
+    /// The game doesn't know this kind of tile.
+    UnknownTileKind(String),
+    /// The parameters of a step are wrong.
+    InvalidParams {
+        step: String,
+        error: serde_json::Error,
+    },
+    /// A file or a sample the parameters of a step point to can't be loaded.
+    InvalidResource {
+        step: String,
+        error: Box<dyn std::error::Error>,
+    },
+    /// A step uses a tile that is not added to the map builder, e.g. missing from the palette.
+    UnknownTile { step: String, tile: String },
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::UnknownStep(step) => write!(f, "unknown step {}", step),
+            PipelineError::UnknownTileKind(kind) => write!(f, "unknown tile kind {}", kind),
+            PipelineError::InvalidParams { step, error } => {
+                write!(f, "invalid parameters for step {}: {}", step, error)
+            }
+            PipelineError::InvalidResource { step, error } => {
+                write!(f, "invalid resource for step {}: {}", step, error)
+            }
+            PipelineError::UnknownTile { step, tile } => {
+                write!(f, "unknown tile {} in step {}", tile, step)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {}
+
+impl PipelineStep {
+    /// Whether the step runs on a level at `depth`.
+    pub fn runs_at(&self, depth: u32) -> bool {
+        self.min_depth.is_none_or(|min_depth| depth >= min_depth)
+            && self.max_depth.is_none_or(|max_depth| depth <= max_depth)
+    }
+
+    /// The parameters of the step on a level at `depth`, see [`PipelineStep::per_depth`].
+    pub fn params_at(&self, depth: u32) -> serde_json::Result<Map<String, Value>> {
+        let mut params = self.params.clone();
+        let zero = Value::from(0);
+        for (name, increment) in self.per_depth.iter() {
+            let value = params.get(name).unwrap_or(&zero);
+            let scaled = match (value.as_i64(), increment.as_i64()) {
+                (Some(value), Some(increment)) => Value::from(value + increment * depth as i64),
+                _ => match (value.as_f64(), increment.as_f64()) {
+                    (Some(value), Some(increment)) => Value::from(value + increment * depth as f64),
+                    _ => {
+                        return Err(serde::de::Error::custom(format!(
+                            "per_depth parameter {} is not a number",
+                            name
+                        )))
+                    }
+                },
+            };
+            params.insert(name.clone(), scaled);
+        }
+        Ok(params)
+    }
+}
+
+impl PipelineConfig {
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    pub fn from_file(config_path: &str) -> Self {
+        let config_content =
+            std::fs::read_to_string(config_path).expect("Failed to read pipeline file");
+        Self::from_json(&config_content).expect("Failed to parse pipeline file")
+    }
+
+    /// Adds the tiles of the palette to `map_builder`, `tile` turns a kind of tile into a tile.
+    pub fn add_tiles<T: Tile>(
+        &self,
+        map_builder: &mut MapBuilder<T>,
+        tile: impl Fn(&str) -> Option<T>,
+    ) -> Result<(), PipelineError> {
+        for (name, kind) in self.palette.iter() {
+            let tile = tile(kind).ok_or_else(|| PipelineError::UnknownTileKind(kind.clone()))?;
+            map_builder.add_tile(name.clone(), tile);
+        }
+        Ok(())
+    }
+}
+
+/// A step with its parameters, ready to run.
+type BuildStep<T> = Box<dyn Fn(&mut MapBuilder<T>)>;
+type StepFactory<T> = Box<dyn Fn(Value) -> Result<PreparedStep<T>, PipelineError>>;
+/// What a step loads from its parameters before running, see [`BuilderRegistry::register_with`].
+type LoadResult<R> = Result<R, Box<dyn std::error::Error>>;
+
+/// A [`BuildStep`] and the names of the tiles it uses.
+struct PreparedStep<T: Tile> {
+    tiles: Vec<String>,
+    run: BuildStep<T>,
+}
+
+/// Names the tiles a step puts on the map, for [`BuilderRegistry::build`] to check them
+/// against the tiles of the map builder before running the first step.
+///
+/// Implemented by the parameters of the steps, or by what [`BuilderRegistry::register_with`]
+/// loads from them.
+pub trait StepTiles {
+    /// The names of the tiles, none by default.
+    fn tiles(&self) -> Vec<&str> {
+        Vec::new()
+    }
+}
+
+/// Turns the steps of a [`PipelineConfig`] into `build_step` calls.
+///
+/// Every step has a name and a type for its parameters, deserialized from the fields of the
+/// step. [`BuilderRegistry::new`] knows the builders of this module, games can add their own
+/// with [`BuilderRegistry::register`] and [`BuilderRegistry::register_with`].
+pub struct BuilderRegistry<T: Tile> {
+    steps: HashMap<String, StepFactory<T>>,
+}
+
+impl<T: Tile> BuilderRegistry<T> {
+    /// A registry with the builders of this module.
+    pub fn new() -> Self {
+        let mut registry = Self::empty();
+        registry.register("fill", fill_step);
+        registry.register("noise", noise_step);
+        registry.register("random_walk", random_walk_step);
+        registry.register("rooms", rooms_step);
+        registry.register("bsp", bsp_step);
+        registry.register("cave", cave_step);
+        registry.register("connectivity", connectivity_step);
+        registry.register_with("vaults", load_vaults, run_step);
+        registry.register_with("wfc", load_wfc, wfc_step);
+        registry.register_with("biomes", load_biomes, biomes_step);
+        registry.register("stairs", stairs_step);
+        registry.register("doors", doors_step);
+        registry
+    }
+
+    /// A registry without any step.
+    pub fn empty() -> Self {
+        Self {
+            steps: HashMap::new(),
+        }
+    }
+
+    /// Registers the step `name`: `step` runs it with its parameters.
+    pub fn register<P, F>(&mut self, name: &str, step: F)
+    where
+        P: DeserializeOwned + StepTiles + 'static,
+        F: Fn(&P, &mut MapBuilder<T>) + 'static,
+    {
+        self.register_with(name, |params: P| Ok(params), step);
+    }
+
+    /// Registers the step `name` for steps that need files or checks beyond their parameters:
+    /// `load` turns the parameters into what `step` runs with, when the pipeline is checked,
+    /// and its errors are [`PipelineError::InvalidResource`].
+    pub fn register_with<P, R, L, F>(&mut self, name: &str, load: L, step: F)
+    where
+        P: DeserializeOwned + 'static,
+        R: StepTiles + 'static,
+        L: Fn(P) -> LoadResult<R> + 'static,
+        F: Fn(&R, &mut MapBuilder<T>) + 'static,
+    {
+        let name = name.to_owned();
+        let step = Rc::new(step);
+        self.steps.insert(
+            name.clone(),
+            Box::new(move |params| {
+                let params: P = serde_json::from_value(params).map_err(|error| {
+                    PipelineError::InvalidParams {
+                        step: name.clone(),
+                        error,
+                    }
+                })?;
+                let resource = load(params).map_err(|error| PipelineError::InvalidResource {
+                    step: name.clone(),
+                    error,
+                })?;
+                let tiles = resource.tiles().into_iter().map(str::to_owned).collect();
+                let step = step.clone();
+                Ok(PreparedStep {
+                    tiles,
+                    run: Box::new(move |map_builder: &mut MapBuilder<T>| {
+                        step(&resource, map_builder)
+                    }),
+                })
+            }),
+        );
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.steps.contains_key(name)
+    }
+
+    /// Runs the steps of `config` on `map_builder`, at the depth of `map_builder`. The
+    /// parameters of every step are checked, the files they point to loaded and the tiles they
+    /// use looked up, see [`StepTiles`], before running the first one, so a wrong pipeline
+    /// leaves the map untouched.
+    pub fn build(
+        &self,
+        config: &PipelineConfig,
+        map_builder: &mut MapBuilder<T>,
+    ) -> Result<(), PipelineError> {
+        let depth = map_builder.depth();
+        let steps = config
+            .steps
+            .iter()
+            .filter(|step| step.runs_at(depth))
+            .map(|step| {
+                let factory = self
+                    .steps
+                    .get(&step.step)
+                    .ok_or_else(|| PipelineError::UnknownStep(step.step.clone()))?;
+                let params =
+                    step.params_at(depth)
+                        .map_err(|error| PipelineError::InvalidParams {
+                            step: step.step.clone(),
+                            error,
+                        })?;
+                let prepared = factory(Value::Object(params))?;
+                if let Some(tile) = prepared
+                    .tiles
+                    .iter()
+                    .find(|tile| map_builder.get_tile(tile).is_none())
+                {
+                    return Err(PipelineError::UnknownTile {
+                        step: step.step.clone(),
+                        tile: tile.clone(),
+                    });
+                }
+                Ok(prepared.run)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for step in steps {
+            step(map_builder);
+        }
+        Ok(())
+    }
+}
+
+impl<T: Tile> Default for BuilderRegistry<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An extent written as `[x, y, width, height]`.
+type ExtentParam = (i32, i32, u32, u32);
+
+fn extent((x, y, width, height): ExtentParam) -> IntExtent2 {
+    IntExtent2::new(x, y, width, height)
+}
+
+/// The extent of a step, `MapBuilder::extent` when the step has none.
+fn step_extent<T: Tile>(param: Option<ExtentParam>, map_builder: &MapBuilder<T>) -> IntExtent2 {
+    param.map_or(map_builder.extent, extent)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FillParams {
+    #[serde(default)]
+    extent: Option<ExtentParam>,
+    tile: String,
+}
+
+impl StepTiles for FillParams {
+    fn tiles(&self) -> Vec<&str> {
+        vec![&self.tile]
+    }
+}
+
+fn fill_step<T: Tile>(params: &FillParams, map_builder: &mut MapBuilder<T>) {
+    let extent = step_extent(params.extent, map_builder);
+    map_builder.build_step(&FillWithFloorBuilderAlgo::new(extent, &params.tile));
+}
+
+/// Fractal noise: the cells where the noise is above `threshold` get `tile`, the others get
+/// `below`, or are left untouched.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NoiseParams {
+    #[serde(default)]
+    extent: Option<ExtentParam>,
+    tile: String,
+    #[serde(default)]
+    below: Option<String>,
+    #[serde(default)]
+    threshold: f64,
+    /// The seed of the noise, drawn from the map seed when missing.
+    #[serde(default)]
+    seed: Option<u32>,
+    #[serde(default)]
+    octaves: Option<usize>,
+    #[serde(default)]
+    frequency: Option<f64>,
+    #[serde(default)]
+    lacunarity: Option<f64>,
+    #[serde(default)]
+    persistence: Option<f64>,
+}
+
+impl StepTiles for NoiseParams {
+    fn tiles(&self) -> Vec<&str> {
+        let mut tiles = vec![self.tile.as_str()];
+        tiles.extend(self.below.as_deref());
+        tiles
+    }
+}
+
+fn noise_step<T: Tile>(params: &NoiseParams, map_builder: &mut MapBuilder<T>) {
+    let seed = params.seed.unwrap_or_else(|| map_builder.rng().gen());
+    let mut noise = Fbm::<Perlin>::new(seed);
+    if let Some(octaves) = params.octaves {
+        noise = noise.set_octaves(octaves);
+    }
+    if let Some(frequency) = params.frequency {
+        noise = noise.set_frequency(frequency);
+    }
+    if let Some(lacunarity) = params.lacunarity {
+        noise = noise.set_lacunarity(lacunarity);
+    }
+    if let Some(persistence) = params.persistence {
+        noise = noise.set_persistence(persistence);
+    }
+
+    let f = |_x: i32, _y: i32, value: f64| {
+        if value > params.threshold {
+            Some(params.tile.clone())
+        } else {
+            params.below.clone()
+        }
+    };
+    let extent = step_extent(params.extent, map_builder);
+    map_builder.build_step(&BuilderAlgoWithNoise::new(&noise, f, extent));
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RandomWalkParams {
+    start: (i32, i32),
+}
+
+impl StepTiles for RandomWalkParams {
+    fn tiles(&self) -> Vec<&str> {
+        vec!["floor"]
+    }
+}
+
+fn random_walk_step<T: Tile>(params: &RandomWalkParams, map_builder: &mut MapBuilder<T>) {
+    let (x, y) = params.start;
+    map_builder.build_step(&RandomWalkBuilder::new(IntVector2::new(x, y)));
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RoomsParams {
+    #[serde(default)]
+    max_rooms: Option<usize>,
+    #[serde(default)]
+    corridor_style: Option<CorridorStyle>,
+    #[serde(default)]
+    corridor_width: Option<u32>,
+    #[serde(default)]
+    extra_edges: Option<f64>,
+    #[serde(default)]
+    shapes: Option<Vec<(RoomShape, f64)>>,
+}
+
+impl StepTiles for RoomsParams {
+    fn tiles(&self) -> Vec<&str> {
+        vec!["floor", "wall"]
+    }
+}
+
+fn rooms_step<T: Tile>(params: &RoomsParams, map_builder: &mut MapBuilder<T>) {
+    let mut builder = RoomBuilder::new();
+    if let Some(max_rooms) = params.max_rooms {
+        builder = builder.with_max_rooms(max_rooms);
+    }
+    if let Some(corridor_style) = params.corridor_style {
+        builder = builder.with_corridor_style(corridor_style);
+    }
+    if let Some(corridor_width) = params.corridor_width {
+        builder = builder.with_corridor_width(corridor_width);
+    }
+    if let Some(extra_edges) = params.extra_edges {
+        builder = builder.with_extra_edges(extra_edges);
+    }
+    if let Some(shapes) = &params.shapes {
+        builder = builder.with_shapes(shapes.clone());
+    }
+    map_builder.build_step(&builder);
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BspParams {
+    #[serde(default)]
+    min_leaf_size: Option<u32>,
+    #[serde(default)]
+    min_room_size: Option<u32>,
+    #[serde(default)]
+    corridor_style: Option<CorridorStyle>,
+    #[serde(default)]
+    corridor_width: Option<u32>,
+    #[serde(default)]
+    shapes: Option<Vec<(RoomShape, f64)>>,
+}
+
+impl StepTiles for BspParams {
+    fn tiles(&self) -> Vec<&str> {
+        vec!["floor", "wall"]
+    }
+}
+
+fn bsp_step<T: Tile>(params: &BspParams, map_builder: &mut MapBuilder<T>) {
+    let mut builder = BspBuilder::new();
+    if let Some(min_leaf_size) = params.min_leaf_size {
+        builder = builder.with_min_leaf_size(min_leaf_size);
+    }
+    if let Some(min_room_size) = params.min_room_size {
+        builder = builder.with_min_room_size(min_room_size);
+    }
+    if let Some(corridor_style) = params.corridor_style {
+        builder = builder.with_corridor_style(corridor_style);
+    }
+    if let Some(corridor_width) = params.corridor_width {
+        builder = builder.with_corridor_width(corridor_width);
+    }
+    if let Some(shapes) = &params.shapes {
+        builder = builder.with_shapes(shapes.clone());
+    }
+    map_builder.build_step(&builder);
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CaveParams {
+    #[serde(default)]
+    extent: Option<ExtentParam>,
+    #[serde(default)]
+    fill_ratio: Option<f64>,
+    #[serde(default)]
+    birth_limit: Option<u8>,
+    #[serde(default)]
+    survival_limit: Option<u8>,
+    #[serde(default)]
+    iterations: Option<u32>,
+    #[serde(default = "floor_tile")]
+    floor: String,
+    #[serde(default = "wall_tile")]
+    wall: String,
+}
+
+impl StepTiles for CaveParams {
+    fn tiles(&self) -> Vec<&str> {
+        vec![&self.floor, &self.wall]
+    }
+}
+
+fn cave_step<T: Tile>(params: &CaveParams, map_builder: &mut MapBuilder<T>) {
+    let mut builder = CaveBuilder::new(step_extent(params.extent, map_builder))
+        .with_tiles(&params.floor, &params.wall);
+    if let Some(fill_ratio) = params.fill_ratio {
+        builder = builder.with_fill_ratio(fill_ratio);
+    }
+    if let Some(birth_limit) = params.birth_limit {
+        builder = builder.with_birth_limit(birth_limit);
+    }
+    if let Some(survival_limit) = params.survival_limit {
+        builder = builder.with_survival_limit(survival_limit);
+    }
+    if let Some(iterations) = params.iterations {
+        builder = builder.with_iterations(iterations);
+    }
+    map_builder.build_step(&builder);
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConnectivityParams {
+    #[serde(default)]
+    repair: ConnectivityRepair,
+    #[serde(default)]
+    dig_cost: Option<f32>,
+    #[serde(default = "floor_tile")]
+    floor: String,
+    #[serde(default = "wall_tile")]
+    wall: String,
+}
+
+impl StepTiles for ConnectivityParams {
+    fn tiles(&self) -> Vec<&str> {
+        match self.repair {
+            ConnectivityRepair::Cull => vec![&self.wall],
+            ConnectivityRepair::Tunnel => vec![&self.floor],
+        }
+    }
+}
+
+fn connectivity_step<T: Tile>(params: &ConnectivityParams, map_builder: &mut MapBuilder<T>) {
+    let mut builder =
+        ConnectivityBuilder::new(params.repair).with_tiles(&params.floor, &params.wall);
+    if let Some(dig_cost) = params.dig_cost {
+        builder = builder.with_dig_cost(dig_cost);
+    }
+    map_builder.build_step(&builder);
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VaultsParams {
+    /// The path of the vault file.
+    config: String,
+    #[serde(default)]
+    count: Option<usize>,
+    #[serde(default)]
+    placement: VaultPlacement,
+    #[serde(default)]
+    rotation: Option<bool>,
+    #[serde(default)]
+    mirroring: Option<bool>,
+}
+
+/// Runs a builder made by the `load` function of a step.
+fn run_step<T: Tile, A: MapBuilderAlgorithm<T>>(builder: &A, map_builder: &mut MapBuilder<T>) {
+    map_builder.build_step(builder);
+}
+
+/// The vaults using a tile that is not added to the map builder are skipped.
+impl<T: Tile> StepTiles for VaultBuilder<T> {}
+
+fn load_vaults<T: Tile>(params: VaultsParams) -> LoadResult<VaultBuilder<T>> {
+    let config = VaultConfig::from_file(&params.config)
+        .map_err(|error| format!("{}: {}", params.config, error))?;
+    let mut builder = VaultBuilder::new(config).with_placement(params.placement);
+    if let Some(count) = params.count {
+        builder = builder.with_count(count);
+    }
+    if let Some(rotation) = params.rotation {
+        builder = builder.with_rotation(rotation);
+    }
+    if let Some(mirroring) = params.mirroring {
+        builder = builder.with_mirroring(mirroring);
+    }
+    Ok(builder)
+}
+
+/// A wave function collapse step, the sample is written as rows of characters.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WfcParams {
+    sample: Vec<String>,
+    legend: HashMap<char, String>,
+    #[serde(default)]
+    extent: Option<ExtentParam>,
+    #[serde(default)]
+    pattern_size: Option<usize>,
+    #[serde(default)]
+    symmetry: Option<bool>,
+    #[serde(default)]
+    max_attempts: Option<u32>,
+}
+
+impl<T: Tile> StepTiles for (WfcParams, WfcBuilder<T>) {
+    fn tiles(&self) -> Vec<&str> {
+        self.0.legend.values().map(String::as_str).collect()
+    }
+}
+
+fn load_wfc<T: Tile>(params: WfcParams) -> LoadResult<(WfcParams, WfcBuilder<T>)> {
+    let rows: Vec<&str> = params.sample.iter().map(String::as_str).collect();
+    let legend: Vec<(char, &str)> = params
+        .legend
+        .iter()
+        .map(|(c, tile)| (*c, tile.as_str()))
+        .collect();
+    let mut builder = WfcBuilder::new(WfcSample::from_rows(&rows, &legend)?);
+    if let Some(area) = params.extent {
+        builder = builder.with_extent(extent(area));
+    }
+    if let Some(pattern_size) = params.pattern_size {
+        builder = builder.with_pattern_size(pattern_size);
+    }
+    if let Some(symmetry) = params.symmetry {
+        builder = builder.with_symmetry(symmetry);
+    }
+    if let Some(max_attempts) = params.max_attempts {
+        builder = builder.with_max_attempts(max_attempts);
+    }
+    Ok((params, builder))
+}
+
+fn wfc_step<T: Tile>((_, builder): &(WfcParams, WfcBuilder<T>), map_builder: &mut MapBuilder<T>) {
+    map_builder.build_step(builder);
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BiomesParams {
+    #[serde(default)]
+    extent: Option<ExtentParam>,
+    /// The path of the biome table.
+    table: String,
+    #[serde(default)]
+    noise_seed: Option<u32>,
+}
+
+impl StepTiles for (BiomesParams, BiomeTable) {
+    fn tiles(&self) -> Vec<&str> {
+        let biomes = self.1.biomes.iter();
+        biomes
+            .clone()
+            .map(|biome| biome.tile.as_str())
+            .chain(biomes.flat_map(|biome| {
+                biome
+                    .decorations
+                    .iter()
+                    .filter_map(|decoration| decoration.tile.as_deref())
+            }))
+            .collect()
+    }
+}
+
+fn load_biomes(params: BiomesParams) -> LoadResult<(BiomesParams, BiomeTable)> {
+    let table = BiomeTable::from_file(&params.table)
+        .map_err(|error| format!("{}: {}", params.table, error))?;
+    Ok((params, table))
+}
+
+fn biomes_step<T: Tile>(
+    (params, table): &(BiomesParams, BiomeTable),
+    map_builder: &mut MapBuilder<T>,
+) {
+    let mut builder = BiomeBuilder::new(step_extent(params.extent, map_builder), table.clone());
+    if let Some(noise_seed) = params.noise_seed {
+        builder = builder.with_noise_seed(noise_seed);
+    }
+    map_builder.build_step(&builder);
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StairsParams {
+    #[serde(default = "stairs_up_tile")]
+    up: String,
+    #[serde(default = "stairs_down_tile")]
+    down: String,
+    #[serde(default)]
+    start: Option<(i32, i32)>,
+}
+
+impl StepTiles for StairsParams {
+    fn tiles(&self) -> Vec<&str> {
+        vec![&self.up, &self.down]
+    }
+}
+
+fn stairs_step<T: Tile>(params: &StairsParams, map_builder: &mut MapBuilder<T>) {
+    let mut builder = StairsBuilder::new().with_tiles(&params.up, &params.down);
+    if let Some((x, y)) = params.start {
+        builder = builder.with_start(IntVector2::new(x, y));
+    }
+    map_builder.build_step(&builder);
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DoorsParams {
+    #[serde(default = "door_tile")]
+    tile: String,
+    #[serde(default)]
+    probability: Option<f64>,
+}
+
+impl StepTiles for DoorsParams {
+    fn tiles(&self) -> Vec<&str> {
+        vec![&self.tile]
+    }
+}
+
+fn doors_step<T: Tile>(params: &DoorsParams, map_builder: &mut MapBuilder<T>) {
+    let mut builder = DoorBuilder::new().with_tile(&params.tile);
+    if let Some(probability) = params.probability {
+        builder = builder.with_probability(probability);
+    }
+    map_builder.build_step(&builder);
+}
+
+fn floor_tile() -> String {
+    "floor".to_owned()
+}
+
+fn wall_tile() -> String {
+    "wall".to_owned()
+}
+
+fn door_tile() -> String {
+    "door".to_owned()
+}
+
+fn stairs_up_tile() -> String {
+    "stairs_up".to_owned()
+}
+
+fn stairs_down_tile() -> String {
+    "stairs_down".to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestTile;
+
+    const PIPELINE: &str = r#"{
+        "seed": 5,
+        "palette": { "floor": "floor", "wall": "wall" },
+        "steps": [
+            { "step": "fill", "extent": [0, 0, 40, 30], "tile": "wall" },
+            { "step": "bsp", "min_leaf_size": 10, "corridor_style": "straight" },
+            { "step": "connectivity", "repair": "cull" }
+        ]
+    }"#;
+
+    fn tile(kind: &str) -> Option<TestTile> {
+        match kind {
+            "floor" => Some(TestTile::Floor),
+            "wall" => Some(TestTile::Wall),
+            _ => None,
+        }
+    }
+
+    fn map_builder(config: &PipelineConfig) -> MapBuilder<TestTile> {
+        let mut map_builder = MapBuilder::<TestTile>::new(IntExtent2::new(0, 0, 40, 30))
+            .with_seed(config.seed.unwrap());
+        config.add_tiles(&mut map_builder, tile).unwrap();
+        map_builder
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let config = PipelineConfig::from_json(PIPELINE).unwrap();
+        assert_eq!(config.steps.len(), 3);
+
+        let mut a = map_builder(&config);
+        BuilderRegistry::new().build(&config, &mut a).unwrap();
+        assert!(a.rooms.len() >= 2);
+        assert!(a.connectivity_report.is_some());
+
+        // the same steps with the builders
+        let mut b = map_builder(&config);
+        b.build_step(&FillWithFloorBuilderAlgo::new(
+            IntExtent2::new(0, 0, 40, 30),
+            "wall",
+        ))
+        .build_step(
+            &BspBuilder::new()
+                .with_min_leaf_size(10)
+                .with_corridor_style(CorridorStyle::Straight),
+        )
+        .build_step(&ConnectivityBuilder::new(ConnectivityRepair::Cull));
+        assert!(a
+            .extent
+            .iter()
+            .all(|pos| a.map.get(pos.x, pos.y) == b.map.get(pos.x, pos.y)));
+    }
+
+    #[test]
+    fn test_pipeline_errors() {
+        let registry = BuilderRegistry::<TestTile>::new();
+        let mut config = PipelineConfig::from_json(PIPELINE).unwrap();
+        let mut map_builder = map_builder(&config);
+
+        config.steps[1].step = "dungeon".to_owned();
+        assert!(matches!(
+            registry.build(&config, &mut map_builder),
+            Err(PipelineError::UnknownStep(step)) if step == "dungeon"
+        ));
+
+        config.steps[1].step = "bsp".to_owned();
+        config.steps[1]
+            .params
+            .insert("min_leaf_size".to_owned(), Value::from("big"));
+        assert!(matches!(
+            registry.build(&config, &mut map_builder),
+            Err(PipelineError::InvalidParams { .. })
+        ));
+        // the first step didn't run either
+        assert!(map_builder.map.is_empty());
+
+        config.palette.insert("lava".to_owned(), "lava".to_owned());
+        assert!(config.add_tiles(&mut map_builder, tile).is_err());
+    }
+
+    #[test]
+    fn test_pipeline_resource_errors() {
+        let registry = BuilderRegistry::<TestTile>::new();
+        let mut config = PipelineConfig::from_json(PIPELINE).unwrap();
+        let mut map_builder = map_builder(&config);
+
+        // the files and the samples are loaded before the first step runs
+        let broken_steps = [
+            r#"{ "step": "vaults", "config": "missing/vaults.json" }"#,
+            r#"{ "step": "biomes", "extent": [0, 0, 40, 30], "table": "missing/biomes.json" }"#,
+            r##"{ "step": "wfc", "sample": [".#", "#?"], "legend": { ".": "floor", "#": "wall" } }"##,
+            r#"{ "step": "wfc", "sample": [".", ".."], "legend": { ".": "floor" } }"#,
+        ];
+        for json in broken_steps {
+            let broken: PipelineStep = serde_json::from_str(json).unwrap();
+            let name = broken.step.clone();
+            config.steps.push(broken);
+            assert!(matches!(
+                registry.build(&config, &mut map_builder),
+                Err(PipelineError::InvalidResource { step, .. }) if step == name
+            ));
+            assert!(map_builder.map.is_empty());
+            config.steps.pop();
+        }
+
+        // a tile missing from the palette, the fill step before it doesn't run
+        config.steps.push(
+            serde_json::from_str(r#"{ "step": "cave", "extent": [0, 0, 40, 30], "wall": "wal" }"#)
+                .unwrap(),
+        );
+        assert!(matches!(
+            registry.build(&config, &mut map_builder),
+            Err(PipelineError::UnknownTile { step, tile }) if step == "cave" && tile == "wal"
+        ));
+        assert!(map_builder.map.is_empty());
+    }
+
+    #[test]
+    fn test_pipeline_default_extent() {
+        let config = PipelineConfig::from_json(
+            r#"{ "steps": [{ "step": "fill", "tile": "wall" }, { "step": "cave" }] }"#,
+        )
+        .unwrap();
+        let mut map_builder = MapBuilder::<TestTile>::new(IntExtent2::new(-5, 5, 30, 20));
+        map_builder.add_tile("floor".to_owned(), TestTile::Floor);
+        map_builder.add_tile("wall".to_owned(), TestTile::Wall);
+        BuilderRegistry::new()
+            .build(&config, &mut map_builder)
+            .unwrap();
+        // the steps without an extent cover the extent of the map builder
+        assert_eq!(map_builder.map.len(), 600);
+        assert!(map_builder
+            .map
+            .read()
+            .iter()
+            .all(|(position, _)| map_builder.extent.contains(position.x, position.y)));
+    }
+
+    #[test]
+    fn test_pipeline_depth() {
+        let config = PipelineConfig::from_json(
+            r#"{
+                "steps": [
+                    { "step": "fill", "extent": [0, 0, 10, 10], "tile": "wall", "max_depth": 0 },
+                    {
+                        "step": "fill",
+                        "extent": [0, 0, 2, 1],
+                        "tile": "floor",
+                        "min_depth": 1,
+                        "per_depth": { "extent": 1 }
+                    },
+                    { "step": "rooms", "max_rooms": 2, "per_depth": { "max_rooms": 3 } }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert!(config.steps[0].runs_at(0));
+        assert!(!config.steps[0].runs_at(1));
+        assert!(!config.steps[1].runs_at(0));
+        assert_eq!(config.steps[2].params_at(2).unwrap()["max_rooms"], 8);
+        // only numbers scale
+        assert!(config.steps[1].params_at(1).is_err());
+
+        let mut map_builder = MapBuilder::<TestTile>::new(IntExtent2::new(0, 0, 10, 10));
+        map_builder.add_tile("wall".to_owned(), TestTile::Wall);
+        let mut steps = config.clone();
+        steps.steps.truncate(1);
+        BuilderRegistry::new()
+            .build(&steps, &mut map_builder)
+            .unwrap();
+        assert_eq!(map_builder.map.len(), 100);
+
+        let mut deeper = MapBuilder::<TestTile>::new(IntExtent2::new(0, 0, 10, 10)).with_depth(1);
+        BuilderRegistry::new().build(&steps, &mut deeper).unwrap();
+        assert!(deeper.map.is_empty());
+        assert!(matches!(
+            BuilderRegistry::new().build(&config, &mut deeper),
+            Err(PipelineError::InvalidParams { step, .. }) if step == "fill"
+        ));
+    }
+
+    #[test]
+    fn test_register_step() {
+        #[derive(Deserialize)]
+        struct DotParams {
+            at: (i32, i32),
+        }
+
+        impl StepTiles for DotParams {
+            fn tiles(&self) -> Vec<&str> {
+                vec!["floor"]
+            }
+        }
+
+        let mut registry = BuilderRegistry::<TestTile>::empty();
+        registry.register("dot", |params: &DotParams, map_builder| {
+            let floor = *map_builder.get_tile("floor").unwrap();
+            map_builder.map.set(params.at.0, params.at.1, floor);
+        });
+        assert!(registry.contains("dot"));
+        assert!(!registry.contains("bsp"));
+
+        let config = PipelineConfig::from_json(
+            r#"{ "palette": { "floor": "floor" }, "steps": [{ "step": "dot", "at": [3, 4] }] }"#,
+        )
+        .unwrap();
+        let mut map_builder = MapBuilder::<TestTile>::new(IntExtent2::new(0, 0, 10, 10));
+        config.add_tiles(&mut map_builder, tile).unwrap();
+        registry.build(&config, &mut map_builder).unwrap();
+        assert_eq!(map_builder.map.get(3, 4), Some(TestTile::Floor));
+        assert_eq!(map_builder.map.len(), 1);
+    }
+}
@@ -1,21 +1,273 @@
-use std::ops::Range;
+use std::{collections::HashSet, ops::Range, sync::Arc};
 
 use bevy_ecs::system::Resource;
-use rand::Rng;
+use rand::{seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
 
 use crate::{Dimension2, IntVector2};
-#[derive(Clone, Debug, Resource)]
+
+const NEIGHBORS: [IntVector2; 8] = [
+    IntVector2::new(-1, -1),
+    IntVector2::new(0, -1),
+    IntVector2::new(1, -1),
+    IntVector2::new(-1, 0),
+    IntVector2::new(1, 0),
+    IntVector2::new(-1, 1),
+    IntVector2::new(0, 1),
+    IntVector2::new(1, 1),
+];
+
+/// The outline of a [`Room`] inside its bounding box.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoomShape {
+    #[default]
+    Rectangle,
+    /// The ellipse inscribed in the bounding box.
+    Circle,
+    /// Two bars crossing in the middle of the bounding box.
+    Cross,
+    /// The bounding box without one of its quarters, chosen at random.
+    LShape,
+    /// An irregular cave-like blob, grown with a cellular automaton.
+    Blob,
+}
+
+impl RoomShape {
+    /// Picks one of the `(shape, weight)` pairs. A single shape is returned without drawing
+    /// from `rng`.
+    pub fn choose(rng: &mut impl Rng, shapes: &[(RoomShape, f64)]) -> RoomShape {
+        match shapes {
+            [] => RoomShape::Rectangle,
+            [(shape, _)] => *shape,
+            _ => shapes
+                .choose_weighted(rng, |(_, weight)| *weight)
+                .map(|(shape, _)| *shape)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The cells of the shape in a box of `width` by `height` cells, from its top left cell.
+    fn cells(&self, rng: &mut impl Rng, width: i32, height: i32) -> HashSet<IntVector2> {
+        let cells = (0..width).flat_map(|x| (0..height).map(move |y| IntVector2::new(x, y)));
+        let in_ellipse = |cell: &IntVector2| {
+            let dx = (cell.x as f64 - (width - 1) as f64 / 2.) / (width as f64 / 2.);
+            let dy = (cell.y as f64 - (height - 1) as f64 / 2.) / (height as f64 / 2.);
+            dx * dx + dy * dy <= 1.
+        };
+
+        match self {
+            RoomShape::Rectangle => cells.collect(),
+            RoomShape::Circle => cells.filter(in_ellipse).collect(),
+            RoomShape::Cross => cells
+                .filter(|cell| {
+                    (width / 3..width - width / 3).contains(&cell.x)
+                        || (height / 3..height - height / 3).contains(&cell.y)
+                })
+                .collect(),
+            RoomShape::LShape => {
+                let (left, top) = (rng.gen_bool(0.5), rng.gen_bool(0.5));
+                cells
+                    .filter(|cell| (cell.x < width / 2) != left || (cell.y < height / 2) != top)
+                    .collect()
+            }
+            RoomShape::Blob => {
+                let mut alive: HashSet<IntVector2> = cells
+                    .filter(in_ellipse)
+                    .filter(|_| rng.gen_bool(0.6))
+                    .collect();
+                for _ in 0..4 {
+                    alive = (0..width)
+                        .flat_map(|x| (0..height).map(move |y| IntVector2::new(x, y)))
+                        .filter(in_ellipse)
+                        .filter(|cell| {
+                            let count = NEIGHBORS
+                                .iter()
+                                .filter(|offset| alive.contains(&(*cell + **offset)))
+                                .count()
+                                + alive.contains(cell) as usize;
+                            count >= 5
+                        })
+                        .collect();
+                }
+                alive
+            }
+        }
+    }
+}
+
+/// A room of the map: its bounding box and the shape of the room inside it.
+#[derive(Clone, Debug, Resource, Serialize, Deserialize)]
+#[serde(from = "RoomData", into = "RoomData")]
 pub struct Room {
     pos: IntVector2,
     size: Dimension2,
+    shape: RoomShape,
+    /// The cells of the rooms that are not rectangles, walls included.
+    mask: Option<Arc<HashSet<IntVector2>>>,
+}
+
+/// How a [`Room`] is saved: the cells of its shape can't be generated again without the
+/// random generator that made them.
+#[derive(Serialize, Deserialize)]
+struct RoomData {
+    pos: [i32; 2],
+    size: [u32; 2],
+    #[serde(default)]
+    shape: RoomShape,
+    #[serde(default)]
+    mask: Option<Vec<[i32; 2]>>,
+}
+
+impl From<Room> for RoomData {
+    fn from(room: Room) -> Self {
+        let mask = room.mask.as_ref().map(|mask| {
+            let mut cells: Vec<[i32; 2]> = mask.iter().map(|cell| [cell.x, cell.y]).collect();
+            cells.sort_by_key(|[x, y]| (*y, *x));
+            cells
+        });
+        Self {
+            pos: [room.pos.x, room.pos.y],
+            size: [room.size.width(), room.size.height()],
+            shape: room.shape,
+            mask,
+        }
+    }
+}
+
+impl From<RoomData> for Room {
+    fn from(data: RoomData) -> Self {
+        let mask = data.mask.map(|cells| {
+            Arc::new(
+                cells
+                    .into_iter()
+                    .map(|[x, y]| IntVector2::new(x, y))
+                    .collect(),
+            )
+        });
+        Self {
+            pos: IntVector2::new(data.pos[0], data.pos[1]),
+            size: Dimension2::new(data.size[0], data.size[1]),
+            shape: data.shape,
+            mask,
+        }
+    }
 }
 
 impl Room {
     pub fn new(pos: IntVector2, size: Dimension2) -> Self {
-        Self { pos, size }
+        Self {
+            pos,
+            size,
+            shape: RoomShape::Rectangle,
+            mask: None,
+        }
+    }
+
+    /// A room of the given shape, fitting the bounding box `pos` and `size`. Only the largest
+    /// connected part of the inside of the shape is kept, with the walls around it. Boxes too
+    /// small for a shape, or shapes with no inside left, give a rectangle.
+    pub fn shaped(rng: &mut impl Rng, pos: IntVector2, size: Dimension2, shape: RoomShape) -> Self {
+        let (width, height) = (size.width() as i32, size.height() as i32);
+        if shape == RoomShape::Rectangle || width < 5 || height < 5 {
+            return Self::new(pos, size);
+        }
+
+        let cells: HashSet<IntVector2> = shape
+            .cells(rng, width, height)
+            .into_iter()
+            .map(|cell| cell + pos)
+            .collect();
+        // the inside of the room, in the order the cells are visited by `Room::cells`
+        let inside: Vec<IntVector2> = (pos.x..pos.x + width)
+            .flat_map(|x| (pos.y..pos.y + height).map(move |y| IntVector2::new(x, y)))
+            .filter(|cell| {
+                cells.contains(cell)
+                    && NEIGHBORS
+                        .iter()
+                        .all(|offset| cells.contains(&(*cell + *offset)))
+            })
+            .collect();
+
+        let inside_set: HashSet<IntVector2> = inside.iter().copied().collect();
+        let mut largest = HashSet::new();
+        let mut visited = HashSet::new();
+        for start in inside.iter() {
+            if !visited.insert(*start) {
+                continue;
+            }
+            let mut region = HashSet::from([*start]);
+            let mut stack = vec![*start];
+            while let Some(cell) = stack.pop() {
+                for offset in NEIGHBORS.iter() {
+                    let next = cell + *offset;
+                    if inside_set.contains(&next) && visited.insert(next) {
+                        region.insert(next);
+                        stack.push(next);
+                    }
+                }
+            }
+            if region.len() > largest.len() {
+                largest = region;
+            }
+        }
+        if largest.is_empty() {
+            return Self::new(pos, size);
+        }
+
+        let mask = largest
+            .iter()
+            .flat_map(|cell| NEIGHBORS.iter().map(move |offset| *cell + *offset))
+            .chain(largest.iter().copied())
+            .collect();
+        Self {
+            pos,
+            size,
+            shape,
+            mask: Some(Arc::new(mask)),
+        }
     }
 
+    pub fn shape(&self) -> RoomShape {
+        self.shape
+    }
+
+    /// Whether `cell` is part of the room, walls included.
+    pub fn contains(&self, cell: IntVector2) -> bool {
+        match &self.mask {
+            Some(mask) => mask.contains(&cell),
+            None => {
+                (self.pos.x..self.pos.x + self.size.width() as i32).contains(&cell.x)
+                    && (self.pos.y..self.pos.y + self.size.height() as i32).contains(&cell.y)
+            }
+        }
+    }
+
+    /// Whether `cell` is part of the room and not one of its walls.
+    fn is_interior(&self, cell: IntVector2) -> bool {
+        self.contains(cell) && NEIGHBORS.iter().all(|offset| self.contains(cell + *offset))
+    }
+
+    /// The top left cell of the room, walls included.
+    pub fn pos(&self) -> IntVector2 {
+        self.pos
+    }
+
+    /// The size of the room, walls included.
+    pub fn size(&self) -> Dimension2 {
+        self.size
+    }
+
+    /// The walls of the room: its cells next to a cell outside of it, diagonals included.
     pub fn border_cells(&self) -> Vec<IntVector2> {
+        if self.mask.is_some() {
+            return self
+                .cells()
+                .into_iter()
+                .filter(|cell| !self.is_interior(*cell))
+                .collect();
+        }
+
         let mut cells = Vec::<IntVector2>::new();
 
         for x in self.pos.x..self.pos.x + self.size.width() as i32 {
@@ -38,6 +290,14 @@ impl Room {
     }
 
     pub fn interior_cells(&self) -> Vec<IntVector2> {
+        if self.mask.is_some() {
+            return self
+                .cells()
+                .into_iter()
+                .filter(|cell| self.is_interior(*cell))
+                .collect();
+        }
+
         let mut cells = Vec::<IntVector2>::new();
 
         for x in self.pos.x + 1..self.pos.x + self.size.width() as i32 - 1 {
@@ -49,23 +309,45 @@ impl Room {
         cells
     }
 
+    /// Whether the rooms overlap or touch. Rectangles also intersect when they are one cell
+    /// apart.
     pub fn intersects(&self, other: &Room) -> bool {
-        self.pos.x <= other.pos.x + other.size.width() as i32
+        let boxes_intersect = self.pos.x <= other.pos.x + other.size.width() as i32
             && self.pos.x + self.size.width() as i32 >= other.pos.x
             && self.pos.y <= other.pos.y + other.size.height() as i32
-            && self.pos.y + self.size.height() as i32 >= other.pos.y
+            && self.pos.y + self.size.height() as i32 >= other.pos.y;
+        if !boxes_intersect || (self.mask.is_none() && other.mask.is_none()) {
+            return boxes_intersect;
+        }
+
+        self.cells().into_iter().any(|cell| {
+            other.contains(cell)
+                || NEIGHBORS
+                    .iter()
+                    .any(|offset| other.contains(cell + *offset))
+        })
     }
 
+    /// The middle of the room, always one of its interior cells.
     pub fn center(&self) -> IntVector2 {
-        IntVector2::new(
+        let center = IntVector2::new(
             self.pos.x + self.size.width() as i32 / 2,
             self.pos.y + self.size.height() as i32 / 2,
-        )
-    }
+        );
+        if self.mask.is_none() || self.is_interior(center) {
+            return center;
+        }
 
-    pub fn create_random(width: i32, height: i32) -> Self {
-        let mut rng = rand::thread_rng();
+        self.interior_cells()
+            .into_iter()
+            .min_by_key(|cell| {
+                let delta = *cell - center;
+                delta.x * delta.x + delta.y * delta.y
+            })
+            .unwrap_or(center)
+    }
 
+    pub fn create_random(rng: &mut impl Rng, width: i32, height: i32) -> Self {
         let x = rng.gen_range(0..width);
         let y = rng.gen_range(0..height);
 
@@ -76,12 +358,11 @@ impl Room {
     }
 
     pub fn create_random_in_rect(
+        rng: &mut impl Rng,
         top_left: IntVector2,
         size: Dimension2,
         room_size_range: (Range<u16>, Range<u16>),
     ) -> Self {
-        let mut rng = rand::thread_rng();
-
         let x = rng.gen_range(top_left.x..top_left.x + size.width() as i32);
         let y = rng.gen_range(top_left.y..top_left.y + size.height() as i32);
 
@@ -91,15 +372,115 @@ impl Room {
         Self::new(IntVector2::new(x, y), Dimension2::new(w, h))
     }
 
+    /// The cells of the room, walls included.
     pub fn cells(&self) -> Vec<IntVector2> {
         let mut cells = Vec::<IntVector2>::new();
 
         for x in self.pos.x..self.pos.x + self.size.width() as i32 {
             for y in self.pos.y..self.pos.y + self.size.height() as i32 {
-                cells.push(IntVector2::new(x, y));
+                let cell = IntVector2::new(x, y);
+                if self.mask.is_none() || self.contains(cell) {
+                    cells.push(cell);
+                }
             }
         }
 
         cells
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    const SHAPES: [RoomShape; 5] = [
+        RoomShape::Rectangle,
+        RoomShape::Circle,
+        RoomShape::Cross,
+        RoomShape::LShape,
+        RoomShape::Blob,
+    ];
+
+    #[test]
+    fn test_rectangle() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let room = Room::shaped(
+            &mut rng,
+            IntVector2::new(2, 3),
+            Dimension2::new(6, 5),
+            RoomShape::Rectangle,
+        );
+
+        let border: HashSet<IntVector2> = room.border_cells().into_iter().collect();
+        assert_eq!(room.cells().len(), 30);
+        assert_eq!(border.len(), 18);
+        assert_eq!(room.interior_cells().len(), 12);
+        assert_eq!(room.center(), IntVector2::new(5, 5));
+        assert!(room.contains(IntVector2::new(7, 7)));
+        assert!(!room.contains(IntVector2::new(8, 7)));
+    }
+
+    #[test]
+    fn test_shapes() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for shape in SHAPES {
+            let room = Room::shaped(
+                &mut rng,
+                IntVector2::new(-4, 10),
+                Dimension2::new(15, 12),
+                shape,
+            );
+            assert_eq!(room.shape(), shape);
+
+            let cells = room.cells();
+            let border: HashSet<IntVector2> = room.border_cells().into_iter().collect();
+            let interior = room.interior_cells();
+            assert!(!interior.is_empty(), "{:?} has no interior", shape);
+            assert_eq!(border.len() + interior.len(), cells.len());
+            assert!(cells.iter().all(|cell| room.contains(*cell)));
+            assert!(interior.contains(&room.center()));
+
+            // the walls close the room
+            for cell in interior.iter() {
+                for offset in NEIGHBORS {
+                    assert!(room.contains(*cell + offset));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_shaped_intersects() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let circle = Room::shaped(
+            &mut rng,
+            IntVector2::new(0, 0),
+            Dimension2::new(12, 12),
+            RoomShape::Circle,
+        );
+        // in the corner of the bounding box of the circle, outside of the circle itself
+        let corner = Room::new(IntVector2::new(11, 11), Dimension2::new(4, 4));
+        let overlapping = Room::new(IntVector2::new(5, 5), Dimension2::new(4, 4));
+
+        assert!(!circle.intersects(&corner));
+        assert!(!corner.intersects(&circle));
+        assert!(circle.intersects(&overlapping));
+        assert!(overlapping.intersects(&circle));
+    }
+
+    #[test]
+    fn test_choose() {
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(RoomShape::choose(&mut rng, &[]), RoomShape::Rectangle);
+        assert_eq!(
+            RoomShape::choose(&mut rng, &[(RoomShape::Cross, 2.)]),
+            RoomShape::Cross
+        );
+        let shapes = [(RoomShape::Circle, 1.), (RoomShape::Blob, 0.)];
+        for _ in 0..20 {
+            assert_eq!(RoomShape::choose(&mut rng, &shapes), RoomShape::Circle);
+        }
+    }
+}
@@ -1,14 +1,25 @@
-use rand::Rng;
-
 use crate::{prelude::Tile, Dimension2, IntVector2};
 
-use super::{MapBuilder, MapBuilderAlgorithm, Room};
+use super::{
+    carve_corridor, room_connections, CorridorStyle, MapBuilder, MapBuilderAlgorithm, Room,
+    RoomConnection, RoomShape,
+};
 
+/// Places non overlapping rooms at random and connects them with corridors.
+///
+/// The rooms are joined by their minimum spanning tree, so that every room is reachable, plus
+/// some extra edges that create loops, see [`room_connections`].
 #[derive(Debug, Clone)]
 pub struct RoomBuilder<T>
 where
     T: Tile,
 {
+    max_rooms: usize,
+    corridor_style: CorridorStyle,
+    corridor_width: u32,
+    extra_edges: f64,
+    /// The shapes of the rooms, with their weights.
+    shapes: Vec<(RoomShape, f64)>,
     _marker: std::marker::PhantomData<T>,
 }
 
@@ -18,66 +29,41 @@ where
 {
     pub fn new() -> Self {
         Self {
+            max_rooms: 4,
+            corridor_style: CorridorStyle::default(),
+            corridor_width: 1,
+            extra_edges: 0.15,
+            shapes: vec![(RoomShape::Rectangle, 1.)],
             _marker: std::marker::PhantomData,
         }
     }
 
-    fn connect_rooms(
-        &self,
-        map_builder: &MapBuilder<T>,
-        room1: &Room,
-        room2: &Room,
-    ) -> Vec<IntVector2> {
-        // let mut rng = rand::thread_rng();
-
-        let start = room1.center();
-        let end = room2.center();
+    pub fn with_max_rooms(mut self, max_rooms: usize) -> Self {
+        self.max_rooms = max_rooms;
+        self
+    }
 
-        let path = map_builder.map.line(start, end);
+    pub fn with_corridor_style(mut self, corridor_style: CorridorStyle) -> Self {
+        self.corridor_style = corridor_style;
+        self
+    }
 
-        let mut corridor = Vec::<IntVector2>::new();
+    pub fn with_corridor_width(mut self, corridor_width: u32) -> Self {
+        self.corridor_width = corridor_width;
+        self
+    }
 
-        for cell in path {
-            // if map_builder.map.get(cell.x(), cell.y()).is_some() {
-            corridor.push(cell);
-            // }
-        }
+    /// Sets the probability of adding a connection between nearby rooms that are already
+    /// connected through other rooms.
+    pub fn with_extra_edges(mut self, extra_edges: f64) -> Self {
+        self.extra_edges = extra_edges;
+        self
+    }
 
-        // let mut corridor = Room::new(room1.center(), Dimension2D::new(0, 0));
-
-        // let mut current_pos = room1.center();
-
-        // while current_pos != room2.center() {
-        //     let mut next_pos = current_pos;
-
-        //     let direction = if rng.gen_range(0..2) == 1 {
-        //         if current_pos.x() < room2.center().x() {
-        //             "right"
-        //         } else {
-        //             "left"
-        //         }
-        //     } else {
-        //         if current_pos.y() < room2.center().y() {
-        //             "down"
-        //         } else {
-        //             "up"
-        //         }
-        //     };
-
-        //     match *direction {
-        //         "up" => *next_pos.y_mut() -= 1,
-        //         "down" => *next_pos.y_mut() += 1,
-        //         "left" => *next_pos.x_mut() -= 1,
-        //         "right" => *next_pos.x_mut() += 1,
-        //         _ => {}
-        //     }
-
-        //     corridor = Room::new(current_pos, Dimension2D::new(1, 1));
-
-        //     current_pos = next_pos;
-        // }
-
-        corridor
+    /// Sets the shapes of the rooms, each picked with a probability proportional to its weight.
+    pub fn with_shapes(mut self, shapes: Vec<(RoomShape, f64)>) -> Self {
+        self.shapes = shapes;
+        self
     }
 }
 
@@ -106,12 +92,16 @@ impl<T: Tile> MapBuilderAlgorithm<T> for RoomBuilder<T> {
 
         let mut attempts = 0;
         let map_extent = map_builder.extent;
-        while rooms.len() < 4 && attempts < 1000 {
+        while rooms.len() < self.max_rooms && attempts < 1000 {
             let candidate = Room::create_random_in_rect(
+                map_builder.rng(),
                 IntVector2::new(map_extent.left(), map_extent.top()),
                 Dimension2::new(map_extent.width(), map_extent.height()),
                 (10..25, 10..25),
             );
+            let shape = RoomShape::choose(map_builder.rng(), &self.shapes);
+            let candidate =
+                Room::shaped(map_builder.rng(), candidate.pos(), candidate.size(), shape);
             // for room in rooms.iter() {
             //     println!("candidate: {:?}", candidate);
             //     if candidate.intersects(room) {
@@ -125,10 +115,11 @@ impl<T: Tile> MapBuilderAlgorithm<T> for RoomBuilder<T> {
                 attempts += 1;
             }
         }
+        map_builder.failed_placements += attempts;
 
         rooms.iter().for_each(|room| {
             room.cells().iter().for_each(|pos| {
-                let tile = map_builder.tiles.get("floor").unwrap().clone();
+                let tile = map_builder.tile("floor");
 
                 map_builder.map.set(pos.x, pos.y, tile);
             });
@@ -136,7 +127,7 @@ impl<T: Tile> MapBuilderAlgorithm<T> for RoomBuilder<T> {
 
         rooms.iter().for_each(|room| {
             room.border_cells().iter().for_each(|pos| {
-                let tile = map_builder.tiles.get("wall").unwrap().clone();
+                let tile = map_builder.tile("wall");
 
                 map_builder.map.set(pos.x, pos.y, tile);
             });
@@ -144,24 +135,22 @@ impl<T: Tile> MapBuilderAlgorithm<T> for RoomBuilder<T> {
         });
 
         //connect rooms
-        for i in 0..rooms.len() - 1 {
-            let room1 = &rooms[i];
-            let room2 = &rooms[i + 1];
-
-            let corridor = self.connect_rooms(map_builder, room1, room2);
-            // let mut rng = rand::thread_rng();
-            for cell in corridor.iter() {
-                let tile = map_builder.tiles.get("floor").unwrap().clone();
-                // let wall_tile = map_builder.tiles.get("wall").unwrap().clone();
-                let offset = 3; //rng.gen_range(0..5);
-                for i in -offset + 1..offset {
-                    map_builder.map.set(cell.x + i, cell.y, tile.clone());
-                }
-
-                // map_builder.map.set(cell.x(), cell.y(), tile);
-            }
+        let floor = map_builder.tile("floor");
+        let edges = room_connections(map_builder.rng(), &rooms, self.extra_edges);
+        let mut connections = Vec::<RoomConnection>::new();
+        for (from, to) in edges {
+            let corridor = self
+                .corridor_style
+                .path(map_builder, &rooms[from], &rooms[to], &rooms);
+            carve_corridor(map_builder, &corridor, self.corridor_width, &floor);
+            connections.push(RoomConnection {
+                from,
+                to,
+                cells: corridor,
+            });
         }
         map_builder.rooms = rooms;
+        map_builder.connections = connections;
 
         map_builder
     }
@@ -0,0 +1,247 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::IntVector2;
+
+use super::Room;
+
+/// The role of a room in the level, see [`RoomGraph::tag_rooms`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum RoomTag {
+    /// Where the player enters the level.
+    Start,
+    /// The room farthest from the start.
+    Exit,
+    /// A dead end that is worth the detour.
+    Treasure,
+    /// The biggest room far from the start, guarding the way out.
+    Boss,
+    /// A room with a single connection.
+    DeadEnd,
+}
+
+/// A corridor joining two rooms, by their index in the room list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoomConnection {
+    pub from: usize,
+    pub to: usize,
+    /// The cells carved for the corridor.
+    pub cells: Vec<IntVector2>,
+}
+
+/// The rooms of a level and the corridors connecting them.
+#[derive(Debug, Clone, Default)]
+pub struct RoomGraph {
+    rooms: Vec<Room>,
+    connections: Vec<RoomConnection>,
+    tags: Vec<HashSet<RoomTag>>,
+}
+
+impl RoomGraph {
+    pub fn new(rooms: Vec<Room>, connections: Vec<RoomConnection>) -> Self {
+        let tags = vec![HashSet::new(); rooms.len()];
+        Self {
+            rooms,
+            connections,
+            tags,
+        }
+    }
+
+    pub fn rooms(&self) -> &[Room] {
+        &self.rooms
+    }
+
+    pub fn room(&self, room: usize) -> &Room {
+        &self.rooms[room]
+    }
+
+    pub fn connections(&self) -> &[RoomConnection] {
+        &self.connections
+    }
+
+    pub fn len(&self) -> usize {
+        self.rooms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rooms.is_empty()
+    }
+
+    /// The rooms connected to `room`, in ascending order.
+    pub fn neighbors(&self, room: usize) -> Vec<usize> {
+        let mut neighbors: Vec<usize> = self
+            .connections
+            .iter()
+            .filter_map(|connection| match (connection.from, connection.to) {
+                (from, to) if from == room && to != room => Some(to),
+                (from, to) if to == room && from != room => Some(from),
+                _ => None,
+            })
+            .collect();
+        neighbors.sort();
+        neighbors.dedup();
+        neighbors
+    }
+
+    /// The number of rooms connected to `room`.
+    pub fn degree(&self, room: usize) -> usize {
+        self.neighbors(room).len()
+    }
+
+    /// The number of corridors between `start` and every room, `None` for unreachable rooms.
+    pub fn distances_from(&self, start: usize) -> Vec<Option<usize>> {
+        let mut distances = vec![None; self.rooms.len()];
+        let mut queue = VecDeque::from([start]);
+        distances[start] = Some(0);
+        while let Some(room) = queue.pop_front() {
+            let distance = distances[room].unwrap();
+            for neighbor in self.neighbors(room) {
+                if distances[neighbor].is_none() {
+                    distances[neighbor] = Some(distance + 1);
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        distances
+    }
+
+    pub fn tags(&self, room: usize) -> &HashSet<RoomTag> {
+        &self.tags[room]
+    }
+
+    pub fn has_tag(&self, room: usize, tag: RoomTag) -> bool {
+        self.tags[room].contains(&tag)
+    }
+
+    pub fn add_tag(&mut self, room: usize, tag: RoomTag) {
+        self.tags[room].insert(tag);
+    }
+
+    /// The rooms with `tag`, in ascending order.
+    pub fn rooms_with_tag(&self, tag: RoomTag) -> impl Iterator<Item = usize> + '_ {
+        (0..self.rooms.len()).filter(move |room| self.has_tag(*room, tag))
+    }
+
+    /// The room whose center is nearest to `position`.
+    pub fn nearest_room(&self, position: IntVector2) -> Option<usize> {
+        (0..self.rooms.len()).min_by_key(|room| {
+            let delta = self.rooms[*room].center() - position;
+            delta.x * delta.x + delta.y * delta.y
+        })
+    }
+
+    /// Tags the rooms by looking at the graph, starting from `start`:
+    ///
+    /// * `start` is the [`RoomTag::Start`];
+    /// * the reachable room farthest from the start is the [`RoomTag::Exit`];
+    /// * rooms with a single connection are [`RoomTag::DeadEnd`]s;
+    /// * the biggest room at least halfway between start and exit is the [`RoomTag::Boss`];
+    /// * the dead ends that are not the start, the exit or the boss are
+    ///   [`RoomTag::Treasure`] rooms.
+    ///
+    /// Previous tags are cleared.
+    pub fn tag_rooms(&mut self, start: usize) {
+        self.tags.iter_mut().for_each(|tags| tags.clear());
+        if self.rooms.is_empty() {
+            return;
+        }
+
+        let distances = self.distances_from(start);
+        self.add_tag(start, RoomTag::Start);
+
+        let exit = (0..self.rooms.len())
+            .filter_map(|room| distances[room].map(|distance| (room, distance)))
+            .max_by_key(|(room, distance)| (*distance, std::cmp::Reverse(*room)))
+            .map(|(room, _)| room)
+            .filter(|room| *room != start);
+        if let Some(exit) = exit {
+            self.add_tag(exit, RoomTag::Exit);
+        }
+
+        for room in 0..self.rooms.len() {
+            if self.degree(room) == 1 {
+                self.add_tag(room, RoomTag::DeadEnd);
+            }
+        }
+
+        let exit_distance = exit.and_then(|exit| distances[exit]).unwrap_or(0);
+        let boss = (0..self.rooms.len())
+            .filter(|room| *room != start && Some(*room) != exit)
+            .filter(|room| distances[*room].is_some_and(|d| 2 * d >= exit_distance))
+            .max_by_key(|room| {
+                let size = self.rooms[*room].size();
+                (size.width() * size.height(), std::cmp::Reverse(*room))
+            });
+        if let Some(boss) = boss {
+            self.add_tag(boss, RoomTag::Boss);
+        }
+
+        for (room, distance) in distances.iter().enumerate() {
+            if self.has_tag(room, RoomTag::DeadEnd)
+                && distance.is_some()
+                && room != start
+                && Some(room) != exit
+                && Some(room) != boss
+            {
+                self.add_tag(room, RoomTag::Treasure);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Dimension2;
+
+    fn connection(from: usize, to: usize) -> RoomConnection {
+        RoomConnection {
+            from,
+            to,
+            cells: vec![],
+        }
+    }
+
+    #[test]
+    fn test_tag_rooms() {
+        //      3
+        //      |
+        // 0 -- 1 -- 2 -- 4
+        //           |
+        //           5
+        let rooms: Vec<Room> = (0..6)
+            .map(|i| {
+                let side = if i == 2 { 12 } else { 6 };
+                Room::new(IntVector2::new(i * 20, 0), Dimension2::new(side, side))
+            })
+            .collect();
+        let connections = vec![
+            connection(0, 1),
+            connection(1, 2),
+            connection(1, 3),
+            connection(2, 4),
+            connection(2, 5),
+        ];
+        let mut graph = RoomGraph::new(rooms, connections);
+
+        assert_eq!(graph.neighbors(2), vec![1, 4, 5]);
+        assert_eq!(
+            graph.distances_from(0),
+            vec![Some(0), Some(1), Some(2), Some(2), Some(3), Some(3)]
+        );
+
+        graph.tag_rooms(0);
+        assert!(graph.has_tag(0, RoomTag::Start));
+        assert!(graph.has_tag(0, RoomTag::DeadEnd));
+        assert!(graph.has_tag(4, RoomTag::Exit));
+        assert!(graph.has_tag(2, RoomTag::Boss));
+        assert_eq!(
+            graph.rooms_with_tag(RoomTag::Treasure).collect::<Vec<_>>(),
+            vec![3, 5]
+        );
+        assert_eq!(
+            graph.rooms_with_tag(RoomTag::DeadEnd).collect::<Vec<_>>(),
+            vec![0, 3, 4, 5]
+        );
+        assert_eq!(graph.nearest_room(IntVector2::new(41, 3)), Some(2));
+    }
+}
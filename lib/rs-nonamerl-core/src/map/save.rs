@@ -0,0 +1,343 @@
+use std::{
+    fmt, fs,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    prelude::{SerializableTile, Tile},
+    Dimension2, IntVector2,
+};
+
+use super::{GameMap, GridStorage, MapBuilder, Room};
+
+/// The version of the save format written by [`MapSave`]. Saves of other versions are
+/// refused.
+pub const MAP_SAVE_VERSION: u32 = 1;
+
+/// How a [`GameMap`] is saved. `visited` is kept apart from the tiles, since not every tile
+/// type serializes its visited state.
+#[derive(Serialize)]
+struct GameMapRef<'a, T: Clone> {
+    size: [u32; 2],
+    grid: &'a GridStorage<T>,
+    visited: Vec<[i32; 2]>,
+}
+
+#[derive(Deserialize)]
+struct GameMapData<T: Clone> {
+    size: [u32; 2],
+    grid: GridStorage<T>,
+    #[serde(default)]
+    visited: Vec<[i32; 2]>,
+}
+
+/// The grid is written with the backend it is stored in, holding the read lock.
+impl<T: SerializableTile> Serialize for GameMap<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let grid = self.read();
+        let mut visited: Vec<[i32; 2]> = grid
+            .iter()
+            .filter(|(_, tile)| tile.is_visited())
+            .map(|(position, _)| [position.x, position.y])
+            .collect();
+        visited.sort_by_key(|[x, y]| (*y, *x));
+        GameMapRef {
+            size: [self.size.width(), self.size.height()],
+            grid: &*grid,
+            visited,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, T: SerializableTile> Deserialize<'de> for GameMap<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let data = GameMapData::<T>::deserialize(deserializer)?;
+        let map = Self {
+            grid: Arc::new(RwLock::new(data.grid)),
+            size: Dimension2::new(data.size[0], data.size[1]),
+            ..Self::new()
+        };
+        for [x, y] in data.visited {
+            map.set_visited(IntVector2::new(x, y), true);
+        }
+        Ok(map)
+    }
+}
+
+#[derive(Debug)]
+pub enum MapSaveError {
+    Io(std::io::Error),
+    Binary(bincode::Error),
+    Json(serde_json::Error),
+    /// The save was written with another version of the format, see [`MAP_SAVE_VERSION`].
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for MapSaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapSaveError::Io(error) => write!(f, "i/o error: {}", error),
+            MapSaveError::Binary(error) => write!(f, "invalid binary map: {}", error),
+            MapSaveError::Json(error) => write!(f, "invalid json map: {}", error),
+            MapSaveError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported map version {}, expected {}",
+                version, MAP_SAVE_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MapSaveError {}
+
+impl From<std::io::Error> for MapSaveError {
+    fn from(error: std::io::Error) -> Self {
+        MapSaveError::Io(error)
+    }
+}
+
+impl From<bincode::Error> for MapSaveError {
+    fn from(error: bincode::Error) -> Self {
+        MapSaveError::Binary(error)
+    }
+}
+
+impl From<serde_json::Error> for MapSaveError {
+    fn from(error: serde_json::Error) -> Self {
+        MapSaveError::Json(error)
+    }
+}
+
+/// The first field of every version of the format, read before the rest of the save.
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+/// A [`GameMap`] and the rooms it was built with, as written to save games, test fixtures or
+/// shared seeds.
+///
+/// Saves come in two forms with the same content: a compact binary one, see
+/// [`MapSave::to_bytes`], and a readable JSON one, see [`MapSave::to_json`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "T: SerializableTile")]
+pub struct MapSave<T: Tile> {
+    version: u32,
+    pub map: GameMap<T>,
+    pub rooms: Vec<Room>,
+}
+
+impl<T: SerializableTile> MapSave<T> {
+    pub fn new(map: GameMap<T>, rooms: Vec<Room>) -> Self {
+        Self {
+            version: MAP_SAVE_VERSION,
+            map,
+            rooms,
+        }
+    }
+
+    /// The map and the rooms of `map_builder`.
+    pub fn from_builder(map_builder: &MapBuilder<T>) -> Self {
+        Self::new(map_builder.map.clone(), map_builder.rooms.clone())
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, MapSaveError> {
+        Ok(bincode::serialize(self)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MapSaveError> {
+        check_version(bincode::deserialize::<SaveHeader>(bytes)?)?;
+        Ok(bincode::deserialize(bytes)?)
+    }
+
+    pub fn to_json(&self) -> Result<String, MapSaveError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, MapSaveError> {
+        check_version(serde_json::from_str::<SaveHeader>(json)?)?;
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Writes the save to `path`, as JSON when its extension is `json` and in the binary form
+    /// otherwise.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), MapSaveError> {
+        let path = path.as_ref();
+        if is_json(path) {
+            fs::write(path, self.to_json()?)?;
+        } else {
+            fs::write(path, self.to_bytes()?)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a save written by [`MapSave::save`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, MapSaveError> {
+        let path = path.as_ref();
+        if is_json(path) {
+            Self::from_json(&fs::read_to_string(path)?)
+        } else {
+            Self::from_bytes(&fs::read(path)?)
+        }
+    }
+}
+
+fn check_version(header: SaveHeader) -> Result<(), MapSaveError> {
+    if header.version != MAP_SAVE_VERSION {
+        return Err(MapSaveError::UnsupportedVersion(header.version));
+    }
+    Ok(())
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        prelude::{FovOccluder, ItemContainer, Openable, RoomShape, Visible, Visited, Walkable},
+        test_utils::{map_from_rows, TestTile},
+    };
+
+    /// A tile that keeps its visited state out of its serialized form.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct VisitedTile {
+        wall: bool,
+        #[serde(skip)]
+        visited: bool,
+    }
+
+    impl Tile for VisitedTile {}
+    impl Visible for VisitedTile {}
+    impl ItemContainer for VisitedTile {}
+    impl Openable for VisitedTile {}
+    impl FovOccluder for VisitedTile {}
+    impl Walkable for VisitedTile {}
+    impl Visited for VisitedTile {
+        fn is_visited(&self) -> bool {
+            self.visited
+        }
+        fn set_visited(&mut self, visited: bool) {
+            self.visited = visited;
+        }
+    }
+
+    fn rows() -> [&'static str; 5] {
+        [
+            "#######", //
+            "#..~..#", "#.....+", "#.....#", "#######",
+        ]
+    }
+
+    fn assert_same_tiles(a: &GameMap<TestTile>, b: &GameMap<TestTile>) {
+        assert_eq!(a.len(), b.len());
+        for y in -1..6 {
+            for x in -1..8 {
+                assert_eq!(a.get(x, y), b.get(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn test_save_round_trip() {
+        let sparse = map_from_rows(&rows());
+        let chunked = GameMap::with_storage(GridStorage::chunked());
+        for (position, tile) in sparse.read().iter() {
+            chunked.set(position.x, position.y, *tile);
+        }
+
+        let mut rng = rand::thread_rng();
+        let rooms = vec![
+            Room::new(IntVector2::new(0, 0), Dimension2::new(7, 5)),
+            Room::shaped(
+                &mut rng,
+                IntVector2::new(10, 0),
+                Dimension2::new(9, 9),
+                RoomShape::Blob,
+            ),
+        ];
+
+        for map in [sparse, chunked] {
+            let save = MapSave::new(map.clone(), rooms.clone());
+
+            let binary = MapSave::<TestTile>::from_bytes(&save.to_bytes().unwrap()).unwrap();
+            let json = MapSave::<TestTile>::from_json(&save.to_json().unwrap()).unwrap();
+            for loaded in [binary, json] {
+                assert_eq!(loaded.version(), MAP_SAVE_VERSION);
+                assert_same_tiles(&map, &loaded.map);
+                assert_eq!(
+                    matches!(*loaded.map.read(), GridStorage::Chunked(_)),
+                    matches!(*map.read(), GridStorage::Chunked(_))
+                );
+                assert_eq!(loaded.rooms.len(), rooms.len());
+                for (room, loaded) in rooms.iter().zip(loaded.rooms.iter()) {
+                    assert_eq!(loaded.shape(), room.shape());
+                    assert_eq!(loaded.cells(), room.cells());
+                    assert_eq!(loaded.center(), room.center());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_save_visited() {
+        let map = GameMap::<VisitedTile>::new();
+        for x in 0..4 {
+            map.set(
+                x,
+                0,
+                VisitedTile {
+                    wall: x == 0,
+                    visited: false,
+                },
+            );
+        }
+        map.set_visited(IntVector2::new(1, 0), true);
+        map.set_visited(IntVector2::new(2, 0), true);
+
+        let save = MapSave::new(map, Vec::new());
+        let loaded = MapSave::<VisitedTile>::from_bytes(&save.to_bytes().unwrap()).unwrap();
+        let visited: Vec<bool> = (0..4)
+            .map(|x| loaded.map.get(x, 0).unwrap().is_visited())
+            .collect();
+        assert_eq!(visited, [false, true, true, false]);
+        assert!(loaded.map.get(0, 0).unwrap().wall);
+    }
+
+    #[test]
+    fn test_save_version() {
+        let save = MapSave::new(map_from_rows(&rows()), Vec::new());
+
+        let json = save.to_json().unwrap().replacen(
+            &format!("\"version\": {}", MAP_SAVE_VERSION),
+            "\"version\": 999",
+            1,
+        );
+        assert!(matches!(
+            MapSave::<TestTile>::from_json(&json),
+            Err(MapSaveError::UnsupportedVersion(999))
+        ));
+
+        let mut bytes = save.to_bytes().unwrap();
+        bytes[..4].copy_from_slice(&999u32.to_le_bytes());
+        assert!(matches!(
+            MapSave::<TestTile>::from_bytes(&bytes),
+            Err(MapSaveError::UnsupportedVersion(999))
+        ));
+        assert!(matches!(
+            MapSave::<TestTile>::from_bytes(&bytes[..2]),
+            Err(MapSaveError::Binary(_))
+        ));
+    }
+}
@@ -0,0 +1,211 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    prelude::{Connectivity, Tile},
+    IntVector2,
+};
+
+use super::{MapBuilder, MapBuilderAlgorithm, RoomTag};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum StairDirection {
+    /// Leads to the level above, `depth - 1`.
+    Up,
+    /// Leads to the level below, `depth + 1`.
+    Down,
+}
+
+/// A staircase placed by a [`StairsBuilder`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Stair {
+    pub position: IntVector2,
+    pub direction: StairDirection,
+}
+
+/// A building step that connects a level to the ones above and below it.
+///
+/// The up stairs go in the room nearest to `start` and the down stairs in the room farthest
+/// from it, as tagged by [`RoomGraph::tag_rooms`](super::RoomGraph::tag_rooms). Without
+/// rooms, they go on the walkable cell nearest to `start` and on the farthest cell of the
+/// same region. Levels at depth 0 have no up stairs.
+#[derive(Debug, Clone)]
+pub struct StairsBuilder<T>
+where
+    T: Tile,
+{
+    up_tile: String,
+    down_tile: String,
+    /// Where the level is entered from, the center of the extent when missing.
+    start: Option<IntVector2>,
+    _marker: std::marker::PhantomData<T>,
+}
+
+impl<T> StairsBuilder<T>
+where
+    T: Tile,
+{
+    pub fn new() -> Self {
+        Self {
+            up_tile: "stairs_up".to_owned(),
+            down_tile: "stairs_down".to_owned(),
+            start: None,
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Sets the names of the tiles of the up and down stairs.
+    pub fn with_tiles(mut self, up_tile: &str, down_tile: &str) -> Self {
+        self.up_tile = up_tile.to_owned();
+        self.down_tile = down_tile.to_owned();
+        self
+    }
+
+    pub fn with_start(mut self, start: IntVector2) -> Self {
+        self.start = Some(start);
+        self
+    }
+}
+
+impl<T: Tile> Default for StairsBuilder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Tile> MapBuilderAlgorithm<T> for StairsBuilder<T> {
+    fn build<'a>(&self, map_builder: &'a mut MapBuilder<T>) -> &'a mut MapBuilder<T> {
+        let extent = map_builder.extent;
+        let start = self.start.unwrap_or_else(|| {
+            IntVector2::new(
+                extent.left() + extent.width() as i32 / 2,
+                extent.top() + extent.height() as i32 / 2,
+            )
+        });
+        let distance = |a: IntVector2, b: IntVector2| {
+            let delta = a - b;
+            delta.x * delta.x + delta.y * delta.y
+        };
+
+        let mut room_graph = map_builder.room_graph();
+        let mut up = None;
+        let mut down = None;
+        if let Some(start_room) = room_graph.nearest_room(start) {
+            room_graph.tag_rooms(start_room);
+            up = Some(room_graph.room(start_room).center());
+            down = room_graph
+                .rooms_with_tag(RoomTag::Exit)
+                .next()
+                .map(|exit| room_graph.room(exit).center());
+        }
+
+        let regions = map_builder.map.regions(&extent, Connectivity::Four);
+        if up.is_none() {
+            up = (0..regions.len())
+                .flat_map(|region| regions.cells(region).iter().copied())
+                .min_by_key(|cell| distance(*cell, start));
+        }
+        let Some(up) = up else {
+            return map_builder;
+        };
+        if down.is_none() {
+            down = regions
+                .region_of(up)
+                .and_then(|region| {
+                    regions
+                        .cells(region)
+                        .iter()
+                        .copied()
+                        .max_by_key(|cell| distance(*cell, up))
+                })
+                .filter(|cell| *cell != up);
+        }
+
+        let depth = map_builder.depth();
+        let mut place = |position: IntVector2, direction: StairDirection, tile_name: &str| {
+            let tile = map_builder.tile(tile_name);
+            map_builder.map.set(position.x, position.y, tile);
+            map_builder.stairs.push(Stair {
+                position,
+                direction,
+            });
+        };
+        if depth > 0 {
+            place(up, StairDirection::Up, &self.up_tile);
+        }
+        if let Some(down) = down {
+            place(down, StairDirection::Down, &self.down_tile);
+        }
+
+        map_builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_utils::TestTile, IntExtent2};
+
+    use super::super::{BspBuilder, CaveBuilder, FillWithFloorBuilderAlgo};
+
+    fn map_builder(depth: u32) -> MapBuilder<TestTile> {
+        let mut map_builder = MapBuilder::<TestTile>::new(IntExtent2::new(0, 0, 60, 40))
+            .with_seed(11)
+            .with_depth(depth);
+        map_builder.add_tile("floor".to_owned(), TestTile::Floor);
+        map_builder.add_tile("wall".to_owned(), TestTile::Wall);
+        map_builder.add_tile("stairs_up".to_owned(), TestTile::Smoke);
+        map_builder.add_tile("stairs_down".to_owned(), TestTile::Smoke);
+        map_builder
+    }
+
+    fn stair(map_builder: &MapBuilder<TestTile>, direction: StairDirection) -> Option<Stair> {
+        map_builder
+            .stairs
+            .iter()
+            .find(|stair| stair.direction == direction)
+            .copied()
+    }
+
+    #[test]
+    fn test_stairs_in_rooms() {
+        let mut map_builder = map_builder(2);
+        map_builder
+            .build_step(&FillWithFloorBuilderAlgo::new(
+                IntExtent2::new(0, 0, 60, 40),
+                "wall",
+            ))
+            .build_step(&BspBuilder::new())
+            .build_step(&StairsBuilder::new().with_start(IntVector2::new(0, 0)));
+
+        let mut room_graph = map_builder.room_graph();
+        let start = room_graph.nearest_room(IntVector2::new(0, 0)).unwrap();
+        room_graph.tag_rooms(start);
+        let exit = room_graph.rooms_with_tag(RoomTag::Exit).next().unwrap();
+
+        let up = stair(&map_builder, StairDirection::Up).unwrap();
+        let down = stair(&map_builder, StairDirection::Down).unwrap();
+        assert_eq!(up.position, room_graph.room(start).center());
+        assert_eq!(down.position, room_graph.room(exit).center());
+        for stair in map_builder.stairs.iter() {
+            assert_eq!(
+                map_builder.map.get(stair.position.x, stair.position.y),
+                Some(TestTile::Smoke)
+            );
+        }
+    }
+
+    #[test]
+    fn test_stairs_without_rooms() {
+        let mut map_builder = map_builder(0);
+        map_builder.build_step(&CaveBuilder::new(IntExtent2::new(0, 0, 60, 40)));
+        map_builder.build_step(&StairsBuilder::new());
+
+        // the surface has no way up
+        assert!(stair(&map_builder, StairDirection::Up).is_none());
+        let down = stair(&map_builder, StairDirection::Down).unwrap();
+        assert_eq!(
+            map_builder.map.get(down.position.x, down.position.y),
+            Some(TestTile::Smoke)
+        );
+    }
+}
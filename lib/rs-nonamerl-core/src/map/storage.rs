@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    prelude::{ChunkedGrid2D, LatticeGrid2D, Plane},
+    IntExtent2, IntVector2,
+};
+
+/// The backend a [`GameMap`](super::GameMap) keeps its tiles in.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GridStorage<T: Clone> {
+    /// A `HashMap` of cells: cheap for small or very scattered maps.
+    Sparse(LatticeGrid2D<T>),
+    /// Dense chunks of cells: faster lookups and region reads on large maps.
+    Chunked(ChunkedGrid2D<T>),
+}
+
+impl<T: Clone> GridStorage<T> {
+    pub fn sparse() -> Self {
+        Self::Sparse(LatticeGrid2D::new())
+    }
+
+    pub fn chunked() -> Self {
+        Self::Chunked(ChunkedGrid2D::new())
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            GridStorage::Sparse(grid) => grid.len(),
+            GridStorage::Chunked(grid) => grid.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            GridStorage::Sparse(grid) => grid.is_empty(),
+            GridStorage::Chunked(grid) => grid.is_empty(),
+        }
+    }
+
+    pub fn remove(&mut self, position: IntVector2) -> Option<T> {
+        match self {
+            GridStorage::Sparse(grid) => grid.remove(position),
+            GridStorage::Chunked(grid) => grid.remove(position),
+        }
+    }
+
+    /// Iterates over the cells that hold a value, in no particular order.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (IntVector2, &T)> + '_> {
+        match self {
+            GridStorage::Sparse(grid) => Box::new(grid.iter()),
+            GridStorage::Chunked(grid) => Box::new(grid.iter()),
+        }
+    }
+
+    /// Iterates over the cells of `extent` that hold a value. The order of the cells depends
+    /// on the backend.
+    pub fn iter_region<'a>(
+        &'a self,
+        extent: &IntExtent2,
+    ) -> Box<dyn Iterator<Item = (IntVector2, &'a T)> + 'a> {
+        match self {
+            GridStorage::Sparse(grid) => Box::new(grid.iter_region(extent)),
+            GridStorage::Chunked(grid) => Box::new(grid.iter_region(extent)),
+        }
+    }
+}
+
+impl<T: Clone> Default for GridStorage<T> {
+    fn default() -> Self {
+        Self::sparse()
+    }
+}
+
+impl<T: Clone> Plane<T> for GridStorage<T> {
+    fn at(&self, position: IntVector2) -> Option<&T> {
+        match self {
+            GridStorage::Sparse(grid) => grid.at(position),
+            GridStorage::Chunked(grid) => grid.at(position),
+        }
+    }
+
+    fn at_mut(&mut self, position: IntVector2) -> Option<&mut T> {
+        match self {
+            GridStorage::Sparse(grid) => grid.at_mut(position),
+            GridStorage::Chunked(grid) => grid.at_mut(position),
+        }
+    }
+
+    fn put(&mut self, pos: IntVector2, value: T) {
+        match self {
+            GridStorage::Sparse(grid) => grid.put(pos, value),
+            GridStorage::Chunked(grid) => grid.put(pos, value),
+        }
+    }
+
+    fn line(&self, start: IntVector2, end: IntVector2) -> Vec<IntVector2> {
+        match self {
+            GridStorage::Sparse(grid) => grid.line(start, end),
+            GridStorage::Chunked(grid) => grid.line(start, end),
+        }
+    }
+
+    fn neighbors(&self, pos: IntVector2) -> Vec<IntVector2> {
+        match self {
+            GridStorage::Sparse(grid) => grid.neighbors(pos),
+            GridStorage::Chunked(grid) => grid.neighbors(pos),
+        }
+    }
+}
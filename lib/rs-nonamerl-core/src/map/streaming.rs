@@ -0,0 +1,420 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::PathBuf,
+};
+
+use bevy_ecs::system::Resource;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    chunked_grid::DEFAULT_CHUNK_SIZE,
+    prelude::{Plane, Tile},
+    IntExtent2, IntVector2,
+};
+
+use super::{GameMap, MapBuilder, SpawnMarker};
+
+/// Where a [`ChunkStreamer`] keeps the chunks it evicts.
+pub trait ChunkStore<T>: Send + Sync {
+    fn save(&mut self, chunk: IntVector2, cells: Vec<(IntVector2, T)>) -> io::Result<()>;
+    /// The cells of a chunk saved before, `None` if the chunk was never saved.
+    fn load(&mut self, chunk: IntVector2) -> io::Result<Option<Vec<(IntVector2, T)>>>;
+}
+
+/// Saves every chunk as a JSON file in a directory.
+#[derive(Debug, Clone)]
+pub struct DiskChunkStore {
+    dir: PathBuf,
+}
+
+impl DiskChunkStore {
+    /// Creates `dir` if it doesn't exist.
+    pub fn new(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    fn path(&self, chunk: IntVector2) -> PathBuf {
+        self.dir.join(format!("chunk_{}_{}.json", chunk.x, chunk.y))
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> ChunkStore<T> for DiskChunkStore {
+    fn save(&mut self, chunk: IntVector2, cells: Vec<(IntVector2, T)>) -> io::Result<()> {
+        let cells: Vec<(i32, i32, T)> = cells
+            .into_iter()
+            .map(|(pos, tile)| (pos.x, pos.y, tile))
+            .collect();
+        let writer = BufWriter::new(File::create(self.path(chunk))?);
+        serde_json::to_writer(writer, &cells)?;
+        Ok(())
+    }
+
+    fn load(&mut self, chunk: IntVector2) -> io::Result<Option<Vec<(IntVector2, T)>>> {
+        let path = self.path(chunk);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let cells: Vec<(i32, i32, T)> = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        Ok(Some(
+            cells
+                .into_iter()
+                .map(|(x, y, tile)| (IntVector2::new(x, y), tile))
+                .collect(),
+        ))
+    }
+}
+
+/// What a [`ChunkStreamer::update`] changed.
+#[derive(Debug, Clone, Default)]
+pub struct ChunkUpdate {
+    /// The chunks generated for the first time.
+    pub generated: Vec<IntVector2>,
+    /// The chunks loaded back from the store.
+    pub restored: Vec<IntVector2>,
+    /// The chunks moved to the store.
+    pub evicted: Vec<IntVector2>,
+    /// The spawn markers of the generated chunks.
+    pub spawn_markers: Vec<SpawnMarker>,
+}
+
+impl ChunkUpdate {
+    pub fn is_empty(&self) -> bool {
+        self.generated.is_empty() && self.restored.is_empty() && self.evicted.is_empty()
+    }
+}
+
+type ChunkGenerator<T> = Box<dyn Fn(&mut MapBuilder<T>) + Send + Sync>;
+
+/// Generates an unbounded map chunk by chunk, around the areas the game is looking at.
+///
+/// Every chunk is built by its own [`MapBuilder`], whose extent is the chunk and whose seed
+/// comes from the world seed and the chunk coordinates: a chunk is always the same, whatever
+/// the order the chunks are generated in. Only the cells inside the chunk that are still
+/// empty on the map are copied, so streaming around a pre-built level never changes it.
+///
+/// With a [`ChunkStore`] the chunks far from every area are moved out of the map, and loaded
+/// back instead of being generated again when they are needed. Entities are not part of the
+/// chunks.
+#[derive(Resource)]
+pub struct ChunkStreamer<T: Tile> {
+    seed: u64,
+    chunk_size: u32,
+    margin: u32,
+    tiles: HashMap<String, T>,
+    generator: ChunkGenerator<T>,
+    store: Option<Box<dyn ChunkStore<T>>>,
+    evict_distance: u32,
+    loaded: HashSet<IntVector2>,
+}
+
+impl<T: Tile> ChunkStreamer<T> {
+    /// `generator` runs the building steps of a chunk.
+    pub fn new(seed: u64, generator: impl Fn(&mut MapBuilder<T>) + Send + Sync + 'static) -> Self {
+        Self {
+            seed,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            margin: DEFAULT_CHUNK_SIZE / 2,
+            tiles: HashMap::new(),
+            generator: Box::new(generator),
+            store: None,
+            evict_distance: 0,
+            loaded: HashSet::new(),
+        }
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: u32) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Sets how many cells around the areas passed to [`ChunkStreamer::update`] must be
+    /// generated too.
+    pub fn with_margin(mut self, margin: u32) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    /// Uses the tiles registered in `map_builder` for the chunks.
+    pub fn with_tiles_from(mut self, map_builder: &MapBuilder<T>) -> Self {
+        self.tiles = map_builder.tiles.clone();
+        self
+    }
+
+    pub fn add_tile(&mut self, name: String, tile: T) {
+        self.tiles.insert(name, tile);
+    }
+
+    /// Evicts to `store` the chunks more than `evict_distance` chunks away from the needed ones.
+    pub fn with_store(mut self, store: impl ChunkStore<T> + 'static, evict_distance: u32) -> Self {
+        self.store = Some(Box::new(store));
+        self.evict_distance = evict_distance;
+        self
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The chunk containing `position`.
+    pub fn chunk_coord(&self, position: IntVector2) -> IntVector2 {
+        let size = self.chunk_size as i32;
+        IntVector2::new(position.x.div_euclid(size), position.y.div_euclid(size))
+    }
+
+    pub fn chunk_extent(&self, chunk: IntVector2) -> IntExtent2 {
+        let size = self.chunk_size as i32;
+        IntExtent2::new(
+            chunk.x * size,
+            chunk.y * size,
+            self.chunk_size,
+            self.chunk_size,
+        )
+    }
+
+    /// The seed of the builder of `chunk`.
+    pub fn chunk_seed(&self, chunk: IntVector2) -> u64 {
+        // splitmix64 of the world seed mixed with the coordinates
+        let mut z = self.seed ^ ((chunk.x as u32 as u64) << 32 | chunk.y as u32 as u64);
+        z = z.wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    pub fn is_loaded(&self, chunk: IntVector2) -> bool {
+        self.loaded.contains(&chunk)
+    }
+
+    pub fn loaded_chunks(&self) -> impl Iterator<Item = &IntVector2> {
+        self.loaded.iter()
+    }
+
+    /// Marks the chunks entirely inside `extent` as loaded, so they are never generated: use it
+    /// for the part of the map built in advance.
+    pub fn mark_loaded(&mut self, extent: &IntExtent2) {
+        for chunk in self.chunks_overlapping(extent) {
+            let chunk_extent = self.chunk_extent(chunk);
+            if extent.contains(chunk_extent.left(), chunk_extent.top())
+                && extent.contains(chunk_extent.right() - 1, chunk_extent.bottom() - 1)
+            {
+                self.loaded.insert(chunk);
+            }
+        }
+    }
+
+    fn chunks_overlapping(&self, extent: &IntExtent2) -> Vec<IntVector2> {
+        if extent.width() == 0 || extent.height() == 0 {
+            return Vec::new();
+        }
+        let min = self.chunk_coord(IntVector2::new(extent.left(), extent.top()));
+        let max = self.chunk_coord(IntVector2::new(extent.right() - 1, extent.bottom() - 1));
+        (min.y..=max.y)
+            .flat_map(|y| (min.x..=max.x).map(move |x| IntVector2::new(x, y)))
+            .collect()
+    }
+
+    /// Makes sure the chunks around `areas` are on `map`, and evicts the far ones when there
+    /// is a store.
+    pub fn update(&mut self, map: &GameMap<T>, areas: &[IntExtent2]) -> io::Result<ChunkUpdate> {
+        let margin = self.margin as i32;
+        let mut needed = Vec::<IntVector2>::new();
+        for area in areas {
+            let grown = IntExtent2::new(
+                area.left() - margin,
+                area.top() - margin,
+                area.width() + 2 * self.margin,
+                area.height() + 2 * self.margin,
+            );
+            for chunk in self.chunks_overlapping(&grown) {
+                if !needed.contains(&chunk) {
+                    needed.push(chunk);
+                }
+            }
+        }
+
+        let mut update = ChunkUpdate::default();
+        for chunk in needed.iter() {
+            if self.loaded.contains(chunk) {
+                continue;
+            }
+            let stored = match self.store.as_mut() {
+                Some(store) => store.load(*chunk)?,
+                None => None,
+            };
+            match stored {
+                Some(cells) => {
+                    let mut grid = map.write();
+                    for (pos, tile) in cells {
+                        grid.put(pos, tile);
+                    }
+                    update.restored.push(*chunk);
+                }
+                None => {
+                    let markers = self.generate(map, *chunk);
+                    update.spawn_markers.extend(markers);
+                    update.generated.push(*chunk);
+                }
+            }
+            self.loaded.insert(*chunk);
+        }
+
+        if self.store.is_some() {
+            let evict_distance = self.evict_distance as i32;
+            let mut far: Vec<IntVector2> = self
+                .loaded
+                .iter()
+                .filter(|chunk| {
+                    needed.iter().all(|other| {
+                        let delta = (**chunk - *other).abs();
+                        delta.x.max(delta.y) > evict_distance
+                    })
+                })
+                .copied()
+                .collect();
+            far.sort_by_key(|chunk| (chunk.y, chunk.x));
+            for chunk in far {
+                let extent = self.chunk_extent(chunk);
+                let cells: Vec<(IntVector2, T)> = map
+                    .read()
+                    .iter_region(&extent)
+                    .map(|(pos, tile)| (pos, tile.clone()))
+                    .collect();
+                if let Some(store) = self.store.as_mut() {
+                    store.save(chunk, cells.clone())?;
+                }
+                for (pos, _) in cells {
+                    map.remove(pos);
+                }
+                self.loaded.remove(&chunk);
+                update.evicted.push(chunk);
+            }
+        }
+
+        Ok(update)
+    }
+
+    /// Builds `chunk` and copies its cells on the empty cells of `map`.
+    fn generate(&self, map: &GameMap<T>, chunk: IntVector2) -> Vec<SpawnMarker> {
+        let extent = self.chunk_extent(chunk);
+        let mut map_builder = MapBuilder::<T>::new(extent).with_seed(self.chunk_seed(chunk));
+        map_builder.tiles = self.tiles.clone();
+        (self.generator)(&mut map_builder);
+
+        let mut placed = HashSet::<IntVector2>::new();
+        {
+            let built = map_builder.map.read();
+            let mut grid = map.write();
+            for (pos, tile) in built.iter_region(&extent) {
+                if grid.at(pos).is_none() {
+                    grid.put(pos, tile.clone());
+                    placed.insert(pos);
+                }
+            }
+        }
+
+        map_builder
+            .spawn_markers
+            .into_iter()
+            .filter(|marker| placed.contains(&marker.position))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{prelude::RandomWalkBuilder, test_utils::TestTile};
+
+    fn streamer(seed: u64) -> ChunkStreamer<TestTile> {
+        let mut streamer = ChunkStreamer::new(seed, |map_builder: &mut MapBuilder<TestTile>| {
+            let extent = map_builder.extent;
+            let center = IntVector2::new(
+                extent.left() + extent.width() as i32 / 2,
+                extent.top() + extent.height() as i32 / 2,
+            );
+            map_builder.build_step(&RandomWalkBuilder::new(center));
+        })
+        .with_chunk_size(16)
+        .with_margin(0);
+        streamer.add_tile("floor".to_owned(), TestTile::Floor);
+        streamer
+    }
+
+    #[test]
+    fn test_chunks_do_not_depend_on_order() {
+        let a_map = GameMap::<TestTile>::new();
+        let b_map = GameMap::<TestTile>::new();
+        let mut a = streamer(1);
+        let mut b = streamer(1);
+        let near = IntExtent2::new(-8, -8, 16, 16);
+        let far = IntExtent2::new(40, 40, 16, 16);
+
+        let update = a.update(&a_map, &[near]).unwrap();
+        assert_eq!(update.generated.len(), 4);
+        a.update(&a_map, &[far]).unwrap();
+        b.update(&b_map, &[far, near]).unwrap();
+        assert!(a.update(&a_map, &[near, far]).unwrap().is_empty());
+
+        assert!(!a_map.is_empty());
+        assert_eq!(a_map.len(), b_map.len());
+        let world = IntExtent2::new(-32, -32, 128, 128);
+        assert!(world
+            .iter()
+            .all(|pos| a_map.get(pos.x, pos.y) == b_map.get(pos.x, pos.y)));
+        // the cells never leave their chunk
+        assert!(world
+            .iter()
+            .all(|pos| a_map.get(pos.x, pos.y).is_none() || a.is_loaded(a.chunk_coord(pos))));
+    }
+
+    #[test]
+    fn test_existing_cells_are_kept() {
+        let map = GameMap::<TestTile>::new();
+        for pos in IntExtent2::new(0, 0, 16, 16).iter() {
+            map.set(pos.x, pos.y, TestTile::Wall);
+        }
+        let mut streamer = streamer(2);
+        streamer.mark_loaded(&IntExtent2::new(-4, -4, 24, 20));
+        assert!(streamer.is_loaded(IntVector2::new(0, 0)));
+        assert!(!streamer.is_loaded(IntVector2::new(1, 0)));
+
+        let update = streamer
+            .update(&map, &[IntExtent2::new(0, 0, 17, 16)])
+            .unwrap();
+        assert_eq!(update.generated, vec![IntVector2::new(1, 0)]);
+        assert!(IntExtent2::new(0, 0, 16, 16)
+            .iter()
+            .all(|pos| map.get(pos.x, pos.y) == Some(TestTile::Wall)));
+    }
+
+    #[test]
+    fn test_evict_to_disk() {
+        let dir = std::env::temp_dir().join(format!("nonamerl_chunks_{}", std::process::id()));
+        let map = GameMap::<TestTile>::new();
+        let mut streamer = streamer(3).with_store(DiskChunkStore::new(&dir).unwrap(), 1);
+
+        let home = IntExtent2::new(0, 0, 16, 16);
+        streamer.update(&map, &[home]).unwrap();
+        let pos = IntVector2::new(8, 8);
+        map.set(pos.x, pos.y, TestTile::Smoke);
+        let home_cells = map.len();
+
+        let update = streamer
+            .update(&map, &[IntExtent2::new(64, 0, 16, 16)])
+            .unwrap();
+        assert_eq!(update.evicted, vec![IntVector2::new(0, 0)]);
+        assert_eq!(map.get(pos.x, pos.y), None);
+
+        // coming back restores the changes instead of generating the chunk again
+        let update = streamer.update(&map, &[home]).unwrap();
+        assert_eq!(update.restored, vec![IntVector2::new(0, 0)]);
+        assert_eq!(map.get(pos.x, pos.y), Some(TestTile::Smoke));
+        assert_eq!(map.len(), home_cells);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
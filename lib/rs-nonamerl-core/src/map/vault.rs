@@ -0,0 +1,533 @@
+use std::{collections::HashMap, fmt};
+
+use rand::{seq::SliceRandom, Rng};
+use serde::Deserialize;
+
+use crate::{prelude::Tile, Dimension2, IntVector2};
+
+use super::{MapBuilder, MapBuilderAlgorithm, Room};
+
+/// What a character of a vault template stands for.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LegendEntry {
+    /// The name of the tile, as registered with [`MapBuilder::add_tile`]. Without a tile the
+    /// cell is left as it is.
+    #[serde(default)]
+    pub tile: Option<String>,
+    /// A marker left for the spawning code, e.g. `"enemy"` or `"item"`.
+    #[serde(default)]
+    pub spawn: Option<String>,
+}
+
+/// A cell where something should be spawned, left by a building step.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpawnMarker {
+    pub position: IntVector2,
+    pub kind: String,
+}
+
+/// A hand-authored set piece. Spaces and the cells past the end of a row are transparent.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct VaultTemplate {
+    pub name: String,
+    pub rows: Vec<String>,
+    /// Entries that override the legend of the [`VaultConfig`] for this vault only.
+    #[serde(default)]
+    pub legend: HashMap<char, LegendEntry>,
+}
+
+impl VaultTemplate {
+    pub fn size(&self) -> Dimension2 {
+        let width = self
+            .rows
+            .iter()
+            .map(|row| row.chars().count())
+            .max()
+            .unwrap_or(0);
+        Dimension2::new(width as u32, self.rows.len() as u32)
+    }
+
+    /// The cells of the template after `transform`, row by row. `None` cells are transparent.
+    pub fn cells(&self, transform: VaultTransform) -> Vec<Vec<Option<char>>> {
+        let size = self.size();
+        let mut cells: Vec<Vec<Option<char>>> = self
+            .rows
+            .iter()
+            .map(|row| {
+                let mut cells: Vec<Option<char>> =
+                    row.chars().map(|c| (c != ' ').then_some(c)).collect();
+                cells.resize(size.width() as usize, None);
+                cells
+            })
+            .collect();
+
+        if transform.mirror {
+            cells.iter_mut().for_each(|row| row.reverse());
+        }
+        for _ in 0..transform.rotation % 4 {
+            // a quarter turn clockwise
+            let height = cells.len();
+            let width = cells.first().map_or(0, |row| row.len());
+            cells = (0..width)
+                .map(|x| (0..height).rev().map(|y| cells[y][x]).collect())
+                .collect();
+        }
+
+        cells
+    }
+}
+
+/// How a vault is rotated and mirrored before being stamped.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct VaultTransform {
+    /// Clockwise quarter turns.
+    pub rotation: u8,
+    /// Mirrored horizontally, before the rotation.
+    pub mirror: bool,
+}
+
+/// A set of vaults with a shared legend, usually loaded from a JSON file.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct VaultConfig {
+    #[serde(default)]
+    pub legend: HashMap<char, LegendEntry>,
+    pub vaults: Vec<VaultTemplate>,
+}
+
+/// Why a [`VaultConfig`] can't be loaded.
+#[derive(Debug)]
+pub enum VaultConfigError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    /// A character of the rows of a vault is in neither the legend of the vault nor the shared
+    /// one.
+    MissingLegend {
+        vault: String,
+        character: char,
+    },
+}
+
+impl fmt::Display for VaultConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VaultConfigError::Io(error) => write!(f, "i/o error: {}", error),
+            VaultConfigError::Json(error) => write!(f, "invalid vault config: {}", error),
+            VaultConfigError::MissingLegend { vault, character } => {
+                write!(f, "vault {}: no legend entry for '{}'", vault, character)
+            }
+        }
+    }
+}
+
+impl std::error::Error for VaultConfigError {}
+
+impl From<std::io::Error> for VaultConfigError {
+    fn from(error: std::io::Error) -> Self {
+        VaultConfigError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for VaultConfigError {
+    fn from(error: serde_json::Error) -> Self {
+        VaultConfigError::Json(error)
+    }
+}
+
+impl VaultConfig {
+    /// Parses a config, checking that every character of the vaults has a legend entry.
+    pub fn from_json(json: &str) -> Result<Self, VaultConfigError> {
+        let config: Self = serde_json::from_str(json)?;
+        for vault in config.vaults.iter() {
+            let missing = vault
+                .rows
+                .iter()
+                .flat_map(|row| row.chars())
+                .find(|c| *c != ' ' && config.legend_entry(vault, *c).is_none());
+            if let Some(character) = missing {
+                return Err(VaultConfigError::MissingLegend {
+                    vault: vault.name.clone(),
+                    character,
+                });
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn from_file(config_path: &str) -> Result<Self, VaultConfigError> {
+        Self::from_json(&std::fs::read_to_string(config_path)?)
+    }
+
+    /// The legend entry of `c` in `vault`.
+    pub fn legend_entry<'a>(
+        &'a self,
+        vault: &'a VaultTemplate,
+        c: char,
+    ) -> Option<&'a LegendEntry> {
+        vault.legend.get(&c).or_else(|| self.legend.get(&c))
+    }
+
+    /// The tiles of the legend entries `vault` uses.
+    fn tiles<'a>(&'a self, vault: &'a VaultTemplate) -> impl Iterator<Item = &'a str> + 'a {
+        vault
+            .rows
+            .iter()
+            .flat_map(|row| row.chars())
+            .filter_map(move |c| self.legend_entry(vault, c)?.tile.as_deref())
+    }
+}
+
+/// Where a [`VaultBuilder`] puts the vaults.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VaultPlacement {
+    /// Away from the rooms, on cells that are empty or not walkable. The vault is added to
+    /// `MapBuilder::rooms` but it is not connected: follow with a
+    /// [`ConnectivityBuilder`](super::ConnectivityBuilder) step.
+    #[default]
+    FreeSpace,
+    /// At the center of a room big enough to contain it.
+    ReplaceRoom,
+}
+
+/// Stamps hand-authored vaults on the map.
+///
+/// Vaults using a tile that is not registered with [`MapBuilder::add_tile`] are never placed.
+/// Spawn markers of the stamped vaults are pushed to `MapBuilder::spawn_markers`.
+#[derive(Debug, Clone)]
+pub struct VaultBuilder<T>
+where
+    T: Tile,
+{
+    config: VaultConfig,
+    count: usize,
+    placement: VaultPlacement,
+    rotate: bool,
+    mirror: bool,
+    _marker: std::marker::PhantomData<T>,
+}
+
+impl<T> VaultBuilder<T>
+where
+    T: Tile,
+{
+    pub fn new(config: VaultConfig) -> Self {
+        Self {
+            config,
+            count: 1,
+            placement: VaultPlacement::default(),
+            rotate: true,
+            mirror: true,
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Sets how many vaults to place. Vaults that don't fit anywhere are skipped.
+    pub fn with_count(mut self, count: usize) -> Self {
+        self.count = count;
+        self
+    }
+
+    pub fn with_placement(mut self, placement: VaultPlacement) -> Self {
+        self.placement = placement;
+        self
+    }
+
+    /// Allows vaults to be rotated by a random number of quarter turns.
+    pub fn with_rotation(mut self, rotate: bool) -> Self {
+        self.rotate = rotate;
+        self
+    }
+
+    /// Allows vaults to be mirrored at random.
+    pub fn with_mirroring(mut self, mirror: bool) -> Self {
+        self.mirror = mirror;
+        self
+    }
+
+    fn random_transform(&self, map_builder: &mut MapBuilder<T>) -> VaultTransform {
+        let rng = map_builder.rng();
+        VaultTransform {
+            rotation: if self.rotate { rng.gen_range(0..4) } else { 0 },
+            mirror: self.mirror && rng.gen_bool(0.5),
+        }
+    }
+
+    /// A random top left cell where a vault of `size` doesn't touch any room and only covers
+    /// empty or not walkable cells.
+    fn find_free_space(
+        &self,
+        map_builder: &mut MapBuilder<T>,
+        size: Dimension2,
+    ) -> Option<IntVector2> {
+        let extent = map_builder.extent;
+        if size.width() > extent.width() || size.height() > extent.height() {
+            return None;
+        }
+
+        for _ in 0..100 {
+            let rng = map_builder.rng();
+            let pos = IntVector2::new(
+                rng.gen_range(extent.left()..=extent.right() - size.width() as i32),
+                rng.gen_range(extent.top()..=extent.bottom() - size.height() as i32),
+            );
+            let footprint = Room::new(pos, size);
+            if map_builder
+                .rooms
+                .iter()
+                .any(|room| room.intersects(&footprint))
+            {
+                map_builder.failed_placements += 1;
+                continue;
+            }
+            let free = footprint.cells().iter().all(|cell| {
+                map_builder
+                    .map
+                    .with_tile(*cell, |tile| !tile.is_walkable())
+                    .unwrap_or(true)
+            });
+            if free {
+                return Some(pos);
+            }
+            map_builder.failed_placements += 1;
+        }
+
+        None
+    }
+
+    /// The top left cell of a vault of `size` centered in a random room big enough for it.
+    fn find_room(&self, map_builder: &mut MapBuilder<T>, size: Dimension2) -> Option<IntVector2> {
+        let candidates: Vec<IntVector2> = map_builder
+            .rooms
+            .iter()
+            .filter(|room| {
+                room.size().width() >= size.width() && room.size().height() >= size.height()
+            })
+            .map(|room| {
+                let top_left = room.pos()
+                    + IntVector2::new(
+                        (room.size().width() - size.width()) as i32 / 2,
+                        (room.size().height() - size.height()) as i32 / 2,
+                    );
+                (room, top_left)
+            })
+            // rooms that are not rectangles must hold the whole vault
+            .filter(|(room, top_left)| {
+                (0..size.width() as i32).all(|x| {
+                    (0..size.height() as i32)
+                        .all(|y| room.contains(*top_left + IntVector2::new(x, y)))
+                })
+            })
+            .map(|(_, top_left)| top_left)
+            .collect();
+        candidates.choose(map_builder.rng()).copied()
+    }
+
+    fn stamp(
+        &self,
+        map_builder: &mut MapBuilder<T>,
+        vault: &VaultTemplate,
+        cells: &[Vec<Option<char>>],
+        top_left: IntVector2,
+    ) {
+        for (y, row) in cells.iter().enumerate() {
+            for (x, c) in row.iter().enumerate() {
+                let Some(c) = c else {
+                    continue;
+                };
+                let position = top_left + IntVector2::new(x as i32, y as i32);
+                let Some(entry) = self.config.legend_entry(vault, *c) else {
+                    continue;
+                };
+
+                if let Some(tile) = entry
+                    .tile
+                    .as_ref()
+                    .and_then(|tile_name| map_builder.get_tile(tile_name))
+                {
+                    map_builder.map.set(position.x, position.y, tile.clone());
+                }
+                if let Some(kind) = &entry.spawn {
+                    map_builder.spawn_markers.push(SpawnMarker {
+                        position,
+                        kind: kind.clone(),
+                    });
+                }
+            }
+        }
+    }
+}
+
+impl<T: Tile> MapBuilderAlgorithm<T> for VaultBuilder<T> {
+    fn build<'a>(&self, map_builder: &'a mut MapBuilder<T>) -> &'a mut MapBuilder<T> {
+        let vaults: Vec<&VaultTemplate> = self
+            .config
+            .vaults
+            .iter()
+            .filter(|vault| {
+                self.config
+                    .tiles(vault)
+                    .all(|tile| map_builder.get_tile(tile).is_some())
+            })
+            .collect();
+        for _ in 0..self.count {
+            let Some(vault) = vaults.choose(map_builder.rng()).copied() else {
+                break;
+            };
+            let transform = self.random_transform(map_builder);
+            let cells = vault.cells(transform);
+            let size = Dimension2::new(
+                cells.first().map_or(0, |row| row.len()) as u32,
+                cells.len() as u32,
+            );
+
+            match self.placement {
+                VaultPlacement::FreeSpace => {
+                    if let Some(top_left) = self.find_free_space(map_builder, size) {
+                        self.stamp(map_builder, vault, &cells, top_left);
+                        map_builder.rooms.push(Room::new(top_left, size));
+                    }
+                }
+                VaultPlacement::ReplaceRoom => match self.find_room(map_builder, size) {
+                    Some(top_left) => self.stamp(map_builder, vault, &cells, top_left),
+                    None => map_builder.failed_placements += 1,
+                },
+            }
+        }
+
+        map_builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_utils::TestTile, IntExtent2};
+
+    const CONFIG: &str = r#####"{
+        "legend": {
+            "#": { "tile": "wall" },
+            ".": { "tile": "floor" },
+            "E": { "tile": "floor", "spawn": "enemy" }
+        },
+        "vaults": [
+            {
+                "name": "guard post",
+                "rows": [
+                    "####",
+                    "#.!#",
+                    "#E.",
+                    "####"
+                ],
+                "legend": { "!": { "tile": "floor", "spawn": "item" } }
+            }
+        ]
+    }"#####;
+
+    fn map_builder() -> MapBuilder<TestTile> {
+        let mut map_builder =
+            MapBuilder::<TestTile>::new(IntExtent2::new(0, 0, 30, 30)).with_seed(5);
+        map_builder.add_tile("floor".to_owned(), TestTile::Floor);
+        map_builder.add_tile("wall".to_owned(), TestTile::Wall);
+        map_builder
+    }
+
+    #[test]
+    fn test_transform() {
+        let config = VaultConfig::from_json(CONFIG).unwrap();
+        let vault = &config.vaults[0];
+        assert_eq!(vault.size().width(), 4);
+        assert_eq!(vault.size().height(), 4);
+
+        let cells = vault.cells(VaultTransform::default());
+        assert_eq!(cells[2][3], None);
+
+        let rotated = vault.cells(VaultTransform {
+            rotation: 1,
+            mirror: false,
+        });
+        assert_eq!(rotated[1][1], Some('E'));
+        assert_eq!(rotated[3][1], None);
+
+        let mirrored = vault.cells(VaultTransform {
+            rotation: 0,
+            mirror: true,
+        });
+        assert_eq!(mirrored[1][1], Some('!'));
+    }
+
+    #[test]
+    fn test_free_space() {
+        let mut map_builder = map_builder();
+        map_builder
+            .rooms
+            .push(Room::new(IntVector2::new(0, 0), Dimension2::new(20, 20)));
+        map_builder.build_step(
+            &VaultBuilder::new(VaultConfig::from_json(CONFIG).unwrap())
+                .with_rotation(false)
+                .with_mirroring(false),
+        );
+
+        let vault = map_builder.rooms.last().unwrap();
+        assert!(!vault.intersects(&map_builder.rooms[0]));
+
+        let kinds: Vec<&str> = map_builder
+            .spawn_markers
+            .iter()
+            .map(|marker| marker.kind.as_str())
+            .collect();
+        assert_eq!(kinds, vec!["item", "enemy"]);
+        assert_eq!(
+            map_builder.spawn_markers[1].position,
+            vault.pos() + IntVector2::new(1, 2)
+        );
+        assert_eq!(
+            map_builder.map.get(vault.pos().x, vault.pos().y),
+            Some(TestTile::Wall)
+        );
+        // transparent cell
+        assert_eq!(
+            map_builder.map.get(vault.pos().x + 3, vault.pos().y + 2),
+            None
+        );
+    }
+
+    #[test]
+    fn test_replace_room() {
+        let mut map_builder = map_builder();
+        map_builder
+            .rooms
+            .push(Room::new(IntVector2::new(2, 2), Dimension2::new(8, 6)));
+        map_builder.build_step(
+            &VaultBuilder::new(VaultConfig::from_json(CONFIG).unwrap())
+                .with_placement(VaultPlacement::ReplaceRoom)
+                .with_rotation(false)
+                .with_mirroring(false),
+        );
+
+        assert_eq!(map_builder.rooms.len(), 1);
+        assert_eq!(map_builder.map.get(4, 3), Some(TestTile::Wall));
+        assert_eq!(map_builder.spawn_markers[0].position, IntVector2::new(6, 4));
+    }
+
+    #[test]
+    fn test_invalid_vaults() {
+        let missing = CONFIG.replace("\"#E.\"", "\"#E?\"");
+        assert!(matches!(
+            VaultConfig::from_json(&missing),
+            Err(VaultConfigError::MissingLegend { vault, character: '?' }) if vault == "guard post"
+        ));
+        assert!(matches!(
+            VaultConfig::from_file("no/such/vaults.json"),
+            Err(VaultConfigError::Io(_))
+        ));
+
+        // a tile the map doesn't know leaves the vault out
+        let unknown_tile = CONFIG.replace("\"tile\": \"wall\"", "\"tile\": \"wal\"");
+        let mut map_builder = map_builder();
+        map_builder.build_step(&VaultBuilder::new(
+            VaultConfig::from_json(&unknown_tile).unwrap(),
+        ));
+        assert!(map_builder.map.is_empty());
+        assert!(map_builder.rooms.is_empty());
+    }
+}
@@ -0,0 +1,545 @@
+use std::{collections::HashMap, fmt};
+
+use rand::{distributions::WeightedIndex, prelude::Distribution, Rng};
+
+use crate::{
+    prelude::{GameMap, Tile},
+    IntExtent2, IntVector2,
+};
+
+use super::{MapBuilder, MapBuilderAlgorithm};
+
+/// Up, right, down and left: the opposite of direction `d` is `(d + 2) % 4`.
+const DIRECTIONS: [(i32, i32); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+
+/// Why rows of characters are not a [`WfcSample`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum WfcSampleError {
+    /// The row at this index doesn't have the length of the first one.
+    RowLength(usize),
+    /// The character is not in the legend.
+    MissingLegend(char),
+}
+
+impl fmt::Display for WfcSampleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WfcSampleError::RowLength(row) => {
+                write!(
+                    f,
+                    "row {} of the sample doesn't have the length of the first",
+                    row
+                )
+            }
+            WfcSampleError::MissingLegend(c) => write!(f, "'{}' is not in the legend", c),
+        }
+    }
+}
+
+impl std::error::Error for WfcSampleError {}
+
+/// The small map a [`WfcBuilder`] learns from: a grid of tile names.
+#[derive(Debug, Clone)]
+pub struct WfcSample {
+    width: usize,
+    height: usize,
+    /// Row by row, indices into `tiles`.
+    cells: Vec<usize>,
+    tiles: Vec<String>,
+}
+
+impl WfcSample {
+    /// A sample drawn as rows of characters, `legend` gives the tile name of every character.
+    pub fn from_rows(rows: &[&str], legend: &[(char, &str)]) -> Result<Self, WfcSampleError> {
+        let width = rows.first().map_or(0, |row| row.chars().count());
+        let mut sample = Self {
+            width,
+            height: rows.len(),
+            cells: Vec::with_capacity(width * rows.len()),
+            tiles: Vec::new(),
+        };
+        for (index, row) in rows.iter().enumerate() {
+            if row.chars().count() != width {
+                return Err(WfcSampleError::RowLength(index));
+            }
+            for c in row.chars() {
+                let (_, name) = legend
+                    .iter()
+                    .find(|(symbol, _)| *symbol == c)
+                    .ok_or(WfcSampleError::MissingLegend(c))?;
+                let tile = sample.tile_index(name);
+                sample.cells.push(tile);
+            }
+        }
+        Ok(sample)
+    }
+
+    /// A sample taken from the `extent` region of `map`, `tile_name` gives the name of a tile.
+    ///
+    /// Panics if a cell of `extent` is empty.
+    pub fn from_map<T: Tile>(
+        map: &GameMap<T>,
+        extent: &IntExtent2,
+        tile_name: impl Fn(&T) -> String,
+    ) -> Self {
+        let mut sample = Self {
+            width: extent.width() as usize,
+            height: extent.height() as usize,
+            cells: Vec::with_capacity((extent.width() * extent.height()) as usize),
+            tiles: Vec::new(),
+        };
+        for pos in extent.iter() {
+            let name = map
+                .with_tile(pos, &tile_name)
+                .unwrap_or_else(|| panic!("the sample has an empty cell at {}", pos));
+            let tile = sample.tile_index(&name);
+            sample.cells.push(tile);
+        }
+        sample
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The names of the tiles used by the sample.
+    pub fn tiles(&self) -> &[String] {
+        &self.tiles
+    }
+
+    fn tile_index(&mut self, name: &str) -> usize {
+        match self.tiles.iter().position(|tile| tile == name) {
+            Some(index) => index,
+            None => {
+                self.tiles.push(name.to_owned());
+                self.tiles.len() - 1
+            }
+        }
+    }
+}
+
+/// The patterns of a sample and which of them can overlap.
+#[derive(Debug, Clone)]
+struct WfcModel {
+    size: usize,
+    /// Row by row, indices into the tiles of the sample.
+    patterns: Vec<Vec<usize>>,
+    /// How many times every pattern appears in the sample.
+    weights: Vec<f64>,
+    /// `propagator[d][p]` are the patterns that can be placed one step in direction `d` from
+    /// pattern `p`.
+    propagator: [Vec<Vec<usize>>; 4],
+}
+
+impl WfcModel {
+    fn learn(sample: &WfcSample, size: usize, symmetry: bool) -> Self {
+        let mut index = HashMap::<Vec<usize>, usize>::new();
+        let mut patterns = Vec::<Vec<usize>>::new();
+        let mut weights = Vec::<f64>::new();
+
+        for y in 0..=sample.height - size {
+            for x in 0..=sample.width - size {
+                let pattern: Vec<usize> = (0..size * size)
+                    .map(|i| sample.cells[(y + i / size) * sample.width + x + i % size])
+                    .collect();
+
+                let mut variants = vec![pattern];
+                if symmetry {
+                    for i in 0..3 {
+                        variants.push(rotate(&variants[i], size));
+                    }
+                    for i in 0..4 {
+                        variants.push(reflect(&variants[i], size));
+                    }
+                }
+
+                for variant in variants {
+                    match index.get(&variant) {
+                        Some(pattern) => weights[*pattern] += 1.,
+                        None => {
+                            index.insert(variant.clone(), patterns.len());
+                            patterns.push(variant);
+                            weights.push(1.);
+                        }
+                    }
+                }
+            }
+        }
+
+        let propagator = std::array::from_fn(|d| {
+            let (dx, dy) = DIRECTIONS[d];
+            patterns
+                .iter()
+                .map(|pattern| {
+                    (0..patterns.len())
+                        .filter(|other| agrees(pattern, &patterns[*other], dx, dy, size))
+                        .collect()
+                })
+                .collect()
+        });
+
+        Self {
+            size,
+            patterns,
+            weights,
+            propagator,
+        }
+    }
+}
+
+/// Rotates a square pattern by a quarter turn.
+fn rotate(pattern: &[usize], size: usize) -> Vec<usize> {
+    (0..size * size)
+        .map(|i| pattern[size - 1 - i / size + (i % size) * size])
+        .collect()
+}
+
+/// Mirrors a square pattern horizontally.
+fn reflect(pattern: &[usize], size: usize) -> Vec<usize> {
+    (0..size * size)
+        .map(|i| pattern[size - 1 - i % size + (i / size) * size])
+        .collect()
+}
+
+/// Whether `other`, moved by (`dx`, `dy`), has the same tiles as `pattern` where they overlap.
+fn agrees(pattern: &[usize], other: &[usize], dx: i32, dy: i32, size: usize) -> bool {
+    let size = size as i32;
+    for y in dy.max(0)..size.min(size + dy) {
+        for x in dx.max(0)..size.min(size + dx) {
+            if pattern[(x + y * size) as usize] != other[(x - dx + (y - dy) * size) as usize] {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// The patterns still possible at every position of the output.
+struct Wave<'a> {
+    model: &'a WfcModel,
+    width: usize,
+    height: usize,
+    possible: Vec<Vec<bool>>,
+    /// `compatible[cell][p][d]` is how many patterns of the neighbor in the direction opposite
+    /// to `d` still allow `p` at `cell`: when it gets to 0, `p` is banned.
+    compatible: Vec<Vec<[usize; 4]>>,
+    counts: Vec<usize>,
+    weight_sums: Vec<f64>,
+    weight_log_sums: Vec<f64>,
+    /// The bans that have not been propagated yet.
+    stack: Vec<(usize, usize)>,
+}
+
+impl<'a> Wave<'a> {
+    fn new(model: &'a WfcModel, width: usize, height: usize) -> Self {
+        let cells = width * height;
+        let patterns = model.patterns.len();
+        let compatible: Vec<[usize; 4]> = (0..patterns)
+            .map(|pattern| std::array::from_fn(|d| model.propagator[(d + 2) % 4][pattern].len()))
+            .collect();
+        let weight_sum: f64 = model.weights.iter().sum();
+        let weight_log_sum: f64 = model.weights.iter().map(|w| w * w.ln()).sum();
+
+        Self {
+            model,
+            width,
+            height,
+            possible: vec![vec![true; patterns]; cells],
+            compatible: vec![compatible; cells],
+            counts: vec![patterns; cells],
+            weight_sums: vec![weight_sum; cells],
+            weight_log_sums: vec![weight_log_sum; cells],
+            stack: Vec::new(),
+        }
+    }
+
+    fn ban(&mut self, cell: usize, pattern: usize) {
+        let weight = self.model.weights[pattern];
+        self.possible[cell][pattern] = false;
+        self.compatible[cell][pattern] = [0; 4];
+        self.counts[cell] -= 1;
+        self.weight_sums[cell] -= weight;
+        self.weight_log_sums[cell] -= weight * weight.ln();
+        self.stack.push((cell, pattern));
+    }
+
+    /// Removes the patterns that don't fit anymore, returns `false` on a contradiction.
+    fn propagate(&mut self) -> bool {
+        let model = self.model;
+        while let Some((cell, pattern)) = self.stack.pop() {
+            let (x, y) = ((cell % self.width) as i32, (cell / self.width) as i32);
+            for (d, (dx, dy)) in DIRECTIONS.iter().enumerate() {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || ny < 0 || nx >= self.width as i32 || ny >= self.height as i32 {
+                    continue;
+                }
+                let neighbor = ny as usize * self.width + nx as usize;
+                for other in &model.propagator[d][pattern] {
+                    let compatible = &mut self.compatible[neighbor][*other][d];
+                    if *compatible == 0 {
+                        continue;
+                    }
+                    *compatible -= 1;
+                    if *compatible == 0 {
+                        self.ban(neighbor, *other);
+                        if self.counts[neighbor] == 0 {
+                            return false;
+                        }
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Collapses the whole wave, returns the pattern of every position or `None` on a
+    /// contradiction.
+    fn run(mut self, rng: &mut impl Rng) -> Option<Vec<usize>> {
+        loop {
+            // the undecided cell with the lowest entropy, a little noise breaks the ties
+            let mut lowest: Option<(f64, usize)> = None;
+            for cell in 0..self.counts.len() {
+                match self.counts[cell] {
+                    0 => return None,
+                    1 => continue,
+                    _ => {
+                        let sum = self.weight_sums[cell];
+                        let entropy =
+                            sum.ln() - self.weight_log_sums[cell] / sum + rng.gen::<f64>() * 1e-6;
+                        if lowest.is_none_or(|(lowest, _)| entropy < lowest) {
+                            lowest = Some((entropy, cell));
+                        }
+                    }
+                }
+            }
+
+            let Some((_, cell)) = lowest else {
+                break;
+            };
+
+            let weights = (0..self.model.patterns.len()).map(|pattern| {
+                if self.possible[cell][pattern] {
+                    self.model.weights[pattern]
+                } else {
+                    0.
+                }
+            });
+            let chosen = WeightedIndex::new(weights).unwrap().sample(rng);
+            for pattern in 0..self.model.patterns.len() {
+                if pattern != chosen && self.possible[cell][pattern] {
+                    self.ban(cell, pattern);
+                }
+            }
+            if !self.propagate() {
+                return None;
+            }
+        }
+
+        self.possible
+            .iter()
+            .map(|possible| possible.iter().position(|p| *p))
+            .collect()
+    }
+}
+
+/// Wave Function Collapse builder, using the overlapping model.
+///
+/// Every `pattern_size` x `pattern_size` square of the sample is a pattern, optionally with its
+/// rotations and reflections. The region is then filled so that each of its squares is one of
+/// the patterns, and patterns appearing more often in the sample are more likely.
+///
+/// The generation starts over when it gets to a contradiction, up to `max_attempts` times; if
+/// every attempt fails the map is left untouched.
+#[derive(Debug, Clone)]
+pub struct WfcBuilder<T>
+where
+    T: Tile,
+{
+    sample: WfcSample,
+    extent: Option<IntExtent2>,
+    pattern_size: usize,
+    symmetry: bool,
+    max_attempts: u32,
+    _marker: std::marker::PhantomData<T>,
+}
+
+impl<T> WfcBuilder<T>
+where
+    T: Tile,
+{
+    pub fn new(sample: WfcSample) -> Self {
+        Self {
+            sample,
+            extent: None,
+            pattern_size: 3,
+            symmetry: true,
+            max_attempts: 10,
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Sets the region to generate, by default `MapBuilder::extent`.
+    pub fn with_extent(mut self, extent: IntExtent2) -> Self {
+        self.extent = Some(extent);
+        self
+    }
+
+    /// Sets the side of the patterns. It is clamped to the size of the sample.
+    pub fn with_pattern_size(mut self, pattern_size: usize) -> Self {
+        self.pattern_size = pattern_size;
+        self
+    }
+
+    /// Sets whether the rotations and reflections of the patterns are used too.
+    pub fn with_symmetry(mut self, symmetry: bool) -> Self {
+        self.symmetry = symmetry;
+        self
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    fn model(&self) -> WfcModel {
+        let size = self
+            .pattern_size
+            .clamp(1, self.sample.width.min(self.sample.height).max(1));
+        WfcModel::learn(&self.sample, size, self.symmetry)
+    }
+}
+
+impl<T: Tile> MapBuilderAlgorithm<T> for WfcBuilder<T> {
+    fn build<'a>(&self, map_builder: &'a mut MapBuilder<T>) -> &'a mut MapBuilder<T> {
+        let extent = self.extent.unwrap_or(map_builder.extent);
+        if self.sample.cells.is_empty() {
+            return map_builder;
+        }
+        let model = self.model();
+        let size = model.size;
+        let (width, height) = (extent.width() as usize, extent.height() as usize);
+        if width < size || height < size {
+            return map_builder;
+        }
+
+        let tiles: Vec<T> = self
+            .sample
+            .tiles
+            .iter()
+            .map(|name| map_builder.tile(name))
+            .collect();
+
+        // a pattern at every position where it fits entirely inside the region
+        let (wave_width, wave_height) = (width - size + 1, height - size + 1);
+        let result = (0..self.max_attempts)
+            .find_map(|_| Wave::new(&model, wave_width, wave_height).run(map_builder.rng()));
+        let Some(result) = result else {
+            return map_builder;
+        };
+
+        for y in 0..height {
+            for x in 0..width {
+                // the cells of the last rows and columns come from the patterns before them
+                let (wx, wy) = (x.min(wave_width - 1), y.min(wave_height - 1));
+                let pattern = &model.patterns[result[wy * wave_width + wx]];
+                let tile = pattern[(y - wy) * size + x - wx];
+                let pos = IntVector2::new(extent.left() + x as i32, extent.top() + y as i32);
+                map_builder.map.set(pos.x, pos.y, tiles[tile].clone());
+            }
+        }
+
+        map_builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{map_from_rows, TestTile};
+
+    const SAMPLE: [&str; 8] = [
+        "........", //
+        ".##..##.", //
+        ".##..##.", //
+        "........", //
+        "........", //
+        ".##..##.", //
+        ".##..##.", //
+        "........", //
+    ];
+    const LEGEND: [(char, &str); 2] = [('.', "floor"), ('#', "wall")];
+
+    fn build(seed: u64) -> MapBuilder<TestTile> {
+        let mut map_builder =
+            MapBuilder::<TestTile>::new(IntExtent2::new(-5, 3, 20, 16)).with_seed(seed);
+        map_builder.add_tile("floor".to_owned(), TestTile::Floor);
+        map_builder.add_tile("wall".to_owned(), TestTile::Wall);
+        map_builder.build_step(&WfcBuilder::new(
+            WfcSample::from_rows(&SAMPLE, &LEGEND).unwrap(),
+        ));
+        map_builder
+    }
+
+    #[test]
+    fn test_rotate_reflect() {
+        // 0 1
+        // 2 3
+        let pattern = vec![0, 1, 2, 3];
+        assert_eq!(rotate(&pattern, 2), vec![1, 3, 0, 2]);
+        assert_eq!(reflect(&pattern, 2), vec![1, 0, 3, 2]);
+        assert!(agrees(&[0, 1, 0, 1], &[1, 2, 1, 2], 1, 0, 2));
+        assert!(!agrees(&[0, 1, 0, 1], &[1, 2, 1, 2], 0, 1, 2));
+    }
+
+    #[test]
+    fn test_wfc_patterns() {
+        let map_builder = build(3);
+        let extent = map_builder.extent;
+        assert!(extent
+            .iter()
+            .all(|pos| map_builder.map.get(pos.x, pos.y).is_some()));
+
+        // every 3x3 square of the result appears in the sample
+        let model = WfcModel::learn(&WfcSample::from_rows(&SAMPLE, &LEGEND).unwrap(), 3, true);
+        for y in extent.top()..extent.bottom() - 2 {
+            for x in extent.left()..extent.right() - 2 {
+                let square: Vec<usize> = (0..9)
+                    .map(|i| match map_builder.map.get(x + i % 3, y + i / 3) {
+                        Some(TestTile::Floor) => 0,
+                        _ => 1,
+                    })
+                    .collect();
+                assert!(model.patterns.contains(&square));
+            }
+        }
+    }
+
+    #[test]
+    fn test_wfc_same_seed() {
+        let a = build(11);
+        let b = build(11);
+        assert!(a
+            .extent
+            .iter()
+            .all(|pos| a.map.get(pos.x, pos.y) == b.map.get(pos.x, pos.y)));
+    }
+
+    #[test]
+    fn test_sample_from_map() {
+        let map = map_from_rows(&SAMPLE);
+        let sample = WfcSample::from_map(&map, &IntExtent2::new(0, 0, 4, 4), |tile| {
+            match tile {
+                TestTile::Wall => "wall",
+                _ => "floor",
+            }
+            .to_owned()
+        });
+        assert_eq!((sample.width(), sample.height()), (4, 4));
+        assert_eq!(sample.tiles(), ["floor", "wall"]);
+        assert_eq!(sample.cells[5], 1);
+    }
+}
@@ -0,0 +1,404 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use crate::{
+    prelude::{Plane, Tile},
+    IntVector2,
+};
+
+const FOUR_NEIGHBORS: [IntVector2; 4] = [
+    IntVector2::new(0, -1),
+    IntVector2::new(1, 0),
+    IntVector2::new(0, 1),
+    IntVector2::new(-1, 0),
+];
+
+const EIGHT_NEIGHBORS: [IntVector2; 8] = [
+    IntVector2::new(0, -1),
+    IntVector2::new(1, 0),
+    IntVector2::new(0, 1),
+    IntVector2::new(-1, 0),
+    IntVector2::new(1, -1),
+    IntVector2::new(1, 1),
+    IntVector2::new(-1, 1),
+    IntVector2::new(-1, -1),
+];
+
+/// Which cells are considered adjacent when moving on the grid.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Connectivity {
+    /// Orthogonal moves only.
+    Four,
+    /// Orthogonal and diagonal moves.
+    #[default]
+    Eight,
+}
+
+impl Connectivity {
+    /// The offsets of the neighbors of a cell. Orthogonal offsets come first.
+    pub fn offsets(&self) -> &'static [IntVector2] {
+        match self {
+            Connectivity::Four => &FOUR_NEIGHBORS,
+            Connectivity::Eight => &EIGHT_NEIGHBORS,
+        }
+    }
+}
+
+/// The estimate of the remaining cost used to guide the search.
+#[derive(Debug, Copy, Clone)]
+pub enum Heuristic {
+    Manhattan,
+    Chebyshev,
+    /// Like `Chebyshev`, but diagonal steps cost `diagonal_cost`.
+    Octile,
+    Euclidean,
+    Custom(fn(IntVector2, IntVector2) -> f32),
+}
+
+impl Heuristic {
+    pub fn estimate(&self, from: IntVector2, to: IntVector2, diagonal_cost: f32) -> f32 {
+        let dx = (from.x - to.x).abs() as f32;
+        let dy = (from.y - to.y).abs() as f32;
+        match self {
+            Heuristic::Manhattan => dx + dy,
+            Heuristic::Chebyshev => dx.max(dy),
+            Heuristic::Octile => dx.max(dy) + (diagonal_cost - 1.) * dx.min(dy),
+            Heuristic::Euclidean => (dx * dx + dy * dy).sqrt(),
+            Heuristic::Custom(f) => f(from, to),
+        }
+    }
+}
+
+/// A* pathfinding over a [`Plane`].
+///
+/// Diagonal moves are not allowed to cut corners: both orthogonal cells next to the move
+/// must be passable.
+#[derive(Debug, Copy, Clone)]
+pub struct AStar {
+    pub connectivity: Connectivity,
+    /// Multiplier applied to the cost of diagonal moves.
+    pub diagonal_cost: f32,
+    pub heuristic: Heuristic,
+    /// Cells farther than this (Chebyshev distance) from the start are not explored.
+    pub search_radius: Option<u32>,
+    /// The search gives up after expanding this many nodes.
+    pub node_limit: Option<usize>,
+}
+
+impl Default for AStar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AStar {
+    pub fn new() -> Self {
+        Self {
+            connectivity: Connectivity::Eight,
+            diagonal_cost: std::f32::consts::SQRT_2,
+            heuristic: Heuristic::Octile,
+            search_radius: None,
+            node_limit: None,
+        }
+    }
+
+    pub fn with_connectivity(mut self, connectivity: Connectivity) -> Self {
+        self.connectivity = connectivity;
+        self
+    }
+
+    pub fn with_diagonal_cost(mut self, diagonal_cost: f32) -> Self {
+        self.diagonal_cost = diagonal_cost;
+        self
+    }
+
+    pub fn with_heuristic(mut self, heuristic: Heuristic) -> Self {
+        self.heuristic = heuristic;
+        self
+    }
+
+    pub fn with_search_radius(mut self, search_radius: u32) -> Self {
+        self.search_radius = Some(search_radius);
+        self
+    }
+
+    pub fn with_node_limit(mut self, node_limit: usize) -> Self {
+        self.node_limit = Some(node_limit);
+        self
+    }
+
+    /// Finds a path between two cells of `plane` walking only on walkable tiles, using
+    /// [`Tile::movement_cost`] as the cost of entering a tile.
+    ///
+    /// The returned path starts with `start` and ends with `goal`.
+    pub fn find_path<T: Tile, P: Plane<T>>(
+        &self,
+        plane: &P,
+        start: IntVector2,
+        goal: IntVector2,
+    ) -> Option<Vec<IntVector2>> {
+        self.find_path_with_cost(plane, start, goal, |_, tile| {
+            tile.is_walkable().then(|| tile.movement_cost())
+        })
+    }
+
+    /// Same as [`AStar::find_path`], but the cost of entering a tile is given by `cost`.
+    /// Tiles for which `cost` returns `None` are impassable.
+    pub fn find_path_with_cost<T, P, F>(
+        &self,
+        plane: &P,
+        start: IntVector2,
+        goal: IntVector2,
+        cost: F,
+    ) -> Option<Vec<IntVector2>>
+    where
+        P: Plane<T>,
+        F: Fn(IntVector2, &T) -> Option<f32>,
+    {
+        self.search(start, goal, |pos| {
+            plane.at(pos).and_then(|tile| cost(pos, tile))
+        })
+    }
+
+    /// Finds a path between `start` and `goal` on an abstract grid: `cost` returns the cost of
+    /// entering a cell, or `None` if the cell can't be entered.
+    pub fn search<F>(&self, start: IntVector2, goal: IntVector2, cost: F) -> Option<Vec<IntVector2>>
+    where
+        F: Fn(IntVector2) -> Option<f32>,
+    {
+        let mut open = BinaryHeap::new();
+        let mut came_from = HashMap::<IntVector2, IntVector2>::new();
+        let mut g_score = HashMap::<IntVector2, f32>::new();
+        let mut closed = HashSet::<IntVector2>::new();
+
+        g_score.insert(start, 0.);
+        open.push(OpenNode {
+            f: self.heuristic.estimate(start, goal, self.diagonal_cost),
+            g: 0.,
+            pos: start,
+        });
+
+        while let Some(OpenNode { g, pos, .. }) = open.pop() {
+            if pos == goal {
+                return Some(Self::reconstruct_path(&came_from, goal));
+            }
+
+            if !closed.insert(pos) {
+                continue;
+            }
+
+            if let Some(node_limit) = self.node_limit {
+                if closed.len() > node_limit {
+                    return None;
+                }
+            }
+
+            for offset in self.connectivity.offsets() {
+                let next = pos + *offset;
+
+                if closed.contains(&next) || !self.is_in_search_radius(start, next) {
+                    continue;
+                }
+
+                let diagonal = offset.x != 0 && offset.y != 0;
+                if diagonal
+                    && (cost(IntVector2::new(next.x, pos.y)).is_none()
+                        || cost(IntVector2::new(pos.x, next.y)).is_none())
+                {
+                    continue;
+                }
+
+                let Some(step_cost) = cost(next) else {
+                    continue;
+                };
+                let step_cost = if diagonal {
+                    step_cost * self.diagonal_cost
+                } else {
+                    step_cost
+                };
+
+                let tentative_g = g + step_cost;
+                if g_score
+                    .get(&next)
+                    .is_some_and(|current| *current <= tentative_g)
+                {
+                    continue;
+                }
+
+                g_score.insert(next, tentative_g);
+                came_from.insert(next, pos);
+                open.push(OpenNode {
+                    f: tentative_g + self.heuristic.estimate(next, goal, self.diagonal_cost),
+                    g: tentative_g,
+                    pos: next,
+                });
+            }
+        }
+
+        None
+    }
+
+    fn is_in_search_radius(&self, start: IntVector2, pos: IntVector2) -> bool {
+        match self.search_radius {
+            Some(radius) => {
+                (pos.x - start.x)
+                    .unsigned_abs()
+                    .max((pos.y - start.y).unsigned_abs())
+                    <= radius
+            }
+            None => true,
+        }
+    }
+
+    fn reconstruct_path(
+        came_from: &HashMap<IntVector2, IntVector2>,
+        goal: IntVector2,
+    ) -> Vec<IntVector2> {
+        let mut path = vec![goal];
+        let mut current = goal;
+        while let Some(previous) = came_from.get(&current) {
+            path.push(*previous);
+            current = *previous;
+        }
+        path.reverse();
+        path
+    }
+}
+
+/// An entry of the open set, ordered so that `BinaryHeap` pops the lowest `f` first.
+#[derive(Debug, Copy, Clone)]
+struct OpenNode {
+    f: f32,
+    g: f32,
+    pos: IntVector2,
+}
+
+impl PartialEq for OpenNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for OpenNode {}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .f
+            .total_cmp(&self.f)
+            // prefer the deepest node on ties, it is closer to the goal
+            .then_with(|| self.g.total_cmp(&other.g))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{prelude::Walkable, test_utils::grid_from_rows};
+
+    #[test]
+    fn test_straight_path() {
+        let grid = grid_from_rows(&[
+            ".....", //
+        ]);
+
+        let path = AStar::new()
+            .find_path(&grid, IntVector2::new(0, 0), IntVector2::new(4, 0))
+            .unwrap();
+        assert_eq!(path.len(), 5);
+        assert_eq!(path[0], IntVector2::new(0, 0));
+        assert_eq!(path[4], IntVector2::new(4, 0));
+    }
+
+    #[test]
+    fn test_path_around_walls() {
+        let grid = grid_from_rows(&[
+            ".....", //
+            ".###.", //
+            "...#.", //
+        ]);
+        let start = IntVector2::new(0, 2);
+        let goal = IntVector2::new(4, 2);
+
+        let path = AStar::new().find_path(&grid, start, goal).unwrap();
+        assert_eq!(path.first(), Some(&start));
+        assert_eq!(path.last(), Some(&goal));
+        assert!(path.iter().all(|p| grid.at(*p).unwrap().is_walkable()));
+
+        let path = AStar::new()
+            .with_connectivity(Connectivity::Four)
+            .find_path(&grid, start, goal)
+            .unwrap();
+        assert_eq!(path.len(), 9);
+        assert!(path
+            .windows(2)
+            .all(|w| (w[0].x - w[1].x).abs() + (w[0].y - w[1].y).abs() == 1));
+    }
+
+    #[test]
+    fn test_no_path() {
+        let grid = grid_from_rows(&[
+            "..#..", //
+            "..#..", //
+        ]);
+
+        let path = AStar::new().find_path(&grid, IntVector2::new(0, 0), IntVector2::new(4, 0));
+        assert_eq!(path, None);
+    }
+
+    #[test]
+    fn test_no_corner_cutting() {
+        let grid = grid_from_rows(&[
+            ".#", //
+            "#.", //
+        ]);
+
+        let path = AStar::new().find_path(&grid, IntVector2::new(0, 0), IntVector2::new(1, 1));
+        assert_eq!(path, None);
+    }
+
+    #[test]
+    fn test_movement_cost() {
+        let grid = grid_from_rows(&[
+            ".....", //
+            ".~~~.", //
+            ".....", //
+        ]);
+        let start = IntVector2::new(0, 1);
+        let goal = IntVector2::new(4, 1);
+
+        let path = AStar::new()
+            .with_connectivity(Connectivity::Four)
+            .find_path(&grid, start, goal)
+            .unwrap();
+        assert!(path.iter().all(|p| p.y != 1 || p.x == 0 || p.x == 4));
+    }
+
+    #[test]
+    fn test_limits() {
+        let grid = grid_from_rows(&[
+            "..........", //
+            "..........", //
+        ]);
+        let start = IntVector2::new(0, 0);
+        let goal = IntVector2::new(9, 0);
+
+        assert!(AStar::new().find_path(&grid, start, goal).is_some());
+        assert!(AStar::new()
+            .with_search_radius(5)
+            .find_path(&grid, start, goal)
+            .is_none());
+        assert!(AStar::new()
+            .with_node_limit(3)
+            .find_path(&grid, start, goal)
+            .is_none());
+    }
+}
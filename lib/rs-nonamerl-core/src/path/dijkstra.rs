@@ -0,0 +1,337 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+};
+
+use crate::{
+    prelude::{Plane, Tile},
+    IntVector2,
+};
+
+use super::Connectivity;
+
+/// A distance field over a [`Plane`]: for every reachable cell, the cost of the cheapest path
+/// to the nearest goal.
+///
+/// Entities "roll downhill" towards the goals with [`DijkstraMap::downhill`]; a flee map built
+/// by [`DijkstraMap::flee_map`] makes them run away from the goals instead.
+#[derive(Debug, Clone)]
+pub struct DijkstraMap {
+    pub connectivity: Connectivity,
+    /// Multiplier applied to the cost of diagonal moves.
+    pub diagonal_cost: f32,
+    /// Cells farther than this from every goal are not part of the map.
+    pub max_distance: Option<f32>,
+    values: HashMap<IntVector2, f32>,
+}
+
+impl Default for DijkstraMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DijkstraMap {
+    pub fn new() -> Self {
+        Self {
+            connectivity: Connectivity::Eight,
+            diagonal_cost: std::f32::consts::SQRT_2,
+            max_distance: None,
+            values: HashMap::new(),
+        }
+    }
+
+    pub fn with_connectivity(mut self, connectivity: Connectivity) -> Self {
+        self.connectivity = connectivity;
+        self
+    }
+
+    pub fn with_diagonal_cost(mut self, diagonal_cost: f32) -> Self {
+        self.diagonal_cost = diagonal_cost;
+        self
+    }
+
+    pub fn with_max_distance(mut self, max_distance: f32) -> Self {
+        self.max_distance = Some(max_distance);
+        self
+    }
+
+    /// Computes the distance from `goals` over the walkable tiles of `plane`, using
+    /// [`Tile::movement_cost`] as the cost of entering a tile.
+    pub fn compute<T: Tile, P: Plane<T>>(self, plane: &P, goals: &[IntVector2]) -> Self {
+        self.compute_with_cost(plane, goals, Self::walkable_cost)
+    }
+
+    /// Same as [`DijkstraMap::compute`], but the cost of entering a tile is given by `cost`.
+    /// Tiles for which `cost` returns `None` are impassable.
+    pub fn compute_with_cost<T, P, F>(mut self, plane: &P, goals: &[IntVector2], cost: F) -> Self
+    where
+        P: Plane<T>,
+        F: Fn(IntVector2, &T) -> Option<f32>,
+    {
+        let seeds = goals.iter().map(|goal| (*goal, 0.)).collect();
+        self.values = self.scan(seeds, |pos| plane.at(pos).and_then(|tile| cost(pos, tile)));
+        self
+    }
+
+    /// Builds a map that leads away from the goals.
+    ///
+    /// The values are multiplied by `coefficient` (a negative number, usually around -1.2) and
+    /// the field is scanned again, so that fleeing entities prefer escape routes over dead ends.
+    pub fn flee_map<T: Tile, P: Plane<T>>(&self, plane: &P, coefficient: f32) -> Self {
+        self.flee_map_with_cost(plane, coefficient, Self::walkable_cost)
+    }
+
+    /// Same as [`DijkstraMap::flee_map`], with a custom cost function.
+    pub fn flee_map_with_cost<T, P, F>(&self, plane: &P, coefficient: f32, cost: F) -> Self
+    where
+        P: Plane<T>,
+        F: Fn(IntVector2, &T) -> Option<f32>,
+    {
+        let seeds = self
+            .values
+            .iter()
+            .map(|(pos, value)| (*pos, value * coefficient))
+            .collect();
+
+        let mut flee_map = Self {
+            max_distance: None,
+            values: HashMap::new(),
+            ..*self
+        };
+        flee_map.values =
+            flee_map.scan(seeds, |pos| plane.at(pos).and_then(|tile| cost(pos, tile)));
+        flee_map
+    }
+
+    pub fn get(&self, position: IntVector2) -> Option<f32> {
+        self.values.get(&position).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&IntVector2, &f32)> {
+        self.values.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the neighbor of `position` with the lowest value, if it is lower than the value
+    /// of `position`.
+    pub fn downhill(&self, position: IntVector2) -> Option<IntVector2> {
+        let current = self.get(position)?;
+        self.connectivity
+            .offsets()
+            .iter()
+            .map(|offset| position + *offset)
+            .filter_map(|neighbor| self.get(neighbor).map(|value| (neighbor, value)))
+            .filter(|(_, value)| *value < current)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(neighbor, _)| neighbor)
+    }
+
+    /// Returns the neighbor of `position` with the highest value, if it is higher than the
+    /// value of `position`.
+    pub fn uphill(&self, position: IntVector2) -> Option<IntVector2> {
+        let current = self.get(position)?;
+        self.connectivity
+            .offsets()
+            .iter()
+            .map(|offset| position + *offset)
+            .filter_map(|neighbor| self.get(neighbor).map(|value| (neighbor, value)))
+            .filter(|(_, value)| *value > current)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(neighbor, _)| neighbor)
+    }
+
+    /// Rolls downhill from `position` until a local minimum (a goal, for a distance map) is
+    /// reached. The returned path starts with `position`.
+    pub fn path_from(&self, position: IntVector2) -> Vec<IntVector2> {
+        let mut path = vec![position];
+        let mut current = position;
+        while let Some(next) = self.downhill(current) {
+            path.push(next);
+            current = next;
+        }
+        path
+    }
+
+    /// The cell with the highest value, e.g. the reachable cell farthest from the goals.
+    pub fn farthest(&self) -> Option<(IntVector2, f32)> {
+        self.values
+            .iter()
+            .max_by(|(pos_a, a), (pos_b, b)| {
+                // break ties on the position so that the result doesn't depend on the hash order
+                a.total_cmp(b)
+                    .then_with(|| (pos_b.y, pos_b.x).cmp(&(pos_a.y, pos_a.x)))
+            })
+            .map(|(pos, value)| (*pos, *value))
+    }
+
+    fn walkable_cost<T: Tile>(_: IntVector2, tile: &T) -> Option<f32> {
+        tile.is_walkable().then(|| tile.movement_cost())
+    }
+
+    fn scan<F>(&self, seeds: Vec<(IntVector2, f32)>, cost: F) -> HashMap<IntVector2, f32>
+    where
+        F: Fn(IntVector2) -> Option<f32>,
+    {
+        let mut values = HashMap::<IntVector2, f32>::new();
+        let mut open = BinaryHeap::new();
+
+        for (pos, value) in seeds {
+            if cost(pos).is_some() && values.get(&pos).is_none_or(|current| value < *current) {
+                values.insert(pos, value);
+                open.push(ScanNode { value, pos });
+            }
+        }
+
+        while let Some(ScanNode { value, pos }) = open.pop() {
+            if values.get(&pos).is_some_and(|current| *current < value) {
+                continue;
+            }
+
+            for offset in self.connectivity.offsets() {
+                let next = pos + *offset;
+                let diagonal = offset.x != 0 && offset.y != 0;
+                if diagonal
+                    && (cost(IntVector2::new(next.x, pos.y)).is_none()
+                        || cost(IntVector2::new(pos.x, next.y)).is_none())
+                {
+                    continue;
+                }
+
+                let Some(step_cost) = cost(next) else {
+                    continue;
+                };
+                let next_value = value
+                    + if diagonal {
+                        step_cost * self.diagonal_cost
+                    } else {
+                        step_cost
+                    };
+
+                if self.max_distance.is_some_and(|max| next_value > max) {
+                    continue;
+                }
+                if values
+                    .get(&next)
+                    .is_some_and(|current| *current <= next_value)
+                {
+                    continue;
+                }
+
+                values.insert(next, next_value);
+                open.push(ScanNode {
+                    value: next_value,
+                    pos: next,
+                });
+            }
+        }
+
+        values
+    }
+}
+
+/// An entry of the scan frontier, ordered so that `BinaryHeap` pops the lowest value first.
+#[derive(Debug, Copy, Clone)]
+struct ScanNode {
+    value: f32,
+    pos: IntVector2,
+}
+
+impl PartialEq for ScanNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ScanNode {}
+
+impl PartialOrd for ScanNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ScanNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.value.total_cmp(&self.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::grid_from_rows;
+
+    #[test]
+    fn test_multiple_sources() {
+        let grid = grid_from_rows(&[
+            "..........", //
+        ]);
+
+        let map = DijkstraMap::new()
+            .with_connectivity(Connectivity::Four)
+            .compute(&grid, &[IntVector2::new(0, 0), IntVector2::new(9, 0)]);
+        assert_eq!(map.len(), 10);
+        assert_eq!(map.get(IntVector2::new(0, 0)), Some(0.));
+        assert_eq!(map.get(IntVector2::new(3, 0)), Some(3.));
+        assert_eq!(map.get(IntVector2::new(7, 0)), Some(2.));
+        assert_eq!(map.get(IntVector2::new(10, 0)), None);
+    }
+
+    #[test]
+    fn test_roll_downhill() {
+        let grid = grid_from_rows(&[
+            ".....", //
+            ".###.", //
+            "...#.", //
+        ]);
+        let goal = IntVector2::new(4, 2);
+
+        let map = DijkstraMap::new()
+            .with_connectivity(Connectivity::Four)
+            .compute(&grid, &[goal]);
+        assert_eq!(map.get(IntVector2::new(1, 1)), None);
+
+        let path = map.path_from(IntVector2::new(0, 2));
+        assert_eq!(path.len(), 9);
+        assert_eq!(path.last(), Some(&goal));
+        assert_eq!(map.farthest(), Some((IntVector2::new(2, 2), 10.)));
+    }
+
+    #[test]
+    fn test_flee_map() {
+        let grid = grid_from_rows(&[
+            "#########", //
+            "#.......#", //
+            "#########", //
+        ]);
+        let threat = IntVector2::new(3, 1);
+
+        let map = DijkstraMap::new().compute(&grid, &[threat]);
+        let flee_map = map.flee_map(&grid, -1.2);
+
+        let next = flee_map.downhill(IntVector2::new(4, 1)).unwrap();
+        assert_eq!(next, IntVector2::new(5, 1));
+        let path = flee_map.path_from(IntVector2::new(4, 1));
+        assert_eq!(path.last(), Some(&IntVector2::new(7, 1)));
+    }
+
+    #[test]
+    fn test_max_distance() {
+        let grid = grid_from_rows(&[
+            "..........", //
+        ]);
+
+        let map = DijkstraMap::new()
+            .with_max_distance(3.)
+            .compute(&grid, &[IntVector2::new(0, 0)]);
+        assert_eq!(map.len(), 4);
+    }
+}
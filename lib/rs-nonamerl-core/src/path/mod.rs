@@ -0,0 +1,5 @@
+mod astar;
+mod dijkstra;
+
+pub use astar::*;
+pub use dijkstra::*;
@@ -6,7 +6,7 @@ use macroquad::texture::{draw_texture_ex, DrawTextureParams, Texture2D};
 
 use crate::camera::{Camera, Camera2D, TestCamera2D, Viewport};
 use crate::prelude::{GameMap, SpriteContainer, Tile, TileSpriteInfo};
-use crate::{Dimension2, IntVector2};
+use crate::{Dimension2, IntExtent2, IntVector2};
 
 #[derive(Debug, Copy, Clone)]
 pub enum RenderOp<T: Tile> {
@@ -96,127 +96,7 @@ impl Renderer {
                     );
                 }
                 RenderOp::DrawTile(x, y, tile) => {
-                    let (viewport_x, viewport_y) =
-                        camera.tile_to_viewport(IntVector2::new(*x, *y)).into();
-
-                    let sprite_info = tile.sprite_info();
-
-                    let scaled_cell_width = self.cell_size.width() as f32 / camera.zoom_scale;
-                    let scaled_cell_height = self.cell_size.height() as f32 / camera.zoom_scale;
-
-                    match sprite_info {
-                        TileSpriteInfo::None => {}
-                        TileSpriteInfo::Fill(color) => {
-                            draw_rectangle(
-                                viewport_x,
-                                viewport_y,
-                                camera_cell_size_x,
-                                camera_cell_size_y,
-                                color,
-                            );
-                        }
-                        TileSpriteInfo::SpriteSheet(name) => {
-                            let (rect, texture) = sprites.get_sprite(name);
-
-                            draw_texture_ex(
-                                texture,
-                                viewport_x,
-                                viewport_y,
-                                WHITE,
-                                DrawTextureParams {
-                                    source: Some(*rect),
-                                    dest_size: Some(Vec2::new(
-                                        camera_cell_size_x,
-                                        camera_cell_size_y,
-                                    )),
-                                    ..Default::default()
-                                },
-                            );
-                        }
-                        TileSpriteInfo::SingleSprite(texture) => {
-                            draw_texture_ex(
-                                &texture,
-                                viewport_x,
-                                viewport_y,
-                                WHITE,
-                                DrawTextureParams {
-                                    dest_size: Some(Vec2::new(
-                                        camera_cell_size_x,
-                                        camera_cell_size_y,
-                                    )),
-                                    ..Default::default()
-                                },
-                            );
-                        }
-                    };
-
-                    if let Some(items) = tile.items() {
-                        draw_text_ex(
-                            &format!("{}", items.len()),
-                            viewport_x + scaled_cell_width / 2.0,
-                            viewport_y + scaled_cell_height / 2.0,
-                            Default::default(),
-                        );
-                    }
-                    // if !tile.is_visited() {
-                    //     draw_rectangle(
-                    //         viewport_x,
-                    //         viewport_y,
-                    //         self.cell_size.width() as f32 / camera.zoom_scale,
-                    //         self.cell_size.height() as f32 / camera.zoom_scale,
-                    //         Color {
-                    //             r: 0.0,
-                    //             g: 0.0,
-                    //             b: 0.0,
-                    //             a: 0.8,
-                    //         },
-                    //     );
-                    // }
-
-                    match (tile.is_visible(), tile.is_visited()) {
-                        (false, true) => {
-                            draw_rectangle(
-                                viewport_x,
-                                viewport_y,
-                                camera_cell_size_x,
-                                camera_cell_size_y,
-                                Color {
-                                    r: 0.0,
-                                    g: 0.0,
-                                    b: 0.0,
-                                    a: 0.3,
-                                },
-                            );
-                        }
-                        (false, false) => {
-                            draw_rectangle(
-                                viewport_x,
-                                viewport_y,
-                                camera_cell_size_x,
-                                camera_cell_size_y,
-                                Color {
-                                    r: 0.0,
-                                    g: 0.0,
-                                    b: 0.0,
-                                    a: 0.6,
-                                },
-                            );
-                        }
-                        (_, _) => {}
-                    }
-
-                    // draw_rectangle(
-                    //     viewport_x,
-                    //     viewport_y,
-                    //     self.cell_size.width() as f32 * camera.zoom_scale,
-                    //     self.cell_size.height() as f32 * camera.zoom_scale,
-                    //     Color {
-                    //         r: 1.0,
-                    //         g: 0.,
-                    //         b: 0.,
-                    //         a: 0.6,
-                    //     },
-                    // );
+                    self.draw_tile(camera, sprites, IntVector2::new(*x, *y), tile);
                 }
                 RenderOp::DrawRectangle => {}
                 RenderOp::DrawCircle => {}
@@ -240,4 +120,159 @@ impl Renderer {
             }
         }
     }
+
+    /// Draws the tiles of `extent` straight from `game_map`, under a single read lock and
+    /// without cloning them.
+    pub fn render_map_region<T: Tile>(
+        &self,
+        camera: &TestCamera2D,
+        sprites: &SpriteContainer,
+        game_map: &GameMap<T>,
+        extent: &IntExtent2,
+    ) {
+        game_map.for_each_in(extent, |position, tile| {
+            self.draw_tile(camera, sprites, position, tile)
+        });
+    }
+
+    fn draw_tile<T: Tile>(
+        &self,
+        camera: &TestCamera2D,
+        sprites: &SpriteContainer,
+        position: IntVector2,
+        tile: &T,
+    ) {
+        let camera_cell_size_x = camera.cell_size.width() as f32 * camera.zoom_scale;
+        let camera_cell_size_y = camera.cell_size.height() as f32 * camera.zoom_scale;
+        let (viewport_x, viewport_y) = camera.tile_to_viewport(position).into();
+
+        let sprite_info = tile.sprite_info();
+
+        let scaled_cell_width = self.cell_size.width() as f32 / camera.zoom_scale;
+        let scaled_cell_height = self.cell_size.height() as f32 / camera.zoom_scale;
+
+        match sprite_info {
+            TileSpriteInfo::None => {}
+            TileSpriteInfo::Fill(color) => {
+                draw_rectangle(
+                    viewport_x,
+                    viewport_y,
+                    camera_cell_size_x,
+                    camera_cell_size_y,
+                    color,
+                );
+            }
+            TileSpriteInfo::SpriteSheet(name) => {
+                let (rect, texture) = sprites.get_sprite(name);
+
+                draw_texture_ex(
+                    texture,
+                    viewport_x,
+                    viewport_y,
+                    WHITE,
+                    DrawTextureParams {
+                        source: Some(*rect),
+                        dest_size: Some(Vec2::new(camera_cell_size_x, camera_cell_size_y)),
+                        ..Default::default()
+                    },
+                );
+            }
+            TileSpriteInfo::SingleSprite(texture) => {
+                draw_texture_ex(
+                    &texture,
+                    viewport_x,
+                    viewport_y,
+                    WHITE,
+                    DrawTextureParams {
+                        dest_size: Some(Vec2::new(camera_cell_size_x, camera_cell_size_y)),
+                        ..Default::default()
+                    },
+                );
+            }
+        };
+
+        let item_count = tile.item_count();
+        if item_count > 0 {
+            let mut buffer = [0; 20];
+            draw_text_ex(
+                count_text(item_count, &mut buffer),
+                viewport_x + scaled_cell_width / 2.0,
+                viewport_y + scaled_cell_height / 2.0,
+                Default::default(),
+            );
+        }
+        // if !tile.is_visited() {
+        //     draw_rectangle(
+        //         viewport_x,
+        //         viewport_y,
+        //         self.cell_size.width() as f32 / camera.zoom_scale,
+        //         self.cell_size.height() as f32 / camera.zoom_scale,
+        //         Color {
+        //             r: 0.0,
+        //             g: 0.0,
+        //             b: 0.0,
+        //             a: 0.8,
+        //         },
+        //     );
+        // }
+
+        match (tile.is_visible(), tile.is_visited()) {
+            (false, true) => {
+                draw_rectangle(
+                    viewport_x,
+                    viewport_y,
+                    camera_cell_size_x,
+                    camera_cell_size_y,
+                    Color {
+                        r: 0.0,
+                        g: 0.0,
+                        b: 0.0,
+                        a: 0.3,
+                    },
+                );
+            }
+            (false, false) => {
+                draw_rectangle(
+                    viewport_x,
+                    viewport_y,
+                    camera_cell_size_x,
+                    camera_cell_size_y,
+                    Color {
+                        r: 0.0,
+                        g: 0.0,
+                        b: 0.0,
+                        a: 0.6,
+                    },
+                );
+            }
+            (_, _) => {}
+        }
+
+        // draw_rectangle(
+        //     viewport_x,
+        //     viewport_y,
+        //     self.cell_size.width() as f32 * camera.zoom_scale,
+        //     self.cell_size.height() as f32 * camera.zoom_scale,
+        //     Color {
+        //         r: 1.0,
+        //         g: 0.,
+        //         b: 0.,
+        //         a: 0.6,
+        //     },
+        // );
+    }
+}
+
+/// Writes `count` in `buffer`, to draw it every frame without allocating.
+fn count_text(mut count: usize, buffer: &mut [u8; 20]) -> &str {
+    let mut start = buffer.len();
+    loop {
+        start -= 1;
+        buffer[start] = b'0' + (count % 10) as u8;
+        count /= 10;
+        if count == 0 {
+            break;
+        }
+    }
+    std::str::from_utf8(&buffer[start..]).unwrap_or_default()
 }
@@ -56,6 +56,50 @@ pub struct SpriteSheetConfig {
     pub defaults: HashMap<String, AddSpriteOptions>,
 }
 
+impl AddSpriteOptions {
+    /// The rect of the sprite at `sprite_cell` in a sheet of sprites of `size`, separated by
+    /// `gap`.
+    pub fn sprite_rect(&self, sprite_cell: (u32, u32)) -> Rect {
+        Rect::new(
+            (sprite_cell.0 * (self.size.0 + self.gap.0)) as f32,
+            (sprite_cell.1 * (self.size.1 + self.gap.1)) as f32,
+            self.size.0 as f32,
+            self.size.1 as f32,
+        )
+    }
+}
+
+impl SpriteSheetConfig {
+    pub fn from_file(config_path: &str) -> Self {
+        let config_content =
+            &std::fs::read_to_string(config_path).expect("Failed to read config file");
+
+        serde_json::from_str(config_content)
+            .unwrap_or_else(|_| panic!("Failed to parse {}", config_path))
+    }
+
+    /// The path of the sheet and the rect of every sprite, without loading the sheets, e.g.
+    /// to draw them without a window.
+    pub fn sprite_rects(&self) -> HashMap<String, (String, Rect)> {
+        let mut rects = HashMap::new();
+        for (sheet, sprites) in self.sprites.iter() {
+            let defaults = self.defaults.get(sheet).cloned().unwrap_or_default();
+            for sprite in sprites {
+                let options = if sprite.options.size == (0, 0) {
+                    &defaults
+                } else {
+                    &sprite.options
+                };
+                rects.insert(
+                    sprite.name.clone(),
+                    (sheet.clone(), options.sprite_rect(sprite.pos)),
+                );
+            }
+        }
+        rects
+    }
+}
+
 impl SpriteContainer {
     /// Creates a new sprite container from a spritesheet.
     pub async fn from_spritesheet(spritesheet_path: &str) -> Self {
@@ -75,11 +119,7 @@ impl SpriteContainer {
     }
 
     pub async fn from_config(config_path: &str) -> Self {
-        let config_content =
-            &std::fs::read_to_string(config_path).expect("Failed to read config file");
-
-        let config: SpriteSheetConfig = serde_json::from_str(config_content)
-            .unwrap_or_else(|_| panic!("Failed to parse {}", config_path));
+        let config = SpriteSheetConfig::from_file(config_path);
 
         let mut sprite_container = Self::default();
 
@@ -111,15 +151,9 @@ impl SpriteContainer {
         sprite_cell: (u32, u32),
         add_sprite_options: Option<AddSpriteOptions>,
     ) {
-        let mut pos = Rect::default();
-        if let Some(add_sprite_optione) = add_sprite_options {
-            pos.x = sprite_cell.0 as f32
-                * (add_sprite_optione.size.0 + add_sprite_optione.gap.0) as f32;
-            pos.y = sprite_cell.1 as f32
-                * (add_sprite_optione.size.1 + add_sprite_optione.gap.1) as f32;
-            pos.w = add_sprite_optione.size.0 as f32;
-            pos.h = add_sprite_optione.size.1 as f32;
-        }
+        let pos = add_sprite_options
+            .map(|options| options.sprite_rect(sprite_cell))
+            .unwrap_or_default();
 
         self.spritesheets.insert(
             name.to_owned(),
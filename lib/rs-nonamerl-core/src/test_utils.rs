@@ -0,0 +1,102 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    prelude::{
+        FovOccluder, GameMap, ItemContainer, LatticeGrid2D, Openable, Plane, Tile,
+        VisibilityOcclusion, Visible, Visited, Walkable,
+    },
+    IntVector2,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TestTile {
+    Floor,
+    Wall,
+    Smoke,
+    Door { open: bool },
+}
+
+impl TestTile {
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            '.' => Some(TestTile::Floor),
+            '#' => Some(TestTile::Wall),
+            '~' => Some(TestTile::Smoke),
+            '+' => Some(TestTile::Door { open: false }),
+            '/' => Some(TestTile::Door { open: true }),
+            _ => None,
+        }
+    }
+}
+
+impl Tile for TestTile {
+    fn movement_cost(&self) -> f32 {
+        match self {
+            TestTile::Smoke => 5.,
+            _ => 1.,
+        }
+    }
+
+    fn glyph(&self) -> char {
+        match self {
+            TestTile::Floor => '.',
+            TestTile::Wall => '#',
+            TestTile::Smoke => '~',
+            TestTile::Door { open: false } => '+',
+            TestTile::Door { open: true } => '/',
+        }
+    }
+}
+impl Visible for TestTile {}
+impl Visited for TestTile {}
+impl ItemContainer for TestTile {}
+
+impl Openable for TestTile {
+    fn is_open(&self) -> Option<bool> {
+        match self {
+            TestTile::Door { open } => Some(*open),
+            _ => None,
+        }
+    }
+
+    fn set_open(&mut self, open: bool) {
+        if let TestTile::Door { open: door } = self {
+            *door = open;
+        }
+    }
+}
+
+impl FovOccluder for TestTile {
+    fn block_visibility(&self) -> VisibilityOcclusion {
+        match self {
+            TestTile::Wall | TestTile::Door { open: false } => Self::BLOCKED,
+            TestTile::Floor | TestTile::Door { open: true } => Self::VISIBLE,
+            TestTile::Smoke => VisibilityOcclusion::new(0.5).unwrap(),
+        }
+    }
+}
+
+impl Walkable for TestTile {
+    fn is_walkable(&self) -> bool {
+        !matches!(self, TestTile::Wall | TestTile::Door { open: false })
+    }
+}
+
+/// Builds a grid from rows of characters: `.` is a floor, `#` is a wall, `~` is smoke, `+` is
+/// a closed door, `/` an open one and any other character leaves the cell empty. The first character of the first row is at (0, 0).
+pub fn grid_from_rows(rows: &[&str]) -> LatticeGrid2D<TestTile> {
+    let mut grid = LatticeGrid2D::new();
+    for (y, row) in rows.iter().enumerate() {
+        for (x, c) in row.chars().enumerate() {
+            if let Some(tile) = TestTile::from_char(c) {
+                grid.put(IntVector2::new(x as i32, y as i32), tile);
+            }
+        }
+    }
+    grid
+}
+
+/// Same as [`grid_from_rows`], but returns a `GameMap`.
+pub fn map_from_rows(rows: &[&str]) -> GameMap<TestTile> {
+    GameMap::from_ascii(&rows.join("\n"), IntVector2::ZERO, TestTile::from_char)
+}
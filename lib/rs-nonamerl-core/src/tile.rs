@@ -2,6 +2,7 @@ use std::fmt::Debug;
 
 use bevy_ecs::prelude::Entity;
 use macroquad::{prelude::Color, texture::Texture2D};
+use serde::{de::DeserializeOwned, Serialize};
 
 #[derive(Debug, Clone)]
 pub enum TileSpriteInfo {
@@ -18,13 +19,31 @@ impl Default for TileSpriteInfo {
 }
 
 pub trait Tile:
-    'static + Debug + Clone + Visible + Visited + FovOccluder + Walkable + ItemContainer
+    'static + Debug + Clone + Visible + Visited + FovOccluder + Walkable + ItemContainer + Openable
 {
     fn sprite_info(&self) -> TileSpriteInfo {
         TileSpriteInfo::None
     }
+
+    /// The cost of entering the tile, used by pathfinding. Only meaningful for walkable tiles.
+    fn movement_cost(&self) -> f32 {
+        1.
+    }
+
+    /// The character standing for the tile in ASCII maps, see
+    /// [`GameMap::to_ascii`](crate::prelude::GameMap::to_ascii).
+    fn glyph(&self) -> char {
+        '?'
+    }
 }
 
+/// Tiles that can be written to and read from save files, see
+/// [`MapSave`](crate::prelude::MapSave). Every tile type implementing serde's `Serialize` and
+/// `Deserialize` opts in.
+pub trait SerializableTile: Tile + Serialize + DeserializeOwned {}
+
+impl<T: Tile + Serialize + DeserializeOwned> SerializableTile for T {}
+
 pub trait Visible {
     fn is_visible(&self) -> bool {
         true
@@ -45,10 +64,25 @@ pub trait Walkable {
     }
 }
 
+/// Tiles that can be opened and closed, like doors. Opening a tile usually changes what its
+/// `Walkable` and `FovOccluder` implementations report.
+pub trait Openable {
+    /// Whether the tile is open, `None` if it can't be opened.
+    fn is_open(&self) -> Option<bool> {
+        None
+    }
+    fn set_open(&mut self, _open: bool) {}
+}
+
 pub trait ItemContainer {
     fn items(&self) -> Option<Vec<Entity>> {
         None
     }
+    /// The number of items on the tile. Tiles holding items should override it so that
+    /// drawing the map doesn't clone them.
+    fn item_count(&self) -> usize {
+        self.items().map_or(0, |items| items.len())
+    }
     fn add_item(&mut self, _item: Entity) {}
     fn remove_item(&mut self, _item: Entity) {}
 }
@@ -62,7 +96,7 @@ pub trait FovOccluder {
 }
 
 #[repr(transparent)]
-#[derive(PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct VisibilityOcclusion(f32);
 
 impl VisibilityOcclusion {
@@ -77,6 +111,16 @@ impl VisibilityOcclusion {
     pub unsafe fn new_unchecked(v: f32) -> Self {
         Self(v)
     }
+
+    /// The fraction of light that goes through the tile: 1 is fully transparent, 0 is opaque.
+    pub fn transmittance(&self) -> f32 {
+        self.0
+    }
+
+    /// The fraction of light stopped by the tile: `1 - transmittance`.
+    pub fn opacity(&self) -> f32 {
+        1. - self.0
+    }
 }
 
 impl From<VisibilityOcclusion> for f32 {